@@ -0,0 +1,137 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub struct Timings {
+    pub runs: Vec<Duration>,
+}
+
+impl Timings {
+    pub fn min(&self) -> Duration {
+        self.runs.iter().copied().min().unwrap_or_default()
+    }
+
+    pub fn max(&self) -> Duration {
+        self.runs.iter().copied().max().unwrap_or_default()
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.runs.is_empty() {
+            return Duration::ZERO;
+        }
+        self.runs.iter().sum::<Duration>() / self.runs.len() as u32
+    }
+}
+
+/// Runs `f` `n` times (at least once), returning the last result and every run's elapsed time.
+pub fn repeat<T>(n: usize, mut f: impl FnMut() -> Result<T>) -> Result<(T, Timings)> {
+    let n = n.max(1);
+    let mut runs = Vec::with_capacity(n);
+    let mut last = None;
+
+    for _ in 0..n {
+        let start = Instant::now();
+        let out = f()?;
+        runs.push(start.elapsed());
+        last = Some(out);
+    }
+
+    Ok((last.expect("at least one run"), Timings { runs }))
+}
+
+/// Reads `path` once, discarding the data, so the timed runs start with it in the OS page
+/// cache. Returns the number of bytes read.
+pub fn warm_cache(path: &Path) -> Result<u64> {
+    let mut file = File::open(path).with_context(|| format!("Cannot open '{}'", path.display()))?;
+    let mut buf = vec![0; 1 << 20];
+    let mut total = 0;
+    loop {
+        match file.read(&mut buf)? {
+            0 => return Ok(total),
+            n => total += n as u64,
+        }
+    }
+}
+
+/// Peak resident set size of this process in bytes, as the kernel tracks it (`VmHWM`), so
+/// nothing is sampled while the run is in progress. `None` where there is no `/proc`.
+pub fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find_map(|l| l.strip_prefix("VmHWM:"))?;
+    let kib: u64 = line.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// The `--memory-report` line for stderr.
+pub fn memory_report() -> String {
+    match peak_rss() {
+        Some(bytes) => format!("Peak memory (RSS): {:.1} MB", bytes as f64 / 1_048_576.0),
+        None => "Peak memory (RSS): unavailable on this platform".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::{process, ProcessOptions};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_repeat_reports_each_run() {
+        let mut f = NamedTempFile::new().expect("tmp file");
+        write!(f, "Timestamp,SensorID,Value\n2024-01-01T00:00:00,S1,10.0\n").expect("write");
+
+        let (stats, timings) =
+            repeat(3, || process(f.path(), &ProcessOptions::default())).expect("repeat");
+
+        assert_eq!(stats.total_rows, 1);
+        assert_eq!(timings.runs.len(), 3);
+        assert!(timings.min() <= timings.mean() && timings.mean() <= timings.max());
+    }
+
+    #[test]
+    fn test_warm_cache_leaves_results_unchanged() {
+        let mut f = NamedTempFile::new().expect("tmp file");
+        write!(
+            f,
+            "Timestamp,SensorID,Value\n2024-01-01T00:00:00,S1,10.0\n2024-01-01T00:00:01,S2,30.0\n"
+        )
+        .expect("write");
+        let opts = ProcessOptions {
+            verbose: true,
+            ..Default::default()
+        };
+
+        let cold = process(f.path(), &opts).expect("process");
+        let bytes = warm_cache(f.path()).expect("warm");
+        let warm = process(f.path(), &opts).expect("process");
+
+        assert_eq!(bytes, std::fs::metadata(f.path()).unwrap().len());
+        assert_eq!(format!("{cold:?}"), format!("{warm:?}"));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_memory_report_after_retaining_values() {
+        let mut f = NamedTempFile::new().expect("tmp file");
+        writeln!(f, "Timestamp,SensorID,Value").expect("write");
+        for i in 0..10_000 {
+            writeln!(f, "2024-01-01T00:00:00,S{},{i}", i % 10).expect("write");
+        }
+        let opts = ProcessOptions {
+            percentiles: vec![50.0],
+            ..Default::default()
+        };
+        process(f.path(), &opts).expect("process");
+
+        let peak = peak_rss().expect("VmHWM on Linux");
+        assert!(peak > 0);
+        let report = memory_report();
+        assert!(report.starts_with("Peak memory (RSS): ") && report.ends_with(" MB"));
+        assert!(!report.contains(" 0.0 MB"), "{report}");
+    }
+}
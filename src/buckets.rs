@@ -0,0 +1,301 @@
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+
+use crate::processor::Record;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum BucketScope {
+    Sensor,
+    Global,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueBuckets {
+    /// `None` for the fleet-wide tally.
+    pub sensor_id: Option<String>,
+    /// The most frequent values (ties by value), sorted by value.
+    pub counts: Vec<(f64, usize)>,
+    /// Readings whose value did not make the `max_values` cut.
+    pub other: usize,
+    pub distinct: usize,
+}
+
+/// Tallies each distinct value, per sensor or across all records, keeping the
+/// `max_values` most frequent per group. `-0.0` and `0.0` count as the same value.
+pub fn tally<'a>(
+    records: impl Iterator<Item = &'a Record>,
+    scope: BucketScope,
+    max_values: usize,
+) -> Vec<ValueBuckets> {
+    let mut groups: BTreeMap<Option<&str>, HashMap<u64, usize>> = BTreeMap::new();
+    for r in records {
+        let key = match scope {
+            BucketScope::Sensor => Some(r.sensor_id.as_str()),
+            BucketScope::Global => None,
+        };
+        let value = if r.value == 0.0 { 0.0 } else { r.value };
+        *groups
+            .entry(key)
+            .or_default()
+            .entry(value.to_bits())
+            .or_default() += 1;
+    }
+
+    groups
+        .into_iter()
+        .map(|(sensor_id, tally)| {
+            let distinct = tally.len();
+            let mut counts: Vec<(f64, usize)> = tally
+                .into_iter()
+                .map(|(bits, n)| (f64::from_bits(bits), n))
+                .collect();
+            counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.total_cmp(&b.0)));
+            let other = counts.iter().skip(max_values).map(|(_, n)| n).sum();
+            counts.truncate(max_values);
+            counts.sort_by(|a, b| a.0.total_cmp(&b.0));
+            ValueBuckets {
+                sensor_id: sensor_id.map(str::to_string),
+                counts,
+                other,
+                distinct,
+            }
+        })
+        .collect()
+}
+
+/// Bins beyond this many need `--force`; a width that small for the data is rarely meant.
+pub const MAX_VALUE_BINS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueBin {
+    /// The bin is [start, start + width).
+    pub start: f64,
+    pub count: usize,
+    /// `None` for an empty bin.
+    pub mean: Option<f64>,
+}
+
+/// Number of bins `value_bins` lays out between `min` and `max`.
+pub fn value_bin_count(min: f64, max: f64, width: f64) -> usize {
+    ((max / width).floor() - (min / width).floor()) as usize + 1
+}
+
+/// Buckets values into bins of `width` aligned to multiples of it, from the bin holding
+/// `min` to the one holding `max`, and averages each bin. Empty bins are kept so the bins
+/// form a regular grid.
+pub fn value_bins(
+    values: impl Iterator<Item = f64>,
+    min: f64,
+    max: f64,
+    width: f64,
+) -> Vec<ValueBin> {
+    let first = (min / width).floor();
+    let mut sums = vec![(0.0, 0); value_bin_count(min, max, width)];
+    let last = sums.len() - 1;
+    for v in values {
+        let bin = (((v / width).floor() - first) as usize).min(last);
+        sums[bin].0 += v;
+        sums[bin].1 += 1;
+    }
+    sums.into_iter()
+        .enumerate()
+        .map(|(i, (sum, count))| ValueBin {
+            start: (first + i as f64) * width,
+            count,
+            mean: (count > 0).then(|| sum / count as f64),
+        })
+        .collect()
+}
+
+/// Thresholds beyond this many make the `--threshold-sweep` table unreadable.
+pub const MAX_SWEEP_THRESHOLDS: usize = 1_000;
+
+/// `--threshold-sweep START:END:STEP`: START, START + STEP, ... up to and including END.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThresholdSweep(pub Vec<f64>);
+
+impl FromStr for ThresholdSweep {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let [start, end, step] = parts[..] else {
+            bail!("expected START:END:STEP, e.g. 0:100:10");
+        };
+        let number = |part: &str| -> Result<f64> {
+            part.trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite())
+                .with_context(|| format!("'{part}' is not a number"))
+        };
+        let (first, last, step_value) = (number(start)?, number(end)?, number(step)?);
+        if step_value <= 0.0 {
+            bail!("STEP must be positive");
+        }
+        if last < first {
+            bail!("END must not be below START");
+        }
+        let count = ((last - first) / step_value + 1e-9).floor() + 1.0;
+        if count > MAX_SWEEP_THRESHOLDS as f64 {
+            bail!("{count} thresholds (limit {MAX_SWEEP_THRESHOLDS}); use a larger STEP");
+        }
+
+        // Snap START + i * STEP to the decimals written, so 0:1:0.1 yields 0.3 rather
+        // than 0.30000000000000004 and matches `--filter-threshold 0.3` exactly.
+        let scale = (!s.contains(['e', 'E'])).then(|| {
+            let decimals = parts
+                .iter()
+                .map(|p| {
+                    p.trim()
+                        .split_once('.')
+                        .map_or(0, |(_, fraction)| fraction.len())
+                })
+                .max()
+                .unwrap_or(0);
+            10f64.powi(decimals.min(15) as i32)
+        });
+        Ok(Self(
+            (0..count as usize)
+                .map(|i| {
+                    let t = first + i as f64 * step_value;
+                    scale.map_or(t, |scale| (t * scale).round() / scale)
+                })
+                .collect(),
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepRow {
+    pub threshold: f64,
+    /// Values strictly above `threshold`, as the filter counts them.
+    pub filtered_rows: usize,
+    pub average: Option<f64>,
+}
+
+/// Filtered-row counts and averages at each of the ascending `thresholds`, from a single
+/// pass over the values. Each value lands in the bucket between the two thresholds around
+/// it; a threshold's totals are then the running sums of the buckets above it.
+pub fn threshold_sweep(values: impl Iterator<Item = f64>, thresholds: &[f64]) -> Vec<SweepRow> {
+    // Bucket i holds the values above exactly the first i thresholds.
+    let mut buckets = vec![(0usize, 0.0); thresholds.len() + 1];
+    for v in values {
+        let above = thresholds.partition_point(|&t| t < v);
+        buckets[above].0 += 1;
+        buckets[above].1 += v;
+    }
+
+    let (mut count, mut sum) = (0, 0.0);
+    let mut rows: Vec<SweepRow> = thresholds
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(i, &threshold)| {
+            count += buckets[i + 1].0;
+            sum += buckets[i + 1].1;
+            SweepRow {
+                threshold,
+                filtered_rows: count,
+                average: (count > 0).then(|| sum / count as f64),
+            }
+        })
+        .collect();
+    rows.reverse();
+    rows
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategoryCounts {
+    /// `None` for the fleet-wide counts.
+    pub sensor_id: Option<String>,
+    /// Every category, most frequent first (ties by name).
+    pub counts: Vec<(String, usize)>,
+}
+
+/// Counts `--categorical` values across all rows, then per sensor. The fleet-wide
+/// counts come first, followed by the sensors sorted by ID.
+pub fn count_categories<'a>(rows: impl Iterator<Item = (&'a str, &'a str)>) -> Vec<CategoryCounts> {
+    let mut groups: BTreeMap<Option<&str>, HashMap<&str, usize>> = BTreeMap::new();
+    for (sensor_id, category) in rows {
+        *groups.entry(None).or_default().entry(category).or_default() += 1;
+        *groups
+            .entry(Some(sensor_id))
+            .or_default()
+            .entry(category)
+            .or_default() += 1;
+    }
+
+    groups
+        .into_iter()
+        .map(|(sensor_id, tally)| {
+            let mut counts: Vec<(String, usize)> = tally
+                .into_iter()
+                .map(|(category, n)| (category.to_string(), n))
+                .collect();
+            counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            CategoryCounts {
+                sensor_id: sensor_id.map(str::to_string),
+                counts,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(sensor_id: &str, value: f64) -> Record {
+        Record {
+            timestamp: String::new(),
+            sensor_id: sensor_id.to_string(),
+            value,
+            time: None,
+        }
+    }
+
+    #[test]
+    fn test_value_bins_count_and_average_each_bin() {
+        let values = [3.0, 7.0, 12.0, 18.0, 19.0, 41.0];
+        let bins = value_bins(values.iter().copied(), 3.0, 41.0, 10.0);
+
+        let starts: Vec<f64> = bins.iter().map(|b| b.start).collect();
+        assert_eq!(starts, [0.0, 10.0, 20.0, 30.0, 40.0]);
+        let counts: Vec<usize> = bins.iter().map(|b| b.count).collect();
+        assert_eq!(counts, [2, 3, 0, 0, 1]);
+        assert_eq!(bins[0].mean, Some(5.0));
+        assert_eq!(bins[1].mean, Some(49.0 / 3.0));
+        assert_eq!(bins[2].mean, None);
+        assert_eq!(bins[4].mean, Some(41.0));
+
+        // A value exactly on an edge starts the next bin.
+        let edge = value_bins([-5.0, 0.0, 5.0].into_iter(), -5.0, 5.0, 5.0);
+        let counts: Vec<usize> = edge.iter().map(|b| b.count).collect();
+        assert_eq!(counts, [1, 1, 1]);
+        assert_eq!(edge[0].start, -5.0);
+    }
+
+    #[test]
+    fn test_tally_discrete_states() {
+        let records: Vec<Record> = [0.0, 1.0, 2.0, 1.0, 1.0, 0.0, -0.0]
+            .iter()
+            .map(|&v| record("S1", v))
+            .chain([record("S2", 5.0)])
+            .collect();
+
+        let per_sensor = tally(records.iter(), BucketScope::Sensor, 10);
+        assert_eq!(per_sensor.len(), 2);
+        assert_eq!(per_sensor[0].sensor_id.as_deref(), Some("S1"));
+        assert_eq!(per_sensor[0].counts, [(0.0, 3), (1.0, 3), (2.0, 1)]);
+        assert_eq!(per_sensor[0].other, 0);
+
+        let global = tally(records.iter(), BucketScope::Global, 2);
+        assert_eq!(global.len(), 1);
+        assert_eq!(global[0].sensor_id, None);
+        assert_eq!(global[0].counts, [(0.0, 3), (1.0, 3)]);
+        assert_eq!((global[0].other, global[0].distinct), (2, 4));
+    }
+}
@@ -0,0 +1,238 @@
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+
+use crate::processor::Record;
+use crate::timestamp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MergePolicy {
+    Average,
+    First,
+    Last,
+    Max,
+    Min,
+}
+
+impl MergePolicy {
+    pub fn reduce(self, values: &[f64]) -> f64 {
+        match self {
+            MergePolicy::Average => values.iter().sum::<f64>() / values.len() as f64,
+            MergePolicy::First => values[0],
+            MergePolicy::Last => values[values.len() - 1],
+            MergePolicy::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            MergePolicy::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+        }
+    }
+}
+
+/// Collapses records sharing a (parsed timestamp, sensor) pair into one, keeping the
+/// position of the group's first row. Returns the merged records and the number of
+/// groups that had more than one row.
+pub fn merge_duplicates(records: Vec<Record>, policy: MergePolicy) -> (Vec<Record>, usize) {
+    let mut index: HashMap<(i64, String), usize> = HashMap::with_capacity(records.len());
+    let mut groups: Vec<(Record, Vec<f64>)> = Vec::with_capacity(records.len());
+
+    for r in records {
+        let time = r.time.expect("timestamps are parsed for duplicate merging");
+        match index.get(&(time, r.sensor_id.clone())) {
+            Some(&i) => groups[i].1.push(r.value),
+            None => {
+                index.insert((time, r.sensor_id.clone()), groups.len());
+                let value = r.value;
+                groups.push((r, vec![value]));
+            }
+        }
+    }
+
+    let mut merged_groups = 0;
+    let records = groups
+        .into_iter()
+        .map(|(mut r, values)| {
+            if values.len() > 1 {
+                merged_groups += 1;
+                r.value = policy.reduce(&values);
+            }
+            r
+        })
+        .collect();
+
+    (records, merged_groups)
+}
+
+/// Collapses each sensor's near-duplicate readings: walking a sensor's readings in
+/// timestamp order, every reading less than `window` microseconds after the first reading
+/// of the current cluster joins that cluster. A cluster never spans more than the window,
+/// so a steady fast stream is thinned rather than merged into one reading. Each cluster
+/// keeps its first reading's timestamp and position, with `policy` applied to its values
+/// in time order. Returns the kept records and the number of readings collapsed away.
+pub fn collapse_window(
+    records: Vec<Record>,
+    window: i64,
+    policy: MergePolicy,
+) -> (Vec<Record>, usize) {
+    let time = |i: usize| {
+        records[i]
+            .time
+            .expect("timestamps are parsed for --dedup-window")
+    };
+    let mut order: Vec<usize> = (0..records.len()).collect();
+    order.sort_by(|&a, &b| {
+        records[a]
+            .sensor_id
+            .cmp(&records[b].sensor_id)
+            .then(time(a).cmp(&time(b)))
+    });
+
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+    for i in order {
+        match clusters.last_mut() {
+            Some(cluster)
+                if records[cluster[0]].sensor_id == records[i].sensor_id
+                    && time(i) - time(cluster[0]) < window =>
+            {
+                cluster.push(i)
+            }
+            _ => clusters.push(vec![i]),
+        }
+    }
+
+    let mut records = records;
+    let mut removed = vec![false; records.len()];
+    let mut collapsed = 0;
+    for cluster in clusters.iter().filter(|c| c.len() > 1) {
+        let values: Vec<f64> = cluster.iter().map(|&i| records[i].value).collect();
+        records[cluster[0]].value = policy.reduce(&values);
+        for &i in &cluster[1..] {
+            removed[i] = true;
+        }
+        collapsed += cluster.len() - 1;
+    }
+
+    let kept = records
+        .into_iter()
+        .zip(removed)
+        .filter_map(|(r, removed)| (!removed).then_some(r))
+        .collect();
+    (kept, collapsed)
+}
+
+/// How time-based features treat several readings from one sensor at the same timestamp.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SameTimestamp {
+    First,
+    Last,
+    #[default]
+    Mean,
+    /// Fail on the first collision.
+    Error,
+}
+
+/// Resolves same-timestamp readings per sensor into one. Returns the resolved records and
+/// the number of collisions (timestamp, sensor pairs with more than one reading).
+pub fn resolve_same_timestamp(
+    records: Vec<Record>,
+    policy: SameTimestamp,
+) -> Result<(Vec<Record>, usize)> {
+    let policy = match policy {
+        SameTimestamp::First => MergePolicy::First,
+        SameTimestamp::Last => MergePolicy::Last,
+        SameTimestamp::Mean => MergePolicy::Average,
+        SameTimestamp::Error => {
+            let mut seen = HashSet::with_capacity(records.len());
+            for r in &records {
+                let time = r
+                    .time
+                    .expect("timestamps are parsed for time-based features");
+                if !seen.insert((time, r.sensor_id.as_str())) {
+                    anyhow::bail!(
+                        "Sensor '{}' has more than one reading at {}; choose --same-timestamp first, last or mean",
+                        r.sensor_id,
+                        timestamp::format_iso8601(time)
+                    );
+                }
+            }
+            return Ok((records, 0));
+        }
+    };
+    Ok(merge_duplicates(records, policy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(ts: &str, sensor: &str, value: f64) -> Record {
+        Record {
+            timestamp: ts.to_string(),
+            sensor_id: sensor.to_string(),
+            value,
+            time: Some(timestamp::parse_iso8601(ts).unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_duplicates_collapse_to_average() {
+        let records = vec![
+            record("2024-01-01T00:00:00", "S1", 10.0),
+            record("2024-01-01T00:00:00", "S2", 99.0),
+            record("2024-01-01T00:00:00", "S1", 20.0),
+            record("2024-01-01T00:00:01", "S1", 30.0),
+        ];
+        let (merged, groups) = merge_duplicates(records, MergePolicy::Average);
+
+        assert_eq!(groups, 1);
+        let values: Vec<f64> = merged.iter().map(|r| r.value).collect();
+        assert_eq!(values, [15.0, 99.0, 30.0]);
+    }
+
+    #[test]
+    fn test_collapse_window_merges_only_close_readings() {
+        let records = vec![
+            record("2024-01-01T00:00:00.000", "S1", 10.0),
+            record("2024-01-01T00:00:00.000", "S2", 50.0),
+            record("2024-01-01T00:00:00.010", "S1", 20.0),
+            record("2024-01-01T00:00:01.010", "S1", 30.0),
+            record("2024-01-01T00:00:01.000", "S2", 60.0),
+        ];
+        let window = timestamp::parse_duration("100ms").unwrap();
+
+        let (kept, collapsed) = collapse_window(records.clone(), window, MergePolicy::First);
+        assert_eq!(collapsed, 1);
+        let values: Vec<f64> = kept.iter().map(|r| r.value).collect();
+        assert_eq!(values, [10.0, 50.0, 30.0, 60.0]);
+
+        let (kept, _) = collapse_window(records, window, MergePolicy::Average);
+        assert_eq!(kept[0].value, 15.0);
+        assert_eq!(kept[0].timestamp, "2024-01-01T00:00:00.000");
+    }
+
+    #[test]
+    fn test_policies() {
+        let values = [3.0, 1.0, 2.0];
+        assert_eq!(MergePolicy::First.reduce(&values), 3.0);
+        assert_eq!(MergePolicy::Last.reduce(&values), 2.0);
+        assert_eq!(MergePolicy::Max.reduce(&values), 3.0);
+        assert_eq!(MergePolicy::Min.reduce(&values), 1.0);
+        assert_eq!(MergePolicy::Average.reduce(&values), 2.0);
+    }
+
+    #[test]
+    fn test_same_timestamp_error_names_the_collision() {
+        let records = vec![
+            record("2024-01-01T00:00:00", "S1", 10.0),
+            record("2024-01-01T00:00:00", "S2", 20.0),
+            record("2024-01-01T00:00:00", "S1", 30.0),
+        ];
+        let err = resolve_same_timestamp(records.clone(), SameTimestamp::Error)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("'S1'") && err.contains("2024-01-01T00:00:00"),
+            "{err}"
+        );
+
+        let (resolved, collisions) = resolve_same_timestamp(records, SameTimestamp::Last).unwrap();
+        assert_eq!(collisions, 1);
+        assert_eq!(resolved[0].value, 30.0);
+    }
+}
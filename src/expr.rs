@@ -0,0 +1,273 @@
+//! Arithmetic over named CSV columns for `--value-expr`.
+//!
+//! Supports numbers, column names (`systolic`, or `"Heart Rate"` for names that are not
+//! identifiers), `+ - * /`, unary minus and parentheses, with the usual precedence.
+
+use anyhow::{bail, Context, Result};
+use csv::StringRecord;
+use std::str::FromStr;
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    source: String,
+    node: Node,
+    /// Column names referenced by `Node::Column`, in first-use order.
+    columns: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Number(f64),
+    Column(usize),
+    Neg(Box<Node>),
+    Binary(Op, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// An expression resolved against one file's header row.
+pub struct BoundExpr<'a> {
+    expr: &'a Expr,
+    /// Field index of each of `expr.columns`.
+    fields: Vec<usize>,
+}
+
+impl Expr {
+    pub fn new(source: &str) -> Result<Self> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            pos: 0,
+            columns: Vec::new(),
+        };
+        let node = parser.sum()?;
+        parser.skip_spaces();
+        if let Some(c) = parser.peek() {
+            bail!("unexpected '{c}' in expression '{source}'");
+        }
+        Ok(Self {
+            source: source.to_string(),
+            node,
+            columns: parser.columns,
+        })
+    }
+
+    /// The column names the expression reads, in order of first use.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// Resolves column names to field indices; fails if the header lacks one.
+    pub fn bind(&self, headers: &StringRecord) -> Result<BoundExpr<'_>> {
+        let fields = self
+            .columns
+            .iter()
+            .map(|name| {
+                headers.iter().position(|h| h == name).with_context(|| {
+                    format!("--value-expr references column '{name}', which is not in the header")
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(BoundExpr { expr: self, fields })
+    }
+}
+
+impl BoundExpr<'_> {
+    pub fn eval(&self, row: &StringRecord) -> Result<f64> {
+        self.eval_node(&self.expr.node, row)
+    }
+
+    fn eval_node(&self, node: &Node, row: &StringRecord) -> Result<f64> {
+        Ok(match node {
+            Node::Number(n) => *n,
+            Node::Column(i) => {
+                let name = &self.expr.columns[*i];
+                let field = row.get(self.fields[*i]).unwrap_or("");
+                field
+                    .parse()
+                    .with_context(|| format!("column '{name}': '{field}' is not a number"))?
+            }
+            Node::Neg(inner) => -self.eval_node(inner, row)?,
+            Node::Binary(op, a, b) => {
+                let (a, b) = (self.eval_node(a, row)?, self.eval_node(b, row)?);
+                match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    Op::Div => a / b,
+                }
+            }
+        })
+    }
+}
+
+impl FromStr for Expr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Expr::new(s)
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    columns: Vec<String>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn sum(&mut self) -> Result<Node> {
+        let mut node = self.product()?;
+        loop {
+            self.skip_spaces();
+            let op = match self.peek() {
+                Some('+') => Op::Add,
+                Some('-') => Op::Sub,
+                _ => return Ok(node),
+            };
+            self.pos += 1;
+            node = Node::Binary(op, Box::new(node), Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<Node> {
+        let mut node = self.unary()?;
+        loop {
+            self.skip_spaces();
+            let op = match self.peek() {
+                Some('*') => Op::Mul,
+                Some('/') => Op::Div,
+                _ => return Ok(node),
+            };
+            self.pos += 1;
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(Node::Neg(Box::new(self.unary()?)))
+            }
+            Some('(') => {
+                self.pos += 1;
+                let node = self.sum()?;
+                self.skip_spaces();
+                if self.peek() != Some(')') {
+                    bail!("missing ')' in expression");
+                }
+                self.pos += 1;
+                Ok(node)
+            }
+            Some('"') => {
+                self.pos += 1;
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c != '"') {
+                    self.pos += 1;
+                }
+                if self.peek().is_none() {
+                    bail!("unterminated quoted column name in expression");
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                self.pos += 1;
+                Ok(self.column(name))
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || c == '.')
+                    || (matches!(self.peek(), Some('+' | '-'))
+                        && matches!(self.chars[self.pos - 1], 'e' | 'E'))
+                {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                let n = text
+                    .parse()
+                    .with_context(|| format!("invalid number '{text}' in expression"))?;
+                Ok(Node::Number(n))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                    self.pos += 1;
+                }
+                let name = self.chars[start..self.pos].iter().collect();
+                Ok(self.column(name))
+            }
+            Some(c) => bail!("unexpected '{c}' in expression"),
+            None => bail!("expression ends unexpectedly"),
+        }
+    }
+
+    fn column(&mut self, name: String) -> Node {
+        let index = match self.columns.iter().position(|c| *c == name) {
+            Some(i) => i,
+            None => {
+                self.columns.push(name);
+                self.columns.len() - 1
+            }
+        };
+        Node::Column(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str, headers: &[&str], row: &[&str]) -> Result<f64> {
+        let expr = Expr::new(source)?;
+        let bound = expr.bind(&StringRecord::from(headers.to_vec()))?;
+        bound.eval(&StringRecord::from(row.to_vec()))
+    }
+
+    #[test]
+    fn test_precedence_and_columns() {
+        let headers = ["a", "Heart Rate", "b"];
+        let row = ["6", "70", "2"];
+        assert_eq!(eval("a - b * 2", &headers, &row).unwrap(), 2.0);
+        assert_eq!(eval("(a - b) * 2", &headers, &row).unwrap(), 8.0);
+        assert_eq!(eval("-a / b + 1.5e1", &headers, &row).unwrap(), 12.0);
+        assert_eq!(
+            eval("\"Heart Rate\" / a", &headers, &row).unwrap() * 6.0,
+            70.0
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Expr::new("a +").is_err());
+        assert!(Expr::new("(a - b").is_err());
+        assert!(Expr::new("a b").is_err());
+        assert!(eval("a - c", &["a", "b"], &["1", "2"]).is_err());
+        let err = eval("a - b", &["a", "b"], &["1", "x"])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("column 'b'"), "{err}");
+    }
+}
@@ -0,0 +1,151 @@
+//! `--fast-float`: decimal parsing for the value column without going through serde.
+//!
+//! Plain decimals whose digits form an integer up to 2^53 and whose power of ten is
+//! within ±22 are converted with a single multiply or divide of two exact doubles
+//! (Clinger's fast path), which rounds exactly as `str::parse` does. Anything else,
+//! including `inf`, `NaN` and malformed input, is left to `str::parse`.
+
+const POW10: [f64; 23] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15, 1e16,
+    1e17, 1e18, 1e19, 1e20, 1e21, 1e22,
+];
+
+/// Largest mantissa the fast path takes; every integer up to 2^53 is an exact double.
+const MAX_MANTISSA: u64 = 1 << 53;
+
+pub fn parse(s: &str) -> Option<f64> {
+    fast_path(s.as_bytes()).or_else(|| s.parse().ok())
+}
+
+fn fast_path(s: &[u8]) -> Option<f64> {
+    let (negative, s) = match s.first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+
+    let mut mantissa: u64 = 0;
+    let mut exponent: i64 = 0;
+    let mut digits = 0;
+    let mut seen_digit = false;
+    let mut seen_dot = false;
+    let mut i = 0;
+    while i < s.len() {
+        match s[i] {
+            c @ b'0'..=b'9' => {
+                seen_digit = true;
+                if mantissa > 0 || c != b'0' {
+                    digits += 1;
+                    if digits > 19 {
+                        return None;
+                    }
+                    mantissa = mantissa * 10 + u64::from(c - b'0');
+                }
+                if seen_dot {
+                    exponent -= 1;
+                }
+            }
+            b'.' if !seen_dot => seen_dot = true,
+            b'e' | b'E' => break,
+            _ => return None,
+        }
+        i += 1;
+    }
+    if !seen_digit {
+        return None;
+    }
+
+    if i < s.len() {
+        let (negative_exp, digits) = match s.get(i + 1) {
+            Some(b'-') => (true, &s[i + 2..]),
+            Some(b'+') => (false, &s[i + 2..]),
+            _ => (false, &s[i + 1..]),
+        };
+        if digits.is_empty() || digits.len() > 4 || !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        let e = digits
+            .iter()
+            .fold(0i64, |e, &d| e * 10 + i64::from(d - b'0'));
+        exponent += if negative_exp { -e } else { e };
+    }
+
+    if mantissa > MAX_MANTISSA {
+        return None;
+    }
+    let value = mantissa as f64;
+    let value = match exponent {
+        0..=22 => value * POW10[exponent as usize],
+        -22..=-1 => value / POW10[-exponent as usize],
+        _ if mantissa == 0 => 0.0,
+        _ => return None,
+    };
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_matches_std() {
+        let mut inputs: Vec<String> = [
+            "0",
+            "-0",
+            "+1",
+            "1.",
+            ".5",
+            "0.1",
+            "0.05",
+            "-273.15",
+            "98.6",
+            "1e3",
+            "1E-3",
+            "2.5e+10",
+            "123456789012345678",
+            "9007199254740993",
+            "0.30000000000000004",
+            "1e22",
+            "1e23",
+            "1e-22",
+            "1e-23",
+            "1.7976931348623157e308",
+            "5e-324",
+            "0e400",
+            "inf",
+            "-inf",
+            "NaN",
+            "",
+            ".",
+            "-",
+            "1e",
+            "1.2.3",
+            "12a",
+            "0x10",
+        ]
+        .map(str::to_string)
+        .to_vec();
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        for _ in 0..10_000 {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1);
+            let v = f64::from_bits(state >> 2) % 1e6;
+            inputs.push(format!("{v}"));
+            inputs.push(format!("{v:.3}"));
+            inputs.push(format!("{v:e}"));
+        }
+
+        for s in &inputs {
+            let expected = s.parse::<f64>().ok();
+            let got = parse(s);
+            assert_eq!(
+                got.map(f64::to_bits),
+                expected.map(f64::to_bits),
+                "{s:?}: {got:?} vs {expected:?}"
+            );
+        }
+        // Most of those take the fast path.
+        assert!(fast_path(b"-273.15").is_some() && fast_path(b"1e23").is_none());
+    }
+}
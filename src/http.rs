@@ -0,0 +1,292 @@
+//! Minimal HTTP/1.1 GET client for `--url`: plain `http://` only, no TLS. Gzip bodies
+//! are decoded by [`crate::gzip`] as they stream into the CSV reader.
+
+use crate::gzip::GzDecoder;
+use anyhow::{Context, Result};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+const GZIP_EXTENSIONS: [&str; 2] = [".gz", ".gzip"];
+
+/// Zstandard has no decoder here; rejected up front with a clear error.
+const ZSTD_EXTENSIONS: [&str; 2] = [".zst", ".zstd"];
+
+/// Sends a GET request for `url` and returns the response body, gunzipped when it is
+/// sent with `Content-Encoding: gzip` or the path ends in `.gz`.
+pub fn get(url: &str) -> Result<Box<dyn Read + Send>> {
+    let (host, port, path) = parse_url(url)?;
+    let file = path.split('?').next().unwrap_or("");
+    if let Some(ext) = ZSTD_EXTENSIONS.iter().find(|ext| file.ends_with(*ext)) {
+        anyhow::bail!("'{url}' looks {ext}-compressed; decompress it first (only plain and gzip bodies are supported)");
+    }
+    let mut gzip = GZIP_EXTENSIONS.iter().any(|ext| file.ends_with(ext));
+
+    let host_header = if host.contains(':') {
+        format!("[{host}]")
+    } else {
+        host.clone()
+    };
+    let mut stream = TcpStream::connect((host.as_str(), port))
+        .with_context(|| format!("Cannot connect to {host}:{port}"))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    write!(
+        stream,
+        "GET {path} HTTP/1.1\r\nHost: {host_header}\r\nUser-Agent: rust-cli/{}\r\nAccept-Encoding: gzip, identity\r\nConnection: close\r\n\r\n",
+        env!("CARGO_PKG_VERSION")
+    )?;
+
+    let mut reader = BufReader::new(stream);
+    let mut status = String::new();
+    reader.read_line(&mut status)?;
+    let mut parts = status.trim_end().splitn(3, ' ');
+    let code = match (parts.next(), parts.next()) {
+        (Some(v), Some(code)) if v.starts_with("HTTP/1.") => code.to_string(),
+        _ => anyhow::bail!(
+            "Invalid HTTP response from '{url}': {:?}",
+            status.trim_end()
+        ),
+    };
+    let reason = parts.next().unwrap_or("").to_string();
+
+    let mut content_length = None;
+    let mut chunked = false;
+    let mut location = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => content_length = value.parse::<u64>().ok(),
+            "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+            "location" => location = Some(value.to_string()),
+            "content-encoding" => match value.to_ascii_lowercase().as_str() {
+                "identity" => {}
+                "gzip" | "x-gzip" => gzip = true,
+                _ => anyhow::bail!(
+                    "'{url}' was sent with Content-Encoding '{value}', which is not supported"
+                ),
+            },
+            _ => {}
+        }
+    }
+
+    if code != "200" {
+        let redirect = location.map_or(String::new(), |l| format!(" (redirects to {l})"));
+        anyhow::bail!("GET '{url}' returned HTTP {code} {reason}{redirect}");
+    }
+
+    let body: Box<dyn Read + Send> = match (chunked, content_length) {
+        (true, _) => Box::new(Chunked::new(reader)),
+        (false, Some(len)) => Box::new(reader.take(len)),
+        (false, None) => Box::new(reader),
+    };
+    Ok(if gzip {
+        Box::new(GzDecoder::new(BufReader::new(body)))
+    } else {
+        body
+    })
+}
+
+/// Splits `http://host[:port]/path` into its parts; an IPv6 host is written in brackets
+/// (`http://[::1]:8080/`) and returned without them. The `#fragment` is never sent.
+fn parse_url(url: &str) -> Result<(String, u16, String)> {
+    let rest = match url.split_once("://") {
+        Some(("http", rest)) => rest,
+        Some(("https", _)) => anyhow::bail!("HTTPS is not supported; use an http:// URL"),
+        _ => anyhow::bail!("'{url}' is not an http:// URL"),
+    };
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(i) if rest[i..].starts_with('?') => (&rest[..i], format!("/{}", &rest[i..])),
+        Some(i) => (&rest[..i], rest[i..].to_string()),
+        None => (rest, "/".to_string()),
+    };
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(bracketed) => match bracketed.split_once(']') {
+            Some((host, "")) => (host, None),
+            Some((host, port)) => match port.strip_prefix(':') {
+                Some(port) => (host, Some(port)),
+                None => anyhow::bail!("Invalid host in '{url}'"),
+            },
+            None => anyhow::bail!("Unclosed '[' in the host of '{url}'"),
+        },
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port
+            .parse()
+            .with_context(|| format!("Invalid port in '{url}'"))?,
+        None => 80,
+    };
+    if host.is_empty() {
+        anyhow::bail!("'{url}' has no host");
+    }
+    Ok((host.to_string(), port, path))
+}
+
+/// Decodes a `Transfer-Encoding: chunked` body.
+struct Chunked<R> {
+    inner: R,
+    remaining: u64,
+    done: bool,
+}
+
+impl<R: BufRead> Chunked<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            remaining: 0,
+            done: false,
+        }
+    }
+
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let mut line = String::new();
+        self.inner.read_line(&mut line)?;
+        let size = line.trim_end().split(';').next().unwrap_or("");
+        self.remaining = u64::from_str_radix(size.trim(), 16).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid chunk size in HTTP body",
+            )
+        })?;
+        if self.remaining == 0 {
+            self.done = true;
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for Chunked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 && !self.done {
+            self.next_chunk()?;
+        }
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        let max = buf.len().min(self.remaining as usize);
+        let n = self.inner.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= n as u64;
+        if self.remaining == 0 {
+            // Each chunk's data is followed by CRLF.
+            let mut crlf = String::new();
+            self.inner.read_line(&mut crlf)?;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::{self, ProcessOptions};
+    use std::net::TcpListener;
+
+    /// Serves `response` once on a local port and returns the URL to fetch.
+    fn serve_once(response: impl Into<Vec<u8>>) -> String {
+        let response = response.into();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+            stream.write_all(&response).unwrap();
+        });
+        format!("http://127.0.0.1:{port}/data.csv")
+    }
+
+    #[test]
+    fn test_processes_csv_served_over_http() {
+        let url = serve_once(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+             19\r\nTimestamp,SensorID,Value\n\r\n\
+             2b\r\n2024-01-01T00:00:00,S1,10\n2024-01-01T00:00:\r\n\
+             b\r\n01,S2,30.5\n\r\n0\r\n\r\n",
+        );
+        let body = get(&url).unwrap();
+        let stats = processor::process_reader(body, &url, &ProcessOptions::default()).unwrap();
+        assert_eq!(stats.total_rows, 2);
+        assert!((stats.average.unwrap() - 20.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_decodes_gzip_content_encoding() {
+        // `gzip` of the same two rows, which it encodes as one fixed-Huffman block.
+        let gz = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x0b, 0xc9, 0xcc, 0x4d,
+            0x2d, 0x2e, 0x49, 0xcc, 0x2d, 0xd0, 0x09, 0x4e, 0xcd, 0x2b, 0xce, 0x2f, 0xf2, 0x74,
+            0xd1, 0x09, 0x4b, 0xcc, 0x29, 0x4d, 0xe5, 0x32, 0x32, 0x30, 0x32, 0xd1, 0x35, 0x30,
+            0x04, 0xa2, 0x10, 0x03, 0x03, 0x2b, 0x30, 0xd2, 0x09, 0x36, 0xd4, 0x31, 0x34, 0xc0,
+            0x22, 0x63, 0xa8, 0x13, 0x6c, 0xa4, 0x63, 0x6c, 0xa0, 0x67, 0xca, 0x05, 0x00, 0x3c,
+            0x27, 0x7f, 0xa0, 0x4f, 0x00, 0x00, 0x00,
+        ];
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            gz.len()
+        )
+        .into_bytes();
+        response.extend(gz);
+        let url = serve_once(response);
+        let body = get(&url).unwrap();
+        let stats = processor::process_reader(body, &url, &ProcessOptions::default()).unwrap();
+        assert_eq!(stats.total_rows, 2);
+        assert!((stats.average.unwrap() - 20.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_non_200_is_an_error() {
+        let url = serve_once("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n");
+        let err = get(&url).err().unwrap().to_string();
+        assert!(err.contains("HTTP 404 Not Found"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_url() {
+        let parts = |url| parse_url(url).unwrap();
+        assert_eq!(
+            parts("http://[::1]:8080/x"),
+            ("::1".to_string(), 8080, "/x".to_string())
+        );
+        assert_eq!(
+            parts("http://[::1]/x"),
+            ("::1".to_string(), 80, "/x".to_string())
+        );
+        assert_eq!(
+            parts("http://example.com:81/a.csv?v=2#top"),
+            ("example.com".to_string(), 81, "/a.csv?v=2".to_string())
+        );
+        assert_eq!(
+            parts("http://example.com#top"),
+            ("example.com".to_string(), 80, "/".to_string())
+        );
+        assert!(parse_url("http://[::1/x").is_err());
+        assert!(parse_url("http://[::1]x/").is_err());
+        assert!(parse_url("http://host:port/").is_err());
+    }
+
+    #[test]
+    fn test_rejects_unsupported_urls() {
+        assert!(get("https://example.com/a.csv").is_err());
+        assert!(get("http://127.0.0.1:1/a.csv.zst")
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("compressed"));
+    }
+}
@@ -0,0 +1,278 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+
+/// Input path that reads from stdin.
+pub const STDIN: &str = "-";
+
+pub fn is_stdin(path: &Path) -> bool {
+    path == Path::new(STDIN)
+}
+
+/// Opens `path`, retrying up to `retries` times with exponential backoff while Windows
+/// reports that another process (typically the logger still writing it) holds the file.
+pub fn open_file(path: &Path, retries: u32) -> io::Result<File> {
+    open_with_retry(retries, RETRY_BASE_DELAY, is_sharing_violation, || {
+        File::open(path)
+    })
+}
+
+/// ERROR_SHARING_VIOLATION (32) and ERROR_LOCK_VIOLATION (33). The same numbers mean
+/// unrelated things on Unix, so this is always false there.
+pub fn is_sharing_violation(err: &io::Error) -> bool {
+    cfg!(windows) && matches!(err.raw_os_error(), Some(32 | 33))
+}
+
+fn open_with_retry<T>(
+    retries: u32,
+    base_delay: Duration,
+    is_retryable: impl Fn(&io::Error) -> bool,
+    mut open: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut attempt = 0;
+    loop {
+        match open() {
+            Err(e) if attempt < retries && is_retryable(&e) => {
+                std::thread::sleep(base_delay * 2u32.pow(attempt.min(6)));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Drops lines that are empty or whitespace-only before they reach the CSV parser,
+/// counting each one in the shared `skipped` counter. Lines inside a double-quoted
+/// field are part of its value and always pass through.
+pub struct SkipBlankLines<R> {
+    inner: R,
+    line: Vec<u8>,
+    pos: usize,
+    quoted: bool,
+    skipped: Arc<AtomicUsize>,
+}
+
+impl<R: BufRead> SkipBlankLines<R> {
+    pub fn new(inner: R, skipped: Arc<AtomicUsize>) -> Self {
+        Self {
+            inner,
+            line: Vec::new(),
+            pos: 0,
+            quoted: false,
+            skipped,
+        }
+    }
+}
+
+impl<R: BufRead> Read for SkipBlankLines<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.line.len() {
+            self.line.clear();
+            self.pos = 0;
+            if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(0);
+            }
+            if !self.quoted && self.line.iter().all(u8::is_ascii_whitespace) {
+                self.skipped.fetch_add(1, Ordering::Relaxed);
+                self.line.clear();
+            }
+            // An escaped quote ("") toggles twice, so counting parity is enough.
+            let quotes = self.line.iter().filter(|&&b| b == b'"').count();
+            self.quoted ^= quotes % 2 == 1;
+        }
+
+        let n = out.len().min(self.line.len() - self.pos);
+        out[..n].copy_from_slice(&self.line[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Splits concatenated CSV documents on lines equal to `delimiter` (ignoring surrounding
+/// whitespace), yielding one document at a time. Chunks with nothing but whitespace, such
+/// as after a trailing delimiter, are skipped.
+pub struct Chunks<R> {
+    inner: R,
+    delimiter: String,
+    done: bool,
+}
+
+impl<R: BufRead> Chunks<R> {
+    pub fn new(inner: R, delimiter: &str) -> Self {
+        Self {
+            inner,
+            delimiter: delimiter.trim().to_string(),
+            done: false,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Chunks<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = Vec::new();
+        let mut line = Vec::new();
+        while !self.done {
+            line.clear();
+            match self.inner.read_until(b'\n', &mut line) {
+                Ok(0) => self.done = true,
+                Ok(_) if line.trim_ascii() == self.delimiter.as_bytes() => {
+                    if chunk.iter().all(u8::is_ascii_whitespace) {
+                        chunk.clear();
+                        continue;
+                    }
+                    return Some(Ok(chunk));
+                }
+                Ok(_) => chunk.extend_from_slice(&line),
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        (!chunk.iter().all(u8::is_ascii_whitespace)).then_some(Ok(chunk))
+    }
+}
+
+/// Delimiters `--autodetect` chooses between.
+pub const CANDIDATE_DELIMITERS: [u8; 4] = [b',', b'\t', b';', b'|'];
+
+/// Non-blank lines `--autodetect` samples.
+const SNIFF_LINES: usize = 10;
+
+/// Reads the first few lines of `input` and picks its delimiter with [`sniff_delimiter`].
+/// Returns the pick and a reader that still yields the whole input.
+pub fn sniff(input: Box<dyn Read + Send>) -> io::Result<(Option<u8>, Box<dyn Read + Send>)> {
+    let mut reader = BufReader::new(input);
+    let mut sample = Vec::new();
+    let mut lines = 0;
+    while lines < SNIFF_LINES {
+        let start = sample.len();
+        if reader.read_until(b'\n', &mut sample)? == 0 {
+            break;
+        }
+        if !sample[start..].iter().all(u8::is_ascii_whitespace) {
+            lines += 1;
+        }
+    }
+    let delimiter = sniff_delimiter(&sample);
+    Ok((delimiter, Box::new(io::Cursor::new(sample).chain(reader))))
+}
+
+/// Scores each candidate by how many sample lines it splits into as many fields as the
+/// first line, which must contain it, ignoring delimiters inside double quotes. `None`
+/// when no candidate appears in the first line or the best ones are tied.
+pub fn sniff_delimiter(sample: &[u8]) -> Option<u8> {
+    let counts: Vec<[usize; 4]> = sample
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+        .map(|line| {
+            let mut counts = [0; 4];
+            let mut quoted = false;
+            for &b in line {
+                if b == b'"' {
+                    quoted = !quoted;
+                } else if !quoted {
+                    if let Some(i) = CANDIDATE_DELIMITERS.iter().position(|&d| d == b) {
+                        counts[i] += 1;
+                    }
+                }
+            }
+            counts
+        })
+        .collect();
+    let first = counts.first()?;
+
+    let scores: Vec<usize> = (0..CANDIDATE_DELIMITERS.len())
+        .map(|i| match first[i] {
+            0 => 0,
+            n => counts.iter().filter(|line| line[i] == n).count(),
+        })
+        .collect();
+    let best = *scores.iter().max()?;
+    let mut winners = (0..scores.len()).filter(|&i| scores[i] == best);
+    match (best, winners.next(), winners.next()) {
+        (1.., Some(i), None) => Some(CANDIDATE_DELIMITERS[i]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sharing_violation() -> io::Error {
+        io::Error::from_raw_os_error(32)
+    }
+
+    #[test]
+    fn test_sharing_violation_is_retried() {
+        let mut attempts = 0;
+        let result = open_with_retry(
+            3,
+            Duration::ZERO,
+            |e| e.raw_os_error() == Some(32),
+            || {
+                attempts += 1;
+                if attempts < 3 {
+                    Err(sharing_violation())
+                } else {
+                    Ok(attempts)
+                }
+            },
+        );
+
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn test_other_errors_and_exhausted_retries_fail() {
+        let mut attempts = 0;
+        let result: io::Result<()> = open_with_retry(
+            3,
+            Duration::ZERO,
+            |e| e.raw_os_error() == Some(32),
+            || {
+                attempts += 1;
+                Err(io::ErrorKind::NotFound.into())
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+
+        let mut attempts = 0;
+        let result: io::Result<()> = open_with_retry(
+            2,
+            Duration::ZERO,
+            |e| e.raw_os_error() == Some(32),
+            || {
+                attempts += 1;
+                Err(sharing_violation())
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn test_chunks_split_on_delimiter_lines() {
+        let input = "a,b\n1,2\n--\n  --  \na,b\n3,4\n--\n";
+        let chunks: Vec<Vec<u8>> = Chunks::new(input.as_bytes(), "--")
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(chunks, [b"a,b\n1,2\n".to_vec(), b"a,b\n3,4\n".to_vec()]);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_sharing_violation_detection() {
+        assert!(is_sharing_violation(&sharing_violation()));
+        assert!(!is_sharing_violation(&io::ErrorKind::NotFound.into()));
+    }
+}
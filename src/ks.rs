@@ -0,0 +1,89 @@
+/// Two-sample Kolmogorov–Smirnov test result.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KsResult {
+    /// Largest distance between the two empirical CDFs, in [0, 1].
+    pub statistic: f64,
+    /// Asymptotic p-value; only a rough approximation for small samples.
+    pub p_value: f64,
+    pub n: usize,
+    pub m: usize,
+}
+
+/// Compares two samples. Returns `None` if either is empty, since the empirical
+/// CDF of an empty sample is undefined.
+pub fn two_sample(a: &[f64], b: &[f64]) -> Option<KsResult> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+    let mut a = a.to_vec();
+    let mut b = b.to_vec();
+    a.sort_unstable_by(f64::total_cmp);
+    b.sort_unstable_by(f64::total_cmp);
+
+    let (n, m) = (a.len(), b.len());
+    let (mut i, mut j) = (0, 0);
+    let mut statistic: f64 = 0.0;
+    while i < n && j < m {
+        // Step past every copy of the smaller value in both samples so ties move together.
+        let x = if a[i] <= b[j] { a[i] } else { b[j] };
+        while i < n && a[i] <= x {
+            i += 1;
+        }
+        while j < m && b[j] <= x {
+            j += 1;
+        }
+        statistic = statistic.max((i as f64 / n as f64 - j as f64 / m as f64).abs());
+    }
+
+    let ne = (n * m) as f64 / (n + m) as f64;
+    let lambda = (ne.sqrt() + 0.12 + 0.11 / ne.sqrt()) * statistic;
+    Some(KsResult {
+        statistic,
+        p_value: kolmogorov_q(lambda),
+        n,
+        m,
+    })
+}
+
+/// Survival function of the Kolmogorov distribution,
+/// `2 * sum_{k>=1} (-1)^(k-1) exp(-2 k^2 lambda^2)`.
+fn kolmogorov_q(lambda: f64) -> f64 {
+    if lambda < 1e-3 {
+        return 1.0;
+    }
+    let mut sum = 0.0;
+    let mut sign = 1.0;
+    for k in 1..=100 {
+        let term = sign * (-2.0 * (k * k) as f64 * lambda * lambda).exp();
+        sum += term;
+        if term.abs() < 1e-12 {
+            break;
+        }
+        sign = -sign;
+    }
+    (2.0 * sum).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_different_distributions_have_large_statistic() {
+        let a: Vec<f64> = (0..200).map(|i| i as f64 / 10.0).collect();
+        let b: Vec<f64> = (0..200).map(|i| 15.0 + i as f64 / 10.0).collect();
+        let result = two_sample(&a, &b).unwrap();
+        assert!((result.statistic - 0.75).abs() < 1e-9, "{result:?}");
+        assert!(result.p_value < 1e-6, "{result:?}");
+
+        let same = two_sample(&a, &a).unwrap();
+        assert_eq!(same.statistic, 0.0);
+        assert_eq!(same.p_value, 1.0);
+    }
+
+    #[test]
+    fn test_empty_sample_has_no_result() {
+        assert_eq!(two_sample(&[], &[1.0]), None);
+        assert_eq!(two_sample(&[1.0], &[]), None);
+    }
+}
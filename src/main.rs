@@ -1,20 +1,89 @@
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{ArgGroup, Parser};
+use std::collections::HashSet;
+use std::io::{self, Write};
 use std::path::PathBuf;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+mod bench;
+mod buckets;
+mod dedup;
+mod expr;
+mod fastfloat;
+#[cfg(feature = "http")]
+mod gzip;
+#[cfg(feature = "http")]
+mod http;
+mod input;
+mod json;
+mod ks;
+mod metadata;
+mod output;
+mod parquet;
+mod percentile;
+mod pivot;
 mod processor;
+mod regex;
+mod report;
+mod resample;
+mod series;
+mod signals;
+mod snappy;
+mod sqlite;
+mod timestamp;
+mod tz;
+mod warnings;
+
+use buckets::{BucketScope, ThresholdSweep};
+use dedup::{MergePolicy, SameTimestamp};
+use metadata::{Calibration, SensorMetadata};
+use output::{OutputFile, WriteOptions};
+use processor::{ProcessOptions, ProcessingStats};
+use report::{Join, OutputFormat, Precision, RenderOptions, SortKey};
+use resample::ResampleAgg;
+use series::{QualityWeights, Transform};
+use timestamp::{TimeUnit, TimestampFormat};
 
 #[derive(Parser, Debug)]
 #[command(
     name = "rust-cli",
     version,
     author,
-    about = "Process large biometric CSV files at blazing speed using parallel execution"
+    about = "Process large biometric CSV files at blazing speed using parallel execution",
+    group(ArgGroup::new("source").required(true))
 )]
 struct Cli {
-    /// Path to the input CSV file (Timestamp, SensorID, Value)
-    #[arg(short, long, value_name = "FILE")]
-    input: PathBuf,
+    /// Path to the input CSV file (Timestamp, SensorID, Value), or - for stdin
+    #[arg(short, long, value_name = "FILE", group = "source")]
+    input: Option<PathBuf>,
+
+    /// With stdin input, split it into separate CSV documents at lines equal to LINE
+    #[arg(long, value_name = "LINE", conflicts_with_all = [
+        "pivot", "coverage_matrix", "anomalies", "transform", "sliding_window", "online_window", "percentiles", "value_buckets", "value_bin_width",
+        "threshold_sweep", "expected_interval", "timeline", "detect_clock_resets", "expect_monotonic_values", "pair_delta", "categorical", "errors_to",
+        "noise_floor", "iqr_filter",
+    ])]
+    chunk_delimiter: Option<String>,
+
+    /// Drop rows whose (timestamp, sensor, value) already appeared in an earlier --chunk-delimiter chunk
+    #[arg(long, default_value_t = false, requires = "chunk_delimiter")]
+    cross_file_dedup: bool,
+
+    /// Print interim stats to stderr whenever the process receives SIGUSR1 (Unix)
+    #[cfg(feature = "signals")]
+    #[arg(long, default_value_t = false)]
+    stats_on_signal: bool,
+
+    /// On Ctrl-C, stop reading and report the rows read so far as PARTIAL (exit code 130);
+    /// a second Ctrl-C quits at once (Unix)
+    #[cfg(feature = "signals")]
+    #[arg(long, default_value_t = false, conflicts_with = "repeat")]
+    partial_on_interrupt: bool,
+
+    /// Read the input CSV from an http:// URL instead of a file
+    #[cfg(feature = "http")]
+    #[arg(long, value_name = "URL", group = "source")]
+    url: Option<String>,
 
     /// Keep only rows where Value > threshold
     #[arg(short = 't', long, value_name = "FLOAT", default_value_t = 0.0)]
@@ -23,49 +92,1335 @@ struct Cli {
     /// Print per-sensor statistics after processing
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
+
+    /// Truncate parsed timestamps to this unit before any time-based processing
+    #[arg(long, value_name = "UNIT", value_enum)]
+    truncate_timestamp: Option<TimeUnit>,
+
+    /// Timestamp format: iso8601, epoch, epoch_ms, or a strftime pattern such as "%d/%m/%Y %H:%M"
+    #[arg(long, value_name = "PATTERN", default_value_t = TimestampFormat::Iso8601)]
+    timestamp_format: TimestampFormat,
+
+    /// Time zone of timestamps without an offset, e.g. Europe/Berlin or EST5EDT,M3.2.0,M11.1.0
+    #[arg(long, value_name = "ZONE")]
+    from_tz: Option<String>,
+
+    /// Rewrite timestamps as wall-clock time in this zone (default UTC)
+    #[arg(long, value_name = "ZONE")]
+    to_tz: Option<String>,
+
+    /// Run the processing N times and report min/mean/max timings
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    repeat: u32,
+
+    /// Read the input once before processing so timings reflect a warm OS page cache
+    #[arg(long, default_value_t = false)]
+    warm_cache: bool,
+
+    /// Silently skip lines that are empty or contain only whitespace
+    #[arg(long, default_value_t = false)]
+    skip_empty_lines: bool,
+
+    /// Print the column names and types of the Parquet file given as --input, read from its footer only, and exit
+    #[arg(long, default_value_t = false, requires = "input")]
+    parquet_schema: bool,
+
+    /// Output format for the summary and per-sensor table
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Sort the per-sensor table by this column
+    #[arg(long, value_enum, default_value_t = SortKey::Id)]
+    sort_by: SortKey,
+
+    /// Show only the first N sensors of the per-sensor table
+    #[arg(long, value_name = "N")]
+    top_n: Option<usize>,
+
+    /// Retry opening the input up to N times while it is locked by another process (Windows)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    retries: u32,
+
+    /// Read and parse rows on the main thread instead of pipelining them from a reader thread
+    #[arg(long, default_value_t = false)]
+    no_pipeline: bool,
+
+    /// Keep only every Nth data row (rows 0, N, 2N, ...) before filtering
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    nth: Option<u64>,
+
+    /// SQLite database whose roster table lists the sensors to process; others are dropped
+    #[arg(long, value_name = "FILE")]
+    roster_db: Option<PathBuf>,
+
+    /// Table of --roster-db holding the sensor IDs
+    #[arg(
+        long,
+        value_name = "NAME",
+        default_value = "sensors",
+        requires = "roster_db"
+    )]
+    roster_table: String,
+
+    /// Column of --roster-table holding the sensor IDs
+    #[arg(
+        long,
+        value_name = "NAME",
+        default_value = "sensor_id",
+        requires = "roster_db"
+    )]
+    roster_column: String,
+
+    /// Write a wide CSV with one row per timestamp and one column per sensor
+    #[arg(long, value_name = "FILE")]
+    pivot: Option<PathBuf>,
+
+    /// Write a CSV with one row per sensor and a 0/1 column per --resample bucket marking readings
+    #[arg(long, value_name = "FILE", requires = "resample")]
+    coverage_matrix: Option<PathBuf>,
+
+    /// Allow outputs that exceed built-in safety limits (e.g. more than 1000 pivot columns)
+    #[arg(long, default_value_t = false)]
+    force: bool,
+
+    /// Clamp values below this bound up to it before filtering
+    #[arg(long, value_name = "FLOAT", allow_negative_numbers = true)]
+    clamp_min: Option<f64>,
+
+    /// Clamp values above this bound down to it before filtering
+    #[arg(long, value_name = "FLOAT", allow_negative_numbers = true)]
+    clamp_max: Option<f64>,
+
+    /// Only report how many values --clamp-min/--clamp-max would change; do not modify them
+    #[arg(long, default_value_t = false)]
+    clamp_report: bool,
+
+    /// Drop values outside each sensor's Tukey fences, Q1 - K*IQR and Q3 + K*IQR (K defaults to 1.5)
+    #[arg(long, value_name = "K", num_args = 0..=1, default_missing_value = "1.5")]
+    iqr_filter: Option<f64>,
+
+    /// Only report how many values --iqr-filter would drop; keep them
+    #[arg(long, default_value_t = false, requires = "iqr_filter")]
+    iqr_report: bool,
+
+    /// Collapse rows sharing a (timestamp, sensor) pair into one using this reducer
+    #[arg(long, value_name = "POLICY", value_enum)]
+    merge_duplicates: Option<MergePolicy>,
+
+    /// Collapse each sensor's readings less than this long after the first of a cluster into it (e.g. 100ms)
+    #[arg(long, value_name = "DURATION", value_parser = timestamp::parse_duration)]
+    dedup_window: Option<i64>,
+
+    /// Value a --dedup-window cluster keeps
+    #[arg(long, value_name = "POLICY", value_enum, default_value_t = MergePolicy::First, requires = "dedup_window")]
+    dedup_policy: MergePolicy,
+
+    /// How time-based features resolve several readings of one sensor at the same timestamp
+    #[arg(long, value_name = "POLICY", value_enum, default_value_t = SameTimestamp::Mean)]
+    same_timestamp: SameTimestamp,
+
+    /// Write readings that deviate from their sensor's rolling mean by more than --zscore std devs
+    #[arg(long, value_name = "FILE")]
+    anomalies: Option<PathBuf>,
+
+    /// Z-score above which --anomalies flags a reading
+    #[arg(long, value_name = "K", default_value_t = 3.0)]
+    zscore: f64,
+
+    /// Number of preceding readings in the rolling window used by --anomalies
+    #[arg(long, value_name = "N", default_value_t = 20, value_parser = clap::value_parser!(u64).range(2..))]
+    window: u64,
+
+    /// CSV of per-sensor metadata (SensorID plus optional Unit column)
+    #[arg(long, value_name = "FILE")]
+    metadata: Option<PathBuf>,
+
+    /// CSV of per-sensor calibration (SensorID, Offset, optional Scale): value * Scale + Offset
+    #[arg(long, value_name = "FILE")]
+    offsets: Option<PathBuf>,
+
+    /// Append each sensor's unit from --metadata to value columns in the per-sensor table
+    #[arg(long, default_value_t = false, requires = "metadata")]
+    show_units: bool,
+
+    /// Decimal places of averages in the per-sensor table and the summary
+    #[arg(long, value_name = "DECIMALS", default_value_t = 6, value_parser = clap::value_parser!(u32).range(..=15))]
+    avg_precision: u32,
+
+    /// Decimal places of percentages in the summary and the coverage report
+    #[arg(long, value_name = "DECIMALS", default_value_t = 2, value_parser = clap::value_parser!(u32).range(..=15))]
+    pct_precision: u32,
+
+    /// Only break these sensor IDs out per sensor; global stats still cover every row
+    #[arg(long, value_name = "ID,ID,...", value_delimiter = ',')]
+    only_sensors: Option<Vec<String>>,
+
+    /// Group the per-sensor breakdown by the first capture group of this regex on the sensor ID
+    #[arg(long, value_name = "PATTERN")]
+    group_regex: Option<regex::Regex>,
+
+    /// Leave sensors that --group-regex does not match out of the breakdown (default: "(no match)" group)
+    #[arg(long, default_value_t = false, requires = "group_regex")]
+    skip_unmatched: bool,
+
+    /// Add a per-sensor count of distinct values (pair with --round-values to ignore float noise)
+    #[arg(long, default_value_t = false)]
+    distinct_values: bool,
+
+    /// Write per-sensor stats to FILE: CSV, or one JSON object per line with --format json
+    #[arg(long, value_name = "FILE")]
+    sensor_output: Option<PathBuf>,
+
+    /// Write each sensor's (or --group-regex group's) stats to its own file in DIR: JSON with --format json, CSV otherwise
+    #[arg(long, value_name = "DIR")]
+    group_output_dir: Option<PathBuf>,
+
+    /// Also write each group's filtered values to its --group-output-dir file
+    #[arg(long, default_value_t = false, requires = "group_output_dir")]
+    group_output_values: bool,
+
+    /// Print the N sensors with the widest value range (max - min)
+    #[arg(long, value_name = "N")]
+    top_by_range: Option<usize>,
+
+    /// Print these per-sensor percentiles (0-100) of the filtered values, e.g. 50,90,99
+    #[arg(long, value_name = "P,P,...", value_delimiter = ',')]
+    percentiles: Vec<f64>,
+
+    /// Keep at most MB of percentile values in memory, spilling sorted runs to temp files beyond it
+    #[arg(long, value_name = "MB", value_parser = clap::value_parser!(u64).range(1..), requires = "percentiles")]
+    spill_to_disk: Option<u64>,
+
+    /// Second CSV file to compare the input against
+    #[arg(long, value_name = "FILE")]
+    compare: Option<PathBuf>,
+
+    /// Run a two-sample Kolmogorov-Smirnov test on the filtered values of --input and --compare
+    #[arg(long, default_value_t = false, requires = "compare")]
+    ks_test: bool,
+
+    /// Sensors in the --compare per-sensor comparison: in both inputs, all of --input, or all of either
+    #[arg(long, value_enum, default_value_t = Join::Outer, requires = "compare")]
+    join: Join,
+
+    /// Significance level for --ks-test
+    #[arg(
+        long,
+        value_name = "ALPHA",
+        default_value_t = 0.05,
+        requires = "ks_test"
+    )]
+    alpha: f64,
+
+    /// Print how many sensors fall into each reading-count bucket
+    #[arg(long, default_value_t = false)]
+    count_histogram: bool,
+
+    /// Upper bucket edges for --count-histogram (default: 10,100,1000,10000,100000)
+    #[arg(
+        long,
+        value_name = "N,N,...",
+        value_delimiter = ',',
+        requires = "count_histogram"
+    )]
+    count_edges: Option<Vec<usize>>,
+
+    /// Print count and average over sliding time windows of this width (e.g. 10m)
+    #[arg(long, value_name = "DURATION", value_parser = timestamp::parse_duration)]
+    sliding_window: Option<i64>,
+
+    /// Distance between consecutive window starts (defaults to the window width)
+    #[arg(long, value_name = "DURATION", value_parser = timestamp::parse_duration, requires = "sliding_window")]
+    step: Option<i64>,
+
+    /// Track the mean and variance of the last N filtered rows (Welford, over all sensors)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    online_window: Option<u64>,
+
+    /// Print the --online-window stats after every M filtered rows (defaults to N)
+    #[arg(long, value_name = "M", requires = "online_window", value_parser = clap::value_parser!(u64).range(1..))]
+    emit_stats_every: Option<u64>,
+
+    /// Compute each row's value from other columns, e.g. "systolic - diastolic" (Value may be absent)
+    #[arg(long, value_name = "EXPR")]
+    value_expr: Option<expr::Expr>,
+
+    /// Treat Value as a category name (e.g. OK/FAIL) and count categories instead of averaging
+    #[arg(long, default_value_t = false, conflicts_with_all = [
+        "value_expr", "offsets", "format", "percent", "quantize", "round_values", "clamp_min", "clamp_max", "iqr_filter",
+        "merge_duplicates", "dedup_window", "max_rate", "max_per_sensor", "percentiles", "value_buckets", "value_bin_width",
+        "threshold_sweep", "target", "noise_floor", "distinct_values", "pivot", "coverage_matrix", "anomalies", "sliding_window", "online_window", "transform",
+        "flatline_duration", "expected_interval", "timeline", "detect_clock_resets", "expect_monotonic_values", "pair_delta", "compare", "top_by_range", "count_histogram",
+        "sensor_output", "group_output_dir", "cross_file_dedup",
+    ])]
+    categorical: bool,
+
+    /// Snap every value to the nearest multiple of STEP before filtering (e.g. 0.5)
+    #[arg(long, value_name = "STEP")]
+    quantize: Option<f64>,
+
+    /// Drop readings that change faster than this many units per second from the sensor's previous kept reading
+    #[arg(long, value_name = "VALUE_PER_SECOND")]
+    max_rate: Option<f64>,
+
+    /// Keep only each sensor's first N readings by timestamp, so chatty sensors do not dominate
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    max_per_sensor: Option<u64>,
+
+    /// Treat values as percentages and count those outside [0, 100]
+    #[arg(long, default_value_t = false)]
+    percent: bool,
+
+    /// With --percent, divide values by 100 before clamping and filtering
+    #[arg(long, default_value_t = false, requires = "percent")]
+    renormalize: bool,
+
+    /// Round every value to this many decimal places before filtering (after --quantize)
+    #[arg(long, value_name = "DECIMALS", value_parser = clap::value_parser!(u32).range(..=15))]
+    round_values: Option<u32>,
+
+    /// Count each distinct value, per sensor (default) or across all sensors
+    #[arg(long, value_name = "SCOPE", value_enum, num_args = 0..=1, default_missing_value = "sensor")]
+    value_buckets: Option<BucketScope>,
+
+    /// Most distinct values listed per --value-buckets group; the rest are counted as "other"
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 20,
+        requires = "value_buckets"
+    )]
+    max_buckets: usize,
+
+    /// Count and average the filtered readings in value bins of this width, e.g. [0,10), [10,20)
+    #[arg(long, value_name = "WIDTH")]
+    value_bin_width: Option<f64>,
+
+    /// Report filtered rows and average at each threshold START, START+STEP, ... END, from one pass
+    #[arg(long, value_name = "START:END:STEP")]
+    threshold_sweep: Option<ThresholdSweep>,
+
+    /// Report the mean absolute and root-mean-square deviation of the filtered values from this setpoint
+    #[arg(long, value_name = "VALUE", allow_negative_numbers = true)]
+    target: Option<f64>,
+
+    /// Add a per-sensor noise-floor column: the std-dev of consecutive differences / sqrt(2)
+    #[arg(long, default_value_t = false, conflicts_with = "group_regex")]
+    noise_floor: bool,
+
+    /// Skip rows that fail to parse instead of aborting, and report how many were skipped
+    #[arg(long, default_value_t = false)]
+    skip_bad_rows: bool,
+
+    /// Fail on invalid UTF-8 with the row and column it is in (skipped with --skip-bad-rows)
+    #[arg(long, default_value_t = false)]
+    strict_utf8: bool,
+
+    /// Parse values with a correctly-rounded fast path instead of serde (same results, faster on numeric-heavy files)
+    #[arg(long, default_value_t = false, conflicts_with_all = ["value_expr", "categorical"])]
+    fast_float: bool,
+
+    /// Match the Timestamp, SensorID, Value and --value-expr column names against the header ignoring case
+    #[arg(long, default_value_t = false)]
+    header_ignore_case: bool,
+
+    /// Detect the field delimiter (comma, tab, semicolon or pipe) from the first lines
+    #[arg(long, default_value_t = false)]
+    autodetect: bool,
+
+    /// Write skipped rows as a JSON array of {line, error, raw} objects
+    #[arg(long, value_name = "FILE", requires = "skip_bad_rows")]
+    errors_to: Option<PathBuf>,
+
+    /// Keep details for at most N skipped rows (the rest are only counted)
+    #[arg(long, value_name = "N", default_value_t = 1000)]
+    max_errors: usize,
+
+    /// Destination for per-sensor series transforms (Timestamp,SensorID,Value CSV)
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Exact column order of the --output CSV, e.g. Value,Timestamp; unlisted columns are dropped
+    #[arg(
+        long,
+        value_name = "COL,COL,...",
+        value_delimiter = ',',
+        requires = "output"
+    )]
+    output_order: Option<Vec<String>>,
+
+    /// Write the N-point rolling sum of each sensor's readings to --output
+    #[arg(long, value_name = "N", group = "transform", requires = "output", value_parser = clap::value_parser!(u64).range(1..))]
+    rolling_sum: Option<u64>,
+
+    /// Write the N-point rolling median of each sensor's readings to --output (despiking)
+    #[arg(long, value_name = "N", group = "transform", requires = "output", value_parser = clap::value_parser!(u64).range(1..))]
+    rolling_median: Option<u64>,
+
+    /// Write each sensor's readings scaled to [0, 1] between its min and max to --output
+    #[arg(
+        long,
+        default_value_t = false,
+        group = "transform",
+        requires = "output"
+    )]
+    minmax_scale: bool,
+
+    /// Write each sensor's running total of its readings, ordered by timestamp, to --output
+    #[arg(
+        long,
+        default_value_t = false,
+        group = "transform",
+        requires = "output"
+    )]
+    cumulative: bool,
+
+    /// Write each sensor's mean over consecutive buckets of this width (e.g. 5m) to --output
+    #[arg(long, value_name = "DURATION", group = "transform", value_parser = timestamp::parse_duration)]
+    resample: Option<i64>,
+
+    /// Reducer applied to the readings in each --resample bucket
+    #[arg(long, value_name = "AGG", value_enum, default_value_t = ResampleAgg::Mean, requires = "resample")]
+    resample_agg: ResampleAgg,
+
+    /// Carry each sensor's last value forward into empty --resample buckets and --pivot cells
+    #[arg(long, default_value_t = false)]
+    ffill: bool,
+
+    /// Carry a value into at most N empty slots after each reading (default: no limit)
+    #[arg(long, value_name = "N", requires = "ffill")]
+    ffill_limit: Option<u64>,
+
+    /// Report runs where a sensor's value stays unchanged for at least this long (e.g. 10m)
+    #[arg(long, value_name = "DURATION", value_parser = timestamp::parse_duration)]
+    flatline_duration: Option<i64>,
+
+    /// Largest difference from a run's first value still treated as unchanged
+    #[arg(long, value_name = "FLOAT", default_value_t = 1e-9)]
+    flatline_epsilon: f64,
+
+    /// Report each sensor's share of expected readings at this interval (e.g. 1m) over its span
+    #[arg(long, value_name = "DURATION", value_parser = timestamp::parse_duration)]
+    expected_interval: Option<i64>,
+
+    /// Flag sensors whose --expected-interval coverage is below this percentage
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value_t = 90.0,
+        requires = "expected_interval"
+    )]
+    min_coverage: f64,
+
+    /// Score each sensor 0-100 from its coverage, noise floor and flatlined share of readings
+    #[arg(long, default_value_t = false, requires_all = ["expected_interval", "flatline_duration"])]
+    quality_score: bool,
+
+    /// Flag sensors whose --quality-score is below this
+    #[arg(
+        long,
+        value_name = "SCORE",
+        default_value_t = 50.0,
+        requires = "quality_score"
+    )]
+    min_quality: f64,
+
+    /// Weight of coverage in --quality-score
+    #[arg(
+        long,
+        value_name = "WEIGHT",
+        default_value_t = 1.0,
+        requires = "quality_score"
+    )]
+    quality_weight_coverage: f64,
+
+    /// Weight of the noise component in --quality-score
+    #[arg(
+        long,
+        value_name = "WEIGHT",
+        default_value_t = 1.0,
+        requires = "quality_score"
+    )]
+    quality_weight_noise: f64,
+
+    /// Weight of the flatline component in --quality-score
+    #[arg(
+        long,
+        value_name = "WEIGHT",
+        default_value_t = 1.0,
+        requires = "quality_score"
+    )]
+    quality_weight_flatline: f64,
+
+    /// Report readings whose timestamp is earlier than the same sensor's previous reading
+    #[arg(long, default_value_t = false)]
+    detect_clock_resets: bool,
+
+    /// Treat values as counters: report every reading whose value is below the sensor's previous one
+    #[arg(long, default_value_t = false)]
+    expect_monotonic_values: bool,
+
+    /// Summarize LEFT minus RIGHT over the timestamps where both sensors have a reading
+    #[arg(long, value_name = "LEFT,RIGHT", value_delimiter = ',')]
+    pair_delta: Option<Vec<String>>,
+
+    /// Show each sensor's reading density as a sparkline of N slots over the input's time span
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    timeline: Option<u64>,
+
+    /// With --format json, wrap the stats in a versioned {version, generated_at, config, stats} envelope
+    #[arg(long, default_value_t = false)]
+    json_envelope: bool,
+
+    /// Leave the final newline off the report (text, JSON, HTML or Markdown) on stdout
+    #[arg(long, default_value_t = false)]
+    no_trailing_newline: bool,
+
+    /// Print the process's peak memory use (resident set size) to stderr at the end
+    #[arg(long, default_value_t = false)]
+    memory_report: bool,
+
+    /// Write buffer size in KB for every output file (minimum 4)
+    #[arg(long, value_name = "KB", default_value_t = 64, value_parser = clap::value_parser!(u64).range(output::MIN_WRITE_BUFFER_KB..))]
+    write_buffer: u64,
+
+    /// Flush file outputs after every row, for streaming `-o -` into a pipe
+    #[arg(long, default_value_t = false)]
+    line_buffered: bool,
+
+    /// Fail (non-zero exit) if processing raised any warnings, listing them all
+    #[arg(long, default_value_t = false)]
+    abort_on_warning: bool,
+}
+
+impl Cli {
+    fn transform(&self) -> Option<Transform> {
+        self.rolling_sum
+            .map(|n| Transform::RollingSum(n as usize))
+            .or(self
+                .rolling_median
+                .map(|n| Transform::RollingMedian(n as usize)))
+            .or(self.minmax_scale.then_some(Transform::MinmaxScale))
+            .or(self.cumulative.then_some(Transform::Cumulative))
+            .or(self.resample.map(|step| Transform::Resample {
+                step,
+                ffill: self.ffill_limit(),
+                agg: self.resample_agg,
+            }))
+    }
+
+    fn precision(&self) -> Precision {
+        Precision {
+            average: self.avg_precision as usize,
+            percent: self.pct_precision as usize,
+        }
+    }
+
+    fn stats_on_signal(&self) -> bool {
+        #[cfg(feature = "signals")]
+        return self.stats_on_signal;
+        #[cfg(not(feature = "signals"))]
+        false
+    }
+
+    fn partial_on_interrupt(&self) -> bool {
+        #[cfg(feature = "signals")]
+        return self.partial_on_interrupt;
+        #[cfg(not(feature = "signals"))]
+        false
+    }
+
+    /// Empty slots --ffill may fill after each reading, if enabled.
+    fn ffill_limit(&self) -> Option<usize> {
+        self.ffill
+            .then(|| self.ffill_limit.map_or(usize::MAX, |n| n as usize))
+    }
+
+    /// The input file path or URL, as shown in messages.
+    fn source_name(&self) -> String {
+        #[cfg(feature = "http")]
+        if let Some(url) = &self.url {
+            return url.clone();
+        }
+        match &self.input {
+            Some(p) if input::is_stdin(p) => "stdin".to_string(),
+            Some(p) => p.display().to_string(),
+            None => String::new(),
+        }
+    }
+
+    /// Processes the input. With --chunk-delimiter, returns the merged total and each
+    /// chunk's stats.
+    fn process(&self, opts: &ProcessOptions) -> Result<(ProcessingStats, Vec<ProcessingStats>)> {
+        #[cfg(feature = "http")]
+        if let Some(url) = &self.url {
+            return Ok((
+                processor::process_reader(http::get(url)?, url, opts)?,
+                Vec::new(),
+            ));
+        }
+        let input = self
+            .input
+            .as_deref()
+            .expect("clap requires --input or --url");
+        if !input::is_stdin(input) {
+            return Ok((processor::process(input, opts)?, Vec::new()));
+        }
+        match &self.chunk_delimiter {
+            Some(delimiter) => {
+                let stdin = io::stdin().lock();
+                let chunks = processor::process_chunks(stdin, delimiter, "stdin", opts)?;
+                let total = chunks
+                    .iter()
+                    .cloned()
+                    .reduce(ProcessingStats::merge)
+                    .unwrap_or_default();
+                Ok((total, chunks))
+            }
+            None => Ok((
+                // Unlocked: the reader thread needs a handle it can own.
+                processor::process_reader(Box::new(io::stdin()), "stdin", opts)?,
+                Vec::new(),
+            )),
+        }
+    }
+
+    /// File outputs set to `-`, which write to stdout in place of the report.
+    fn stdout_outputs(&self) -> Vec<&'static str> {
+        [
+            ("--output", &self.output),
+            ("--pivot", &self.pivot),
+            ("--coverage-matrix", &self.coverage_matrix),
+            ("--anomalies", &self.anomalies),
+            ("--errors-to", &self.errors_to),
+            ("--sensor-output", &self.sensor_output),
+        ]
+        .into_iter()
+        .filter(|(_, path)| path.as_deref().is_some_and(output::is_stdout))
+        .map(|(flag, _)| flag)
+        .collect()
+    }
+
+    /// The options that shape `--format json` output, for the `--json-envelope` config block.
+    fn json_config(&self) -> Vec<(&'static str, String)> {
+        let opt = |n: Option<u64>| n.map_or_else(|| "null".to_string(), |n| n.to_string());
+        vec![
+            ("input", json::string(&self.source_name())),
+            ("filter_threshold", json::number(self.filter_threshold)),
+            (
+                "timestamp_format",
+                json::string(&self.timestamp_format.to_string()),
+            ),
+            (
+                "sort_by",
+                json::string(&format!("{:?}", self.sort_by).to_lowercase()),
+            ),
+            ("top_n", opt(self.top_n.map(|n| n as u64))),
+            ("nth", opt(self.nth)),
+            ("skip_bad_rows", self.skip_bad_rows.to_string()),
+        ]
+    }
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    run(&cli)?;
+    if cli.memory_report {
+        io::stdout().flush()?;
+        eprintln!("{}", bench::memory_report());
+    }
+    if cli.partial_on_interrupt() && signals::interrupted() {
+        io::stdout().flush()?;
+        std::process::exit(signals::PARTIAL_EXIT_CODE);
+    }
+    Ok(())
+}
+
+fn run(cli: &Cli) -> Result<()> {
+    let from_stdin = cli.input.as_deref().is_some_and(input::is_stdin);
+    if cli.chunk_delimiter.is_some() && !from_stdin {
+        anyhow::bail!("--chunk-delimiter needs the input on stdin (--input -).");
+    }
+    if from_stdin && (cli.repeat > 1 || cli.warm_cache) {
+        anyhow::bail!("--repeat and --warm-cache need a file input; stdin can only be read once.");
+    }
+
+    if let Some(input) = cli.input.as_ref().filter(|_| !from_stdin) {
+        if !input.exists() {
+            anyhow::bail!("Input file '{}' does not exist.", input.display());
+        }
+        if !input.is_file() {
+            anyhow::bail!("'{}' is not a regular file.", input.display());
+        }
+    }
+
+    if cli.parquet_schema {
+        let input = cli
+            .input
+            .as_deref()
+            .expect("--parquet-schema requires --input");
+        if from_stdin {
+            anyhow::bail!("--parquet-schema needs a file; the footer is read from the end of it.");
+        }
+        let schema = parquet::read_schema(input)?;
+        let report = report::render_parquet_schema(&input.display().to_string(), &schema);
+        print!("{}", report::finish(&report, !cli.no_trailing_newline));
+        return Ok(());
+    }
+
+    if let (Some(min), Some(max)) = (cli.clamp_min, cli.clamp_max) {
+        if min > max {
+            anyhow::bail!("--clamp-min ({min}) must not be greater than --clamp-max ({max}).");
+        }
+    }
+    if cli.clamp_report && cli.clamp_min.is_none() && cli.clamp_max.is_none() {
+        anyhow::bail!("--clamp-report needs --clamp-min and/or --clamp-max.");
+    }
+
+    if cli
+        .quantize
+        .is_some_and(|step| !(step > 0.0 && step.is_finite()))
+    {
+        anyhow::bail!("--quantize step must be a positive number.");
+    }
+    if cli
+        .value_bin_width
+        .is_some_and(|w| !(w > 0.0 && w.is_finite()))
+    {
+        anyhow::bail!("--value-bin-width must be a positive number.");
+    }
+
+    if cli.resample.is_some() && cli.output.is_none() && cli.coverage_matrix.is_none() {
+        anyhow::bail!("--resample needs --output or --coverage-matrix.");
+    }
+
+    if cli.ffill && cli.resample_agg == ResampleAgg::Count {
+        anyhow::bail!("--ffill cannot be combined with --resample-agg count; an empty bucket's count is 0, not the previous one.");
+    }
+
+    if cli.ffill && cli.resample.is_some() && cli.resample_agg == ResampleAgg::Minmax {
+        anyhow::bail!("--ffill cannot be combined with --resample-agg minmax; an empty bucket has no extremes to carry.");
+    }
+
+    if cli.iqr_filter.is_some_and(|k| !k.is_finite() || k < 0.0) {
+        anyhow::bail!("--iqr-filter K must be a non-negative number.");
+    }
+
+    let weights = [
+        cli.quality_weight_coverage,
+        cli.quality_weight_noise,
+        cli.quality_weight_flatline,
+    ];
+    if weights.iter().any(|w| !w.is_finite() || *w < 0.0) || weights.iter().sum::<f64>() <= 0.0 {
+        anyhow::bail!(
+            "--quality-weight-* must be non-negative numbers, at least one of them positive."
+        );
+    }
+
+    if cli.ffill && cli.resample.is_none() && cli.pivot.is_none() {
+        anyhow::bail!("--ffill needs --resample or --pivot.");
+    }
+
+    if cli.json_envelope && cli.format != OutputFormat::Json {
+        anyhow::bail!("--json-envelope needs --format json.");
+    }
+
+    if let Some(compare) = &cli.compare {
+        if !compare.is_file() {
+            anyhow::bail!("Compare file '{}' does not exist.", compare.display());
+        }
+    }
+    if !(cli.alpha > 0.0 && cli.alpha < 1.0) {
+        anyhow::bail!("--alpha must be between 0 and 1.");
+    }
+
+    if cli
+        .max_rate
+        .is_some_and(|rate| !(rate > 0.0 && rate.is_finite()))
+    {
+        anyhow::bail!("--max-rate must be a positive number.");
+    }
+
+    if cli.percentiles.iter().any(|p| !(0.0..=100.0).contains(p)) {
+        anyhow::bail!("--percentiles must be between 0 and 100.");
+    }
+
+    if cli.group_regex.as_ref().is_some_and(|re| re.groups() == 0) {
+        anyhow::bail!("--group-regex needs a capture group, e.g. '^(floor\\d+)-'.");
+    }
+
+    if cli.format == OutputFormat::Parquet {
+        if cli.output.is_none() {
+            anyhow::bail!("--format parquet needs --output for the Parquet file.");
+        }
+        if cli.transform().is_some()
+            || cli.output_order.is_some()
+            || cli.chunk_delimiter.is_some()
+            || cli.categorical
+        {
+            anyhow::bail!(
+                "--format parquet cannot be combined with series transforms, --output-order, --chunk-delimiter or --categorical."
+            );
+        }
+    }
+
+    if let Some(ids) = &cli.pair_delta {
+        if ids.len() != 2 || ids[0] == ids[1] {
+            anyhow::bail!("--pair-delta needs two different sensor IDs, e.g. left,right.");
+        }
+    }
+
+    if let Some(edges) = &cli.count_edges {
+        if edges.is_empty() || edges[0] == 0 || edges.windows(2).any(|w| w[0] >= w[1]) {
+            anyhow::bail!("--count-edges must be positive and strictly increasing.");
+        }
+    }
+
+    let metadata = cli
+        .metadata
+        .as_deref()
+        .map(SensorMetadata::load)
+        .transpose()?;
+    let calibration = cli.offsets.as_deref().map(Calibration::load).transpose()?;
+    let from_tz = cli.from_tz.as_deref().map(tz::Zone::load).transpose()?;
+    let to_tz = cli.to_tz.as_deref().map(tz::Zone::load).transpose()?;
+    let roster = cli
+        .roster_db
+        .as_deref()
+        .map(|db| sqlite::read_column(db, &cli.roster_table, &cli.roster_column))
+        .transpose()?
+        .map(HashSet::from_iter);
+
+    let output_order = cli
+        .output_order
+        .as_deref()
+        .map(processor::output_order)
+        .transpose()?;
+
+    let to_stdout = cli.stdout_outputs();
+    if to_stdout.len() > 1 {
+        anyhow::bail!(
+            "Only one output can be '-' (stdout); got {}.",
+            to_stdout.join(", ")
+        );
+    }
+    if !to_stdout.is_empty() && cli.format != OutputFormat::Text {
+        anyhow::bail!(
+            "{} '-' writes to stdout and cannot be combined with --format.",
+            to_stdout[0]
+        );
+    }
+    // With an output on stdout, the report is left out so the stream stays clean.
+    let text = cli.format.is_text() && to_stdout.is_empty();
+
+    if text {
+        println!("Input file      : {}", cli.source_name());
+        if let Some(compare) = &cli.compare {
+            println!("Compare file    : {}", compare.display());
+        }
+        println!("Filter threshold: {}", cli.filter_threshold);
+        println!("Threads (rayon) : {}", rayon::current_num_threads());
+        if cli.repeat > 1 {
+            println!(
+                "Repeat          : {} runs (input is re-read on every run)",
+                cli.repeat
+            );
+        }
+    }
+
+    // A URL body cannot be cached by the OS, so there is nothing to warm.
+    if let (true, Some(input)) = (cli.warm_cache, &cli.input) {
+        let start = Instant::now();
+        let bytes = bench::warm_cache(input)?;
+        if text {
+            println!(
+                "Cache warm-up   : {:.1} MB read in {:.4?}",
+                bytes as f64 / 1_048_576.0,
+                start.elapsed()
+            );
+        }
+    }
+    if text {
+        println!();
+    }
+
+    if cli.stats_on_signal() {
+        signals::install()?;
+    }
+    if cli.partial_on_interrupt() {
+        signals::install_interrupt()?;
+    }
+
+    let opts = ProcessOptions {
+        threshold: cli.filter_threshold,
+        verbose: cli.verbose
+            || !cli.format.is_text()
+            || cli.top_by_range.is_some()
+            || cli.count_histogram
+            || cli.sensor_output.is_some()
+            || cli.compare.is_some()
+            || cli.group_output_dir.is_some()
+            || cli.distinct_values,
+        truncate_timestamp: cli.truncate_timestamp,
+        timestamp_format: cli.timestamp_format.clone(),
+        from_tz,
+        to_tz,
+        skip_empty_lines: cli.skip_empty_lines,
+        retries: cli.retries,
+        no_pipeline: cli.no_pipeline,
+        nth: cli.nth.map(|n| n as usize),
+        roster,
+        pivot: cli.pivot.clone(),
+        coverage_matrix: cli.coverage_matrix.clone().zip(cli.resample),
+        force: cli.force,
+        clamp_min: cli.clamp_min,
+        clamp_max: cli.clamp_max,
+        clamp_report: cli.clamp_report,
+        iqr_filter: cli.iqr_filter,
+        iqr_report: cli.iqr_report,
+        merge_duplicates: cli.merge_duplicates,
+        dedup_window: cli.dedup_window.map(|window| (window, cli.dedup_policy)),
+        anomalies: cli.anomalies.clone(),
+        zscore: cli.zscore,
+        window: cli.window as usize,
+        sliding_window: cli.sliding_window.map(|w| (w, cli.step.unwrap_or(w))),
+        online_window: cli
+            .online_window
+            .map(|n| (n as usize, cli.emit_stats_every.unwrap_or(n) as usize)),
+        quantize: cli.quantize,
+        skip_bad_rows: cli.skip_bad_rows,
+        strict_utf8: cli.strict_utf8,
+        fast_float: cli.fast_float,
+        header_ignore_case: cli.header_ignore_case,
+        cross_file_dedup: cli.cross_file_dedup,
+        autodetect: cli.autodetect,
+        errors_to: cli.errors_to.clone(),
+        max_errors: cli.max_errors,
+        transform: cli.transform(),
+        output: cli.output.clone(),
+        output_order,
+        parquet: cli.format == OutputFormat::Parquet,
+        calibration,
+        flatline_duration: cli.flatline_duration,
+        flatline_epsilon: cli.flatline_epsilon,
+        expected_interval: cli.expected_interval,
+        min_coverage: cli.min_coverage,
+        quality_weights: cli.quality_score.then_some(QualityWeights {
+            coverage: cli.quality_weight_coverage,
+            noise: cli.quality_weight_noise,
+            flatline: cli.quality_weight_flatline,
+        }),
+        min_quality: cli.min_quality,
+        timeline: cli.timeline.map(|n| n as usize),
+        detect_clock_resets: cli.detect_clock_resets,
+        expect_monotonic_values: cli.expect_monotonic_values,
+        pair_delta: cli
+            .pair_delta
+            .as_ref()
+            .map(|ids| (ids[0].clone(), ids[1].clone())),
+        percent: cli.percent,
+        renormalize: cli.renormalize,
+        max_rate: cli.max_rate,
+        max_per_sensor: cli.max_per_sensor.map(|n| n as usize),
+        group_regex: cli.group_regex.clone(),
+        skip_unmatched: cli.skip_unmatched,
+        same_timestamp: cli.same_timestamp,
+        distinct_values: cli.distinct_values,
+        value_expr: cli.value_expr.clone(),
+        categorical: cli.categorical,
+        ffill: cli.ffill_limit(),
+        stats_on_signal: cli.stats_on_signal(),
+        partial_on_interrupt: cli.partial_on_interrupt(),
+        keep_values: cli.ks_test,
+        group_values: cli.group_output_values,
+        round_values: cli.round_values,
+        value_buckets: cli.value_buckets,
+        max_buckets: cli.max_buckets,
+        value_bin_width: cli.value_bin_width,
+        threshold_sweep: cli.threshold_sweep.clone().map(|sweep| sweep.0),
+        target: cli.target,
+        noise_floor: cli.noise_floor,
+        only_sensors: cli
+            .only_sensors
+            .as_ref()
+            .map(|ids| ids.iter().cloned().collect()),
+        percentiles: cli.percentiles.clone(),
+        spill_budget: cli.spill_to_disk.map(|mb| mb as usize * 1024 * 1024),
+        write: WriteOptions {
+            buffer_size: cli.write_buffer as usize * 1024,
+            line_buffered: cli.line_buffered,
+        },
+    };
+
+    let ((mut stats, chunks), timings) = bench::repeat(cli.repeat as usize, || {
+        cli.process(&opts)
+            .with_context(|| format!("Failed to process '{}'", cli.source_name()))
+    })?;
+
+    let compared = match &cli.compare {
+        Some(compare) => Some(
+            processor::process(compare, &opts)
+                .with_context(|| format!("Failed to process '{}'", compare.display()))?,
+        ),
+        None => None,
+    };
+    let ks = match (&compared, cli.ks_test) {
+        (Some(other), true) => {
+            let result = ks::two_sample(&stats.values, &other.values);
+            if result.is_none() {
+                stats
+                    .warnings
+                    .push("KS test skipped: a distribution has no values after filtering.");
+            }
+            Some(result)
+        }
+        _ => None,
+    };
+    stats.warnings.emit(cli.abort_on_warning)?;
+
+    let sensors = report::select_sensors(&stats.per_sensor, cli.sort_by, cli.top_n);
+    let render = RenderOptions {
+        units: metadata.as_ref().filter(|_| cli.show_units),
+        distinct_values: cli.distinct_values,
+        target: cli.target.is_some(),
+        noise_floor: cli.noise_floor,
+        precision: cli.precision(),
+    };
+
+    if let Some(path) = &cli.sensor_output {
+        if cli.format == OutputFormat::Json {
+            let mut file = OutputFile::create(path, opts.write)?;
+            file.write_all(report::render_sensor_ndjson(&sensors).as_bytes())?;
+            file.finish()?;
+        } else {
+            report::write_sensor_csv(&sensors, path, opts.write)?;
+        }
+    }
+
+    let group_files = match &cli.group_output_dir {
+        Some(dir) => Some(report::write_group_files(
+            dir,
+            &stats.per_sensor.iter().collect::<Vec<_>>(),
+            cli.group_output_values.then_some(&stats.group_values),
+            cli.format == OutputFormat::Json,
+            opts.write,
+        )?),
+        None => None,
+    };
+
+    if !text && cli.format.is_text() {
+        return Ok(());
+    }
+
+    if cli.format == OutputFormat::Html {
+        let report = report::render_html(&stats, &sensors, &render);
+        print!("{}", report::finish(&report, !cli.no_trailing_newline));
+        return Ok(());
+    }
+    if cli.format == OutputFormat::Markdown {
+        let report = report::render_markdown(&stats, &sensors, &render);
+        print!("{}", report::finish(&report, !cli.no_trailing_newline));
+        return Ok(());
+    }
+    if cli.format == OutputFormat::Json {
+        let mut report = report::render_json(&stats, &sensors);
+        if cli.json_envelope {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_micros() as i64;
+            report = report::render_json_envelope(&report, &cli.json_config(), now);
+        }
+        print!("{}", report::finish(&report, !cli.no_trailing_newline));
+        return Ok(());
+    }
+
+    if cli.verbose && !sensors.is_empty() {
+        report::print_sensor_table(&sensors, &render);
+    }
+
+    if let Some(n) = cli.top_by_range {
+        let top = report::select_sensors(&stats.per_sensor, SortKey::Range, Some(n));
+        println!("{}", report::render_range_ranking(&top));
+    }
+
+    if !stats.percentiles.is_empty() {
+        println!(
+            "{}",
+            report::render_percentiles(&cli.percentiles, &stats.percentiles)
+        );
+    }
 
-    if !cli.input.exists() {
-        anyhow::bail!("Input file '{}' does not exist.", cli.input.display());
+    if let Some(result) = &ks {
+        println!("{}", report::render_ks(result.as_ref(), cli.alpha));
     }
-    if !cli.input.is_file() {
-        anyhow::bail!("'{}' is not a regular file.", cli.input.display());
+
+    if let Some(other) = &compared {
+        let rows = report::compare_sensors(&stats.per_sensor, &other.per_sensor, cli.join);
+        println!(
+            "{}",
+            report::render_comparison(&rows, cli.join, render.precision)
+        );
+    }
+
+    if !stats.value_buckets.is_empty() {
+        println!("{}", report::render_value_buckets(&stats.value_buckets));
+    }
+
+    if let Some(width) = cli.value_bin_width {
+        println!("{}", report::render_value_bins(&stats.value_bins, width));
+    }
+
+    if cli.threshold_sweep.is_some() {
+        println!(
+            "{}",
+            report::render_threshold_sweep(&stats.threshold_sweep, render.precision)
+        );
+    }
+
+    if cli.categorical {
+        println!("{}", report::render_categories(&stats.categories));
+    }
+
+    if cli.count_histogram {
+        let edges = cli
+            .count_edges
+            .as_deref()
+            .unwrap_or(&report::DEFAULT_COUNT_EDGES);
+        let buckets = report::count_histogram(&stats.per_sensor, edges);
+        println!("{}", report::render_count_histogram(&buckets));
+    }
+
+    if !stats.windows.is_empty() {
+        println!("{}", report::render_windows(&stats.windows));
+    }
+
+    if let Some(window) = cli.online_window {
+        println!("{}", report::render_online(&stats.online, window as usize));
     }
 
-    println!("Input file      : {}", cli.input.display());
-    println!("Filter threshold: {}", cli.filter_threshold);
-    println!("Threads (rayon) : {}", rayon::current_num_threads());
-    println!();
+    if cli.chunk_delimiter.is_some() {
+        println!("{}", report::render_chunks(&chunks));
+    }
 
-    let start = std::time::Instant::now();
+    if cli.flatline_duration.is_some() {
+        println!("{}", report::render_flatlines(&stats.flatlines));
+    }
 
-    let stats = processor::process(&cli.input, cli.filter_threshold, cli.verbose)
-        .with_context(|| format!("Failed to process file '{}'", cli.input.display()))?;
+    if cli.expected_interval.is_some() {
+        println!(
+            "{}",
+            report::render_coverage(&stats.coverage, cli.min_coverage, render.precision)
+        );
+    }
 
-    let elapsed = start.elapsed();
+    if cli.quality_score {
+        println!(
+            "{}",
+            report::render_quality(&stats.quality, cli.min_quality)
+        );
+    }
 
+    if let Some(pair) = &stats.pair_delta {
+        println!("{}", report::render_pair_delta(pair));
+    }
+
+    if let Some(resets) = &stats.clock_resets {
+        println!("{}", report::render_clock_resets(resets));
+    }
+
+    if let Some(drops) = &stats.value_drops {
+        println!("{}", report::render_value_drops(drops));
+    }
+
+    if let Some(timeline) = &stats.timeline {
+        println!("{}", report::render_timeline(timeline));
+    }
+
+    if stats.partial {
+        println!("PARTIAL: interrupted, so only the rows read before Ctrl-C are included");
+    }
     println!("Processing complete");
     println!("    Total rows read      : {}", stats.total_rows);
     println!("    Rows after filter    : {}", stats.filtered_rows);
     println!(
-        "    Rows removed         : {} ({:.2}%)",
+        "    Rows removed         : {} ({:.decimals$}%)",
         stats.total_rows - stats.filtered_rows,
         if stats.total_rows > 0 {
             (stats.total_rows - stats.filtered_rows) as f64 / stats.total_rows as f64 * 100.0
         } else {
             0.0
-        }
+        },
+        decimals = render.precision.percent
     );
 
+    if let Some(n) = cli.nth {
+        println!(
+            "    Rows kept by --nth   : {} of {} (every {n})",
+            stats.total_rows,
+            stats.total_rows + stats.rows_skipped_by_nth
+        );
+    }
+
+    if let (Some(outside), Some(roster)) = (stats.rows_outside_roster, &opts.roster) {
+        println!(
+            "    Rows not in roster   : {outside} (roster of {} sensors)",
+            roster.len()
+        );
+    }
+
+    if cli.skip_bad_rows {
+        println!("    Bad rows skipped     : {}", stats.bad_rows_skipped);
+        if cli.verbose {
+            for e in stats.row_errors.iter().take(5) {
+                println!("      line {:<14}: {}", e.line, e.error);
+            }
+        }
+    }
+
+    if cli.skip_empty_lines {
+        println!("    Blank lines skipped  : {}", stats.blank_lines_skipped);
+    }
+
+    if let (Some(path), Some((rows, cols))) = (&cli.pivot, stats.pivot_shape) {
+        println!(
+            "    Pivot written        : {} ({rows} timestamps x {cols} sensors)",
+            path.display()
+        );
+    }
+
+    if let (Some(path), Some((rows, cols))) = (&cli.coverage_matrix, stats.coverage_matrix_shape) {
+        println!(
+            "    Coverage matrix      : {} ({rows} sensors x {cols} buckets)",
+            path.display()
+        );
+    }
+
+    if cli.merge_duplicates.is_some() {
+        println!(
+            "    Duplicate groups     : {} merged",
+            stats.duplicate_groups_merged
+        );
+    }
+
+    if let Some(duplicates) = stats.cross_file_duplicates {
+        println!("    Cross-file duplicates: {duplicates} removed");
+    }
+
+    if let Some(collapsed) = stats.near_duplicates_collapsed {
+        println!("    Near-duplicates      : {collapsed} collapsed");
+    }
+
+    if let Some(collisions) = stats.same_timestamp_resolved {
+        println!("    Same timestamps      : {collisions} resolved");
+    }
+
+    if let (Some(path), Some(rows)) = (&cli.output, stats.output_rows) {
+        println!("    Rows written         : {rows} to {}", path.display());
+    }
+
+    if stats.percentile_spill_runs > 0 {
+        println!(
+            "    Percentile spills    : {} sorted runs",
+            stats.percentile_spill_runs
+        );
+    }
+
+    if let Some(glitches) = &stats.glitches {
+        let total: usize = glitches.iter().map(|g| g.dropped).sum();
+        println!("    Glitches dropped     : {total}");
+        for g in glitches {
+            println!("      {:<19}: {}", g.sensor_id, g.dropped);
+        }
+    }
+
+    if let Some(capped) = &stats.capped {
+        let total: usize = capped.iter().map(|c| c.dropped).sum();
+        println!("    Dropped by cap       : {total}");
+        for c in capped {
+            println!("      {:<19}: {}", c.sensor_id, c.dropped);
+        }
+    }
+
+    if let (Some(calibrated), Some(calibration)) = (&stats.calibrated, &opts.calibration) {
+        println!("    Calibrated sensors   : {}", calibrated.len());
+        for (sensor_id, rows) in calibrated {
+            let (scale, offset) = calibration.get(sensor_id).unwrap_or((1.0, 0.0));
+            println!("      {sensor_id:<19}: {rows} rows (x {scale} + {offset})");
+        }
+    }
+
+    if let Some(out_of_range) = stats.percent_out_of_range {
+        println!("    Outside 0-100%       : {out_of_range}");
+    }
+
+    if let Some(clamp) = &stats.clamp {
+        let label = if cli.clamp_report {
+            "Would be clamped"
+        } else {
+            "Values clamped"
+        };
+        println!(
+            "    {label:<21}: {} below, {} above",
+            clamp.below, clamp.above
+        );
+        if cli.clamp_report {
+            for c in &clamp.per_sensor {
+                println!(
+                    "      {:<19}: {} below, {} above",
+                    c.sensor_id, c.below, c.above
+                );
+            }
+        }
+    }
+
+    if let (Some(k), Some(outliers)) = (cli.iqr_filter, stats.iqr_outliers) {
+        let label = if cli.iqr_report {
+            "IQR outliers found"
+        } else {
+            "IQR outliers removed"
+        };
+        println!("    {label:<21}: {outliers} (K = {k})");
+    }
+
+    if let (Some(path), Some(found)) = (&cli.anomalies, stats.anomalies_found) {
+        println!("    Anomalies written    : {found} to {}", path.display());
+    }
+
+    if let (Some(dir), Some(files)) = (&cli.group_output_dir, group_files) {
+        println!("    Group files written  : {files} to {}", dir.display());
+    }
+
     match stats.average {
-        Some(avg) => println!("    Average value        : {:.6}", avg),
+        Some(avg) => println!(
+            "    Average value        : {avg:.decimals$}",
+            decimals = render.precision.average
+        ),
+        None if cli.categorical => println!("    Average value        : N/A (--categorical)"),
         None => println!("    Average value        : N/A (no rows passed the filter)"),
     }
+    if let Some(target) = cli.target {
+        match stats.target_deviation {
+            Some(d) => println!(
+                "    Target deviation     : MAD {:.decimals$}, RMSD {:.decimals$} (target {target})",
+                d.mad,
+                d.rmsd,
+                decimals = render.precision.average
+            ),
+            None => println!("    Target deviation     : N/A (no rows passed the filter)"),
+        }
+    }
 
-    println!("Wall-clock time : {:.4?}", elapsed);
+    // The last line of the text report, so it carries the trailing-newline setting.
+    let wall_clock = if timings.runs.len() > 1 {
+        format!(
+            "Wall-clock time : min {:.4?} / mean {:.4?} / max {:.4?} over {} runs",
+            timings.min(),
+            timings.mean(),
+            timings.max(),
+            timings.runs.len()
+        )
+    } else {
+        format!("Wall-clock time : {:.4?}", timings.max())
+    };
+    print!("{}", report::finish(&wall_clock, !cli.no_trailing_newline));
 
     Ok(())
 }
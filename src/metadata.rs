@@ -0,0 +1,112 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::processor::Record;
+
+/// Per-sensor attributes joined from a separate CSV keyed by `SensorID`.
+#[derive(Debug, Default)]
+pub struct SensorMetadata {
+    units: HashMap<String, String>,
+}
+
+impl SensorMetadata {
+    /// Reads a CSV with a `SensorID` column and an optional `Unit` column; other columns are ignored.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .with_context(|| format!("Cannot open metadata file '{}'", path.display()))?;
+
+        let headers = reader.headers()?.clone();
+        let column = |name: &str| headers.iter().position(|h| h == name);
+        let id_col = column("SensorID").with_context(|| {
+            format!("Metadata file '{}' has no SensorID column", path.display())
+        })?;
+        let unit_col = column("Unit");
+
+        let mut units = HashMap::new();
+        for row in reader.records() {
+            let row =
+                row.with_context(|| format!("Failed to read metadata file '{}'", path.display()))?;
+            if let Some(unit) = unit_col.and_then(|i| row.get(i)).filter(|u| !u.is_empty()) {
+                units.insert(row[id_col].to_string(), unit.to_string());
+            }
+        }
+
+        Ok(Self { units })
+    }
+
+    pub fn unit(&self, sensor_id: &str) -> Option<&str> {
+        self.units.get(sensor_id).map(String::as_str)
+    }
+}
+
+/// Per-sensor `--offsets` calibration: each value becomes `value * Scale + Offset`.
+#[derive(Debug, Default, Clone)]
+pub struct Calibration {
+    factors: HashMap<String, (f64, f64)>,
+}
+
+impl Calibration {
+    /// Reads a CSV with `SensorID` and `Offset` columns and an optional `Scale` column
+    /// (1 when absent or empty); other columns are ignored.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .with_context(|| format!("Cannot open offsets file '{}'", path.display()))?;
+
+        let headers = reader.headers()?.clone();
+        let column = |name: &str| headers.iter().position(|h| h == name);
+        let missing =
+            |name: &str| format!("Offsets file '{}' has no {name} column", path.display());
+        let id_col = column("SensorID").with_context(|| missing("SensorID"))?;
+        let offset_col = column("Offset").with_context(|| missing("Offset"))?;
+        let scale_col = column("Scale");
+
+        let mut factors = HashMap::new();
+        for (i, row) in reader.records().enumerate() {
+            let row =
+                row.with_context(|| format!("Failed to read offsets file '{}'", path.display()))?;
+            let number = |col: usize, default: f64| -> Result<f64> {
+                match row.get(col).unwrap_or("") {
+                    "" => Ok(default),
+                    field => field.parse().with_context(|| {
+                        format!(
+                            "Offsets file '{}', row {}: '{field}' is not a number",
+                            path.display(),
+                            i + 1
+                        )
+                    }),
+                }
+            };
+            let scale = scale_col.map_or(Ok(1.0), |c| number(c, 1.0))?;
+            let offset = number(offset_col, 0.0)?;
+            factors.insert(row[id_col].to_string(), (scale, offset));
+        }
+
+        Ok(Self { factors })
+    }
+
+    /// Calibrates the readings of listed sensors in place and returns how many rows of
+    /// each such sensor were changed, sorted by sensor ID.
+    pub fn apply(&self, records: &mut [Record]) -> Vec<(String, usize)> {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for r in records.iter_mut() {
+            if let Some((id, (scale, offset))) = self.factors.get_key_value(&r.sensor_id) {
+                r.value = r.value * scale + offset;
+                *counts.entry(id.as_str()).or_default() += 1;
+            }
+        }
+        counts
+            .into_iter()
+            .map(|(id, n)| (id.to_string(), n))
+            .collect()
+    }
+
+    /// (scale, offset) for `sensor_id`, if it is listed.
+    pub fn get(&self, sensor_id: &str) -> Option<(f64, f64)> {
+        self.factors.get(sensor_id).copied()
+    }
+}
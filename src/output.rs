@@ -0,0 +1,218 @@
+use anyhow::{Context, Result};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+pub const DEFAULT_WRITE_BUFFER: usize = 64 * 1024;
+pub const MIN_WRITE_BUFFER_KB: u64 = 4;
+
+/// Output path that means standard output.
+pub const STDOUT: &str = "-";
+
+#[derive(Debug, Clone, Copy)]
+pub struct WriteOptions {
+    pub buffer_size: usize,
+    /// Flush after every CSV row so a reader on the other end of a pipe sees it at once.
+    pub line_buffered: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            buffer_size: DEFAULT_WRITE_BUFFER,
+            line_buffered: false,
+        }
+    }
+}
+
+pub fn is_stdout(path: &Path) -> bool {
+    path == Path::new(STDOUT)
+}
+
+/// A buffered output file shared by every mode that writes files.
+///
+/// Data goes to a temporary file next to `path`, which only replaces `path` in `finish`,
+/// so readers never see a partially written file. Dropping it without `finish` discards it.
+/// The path `-` writes to standard output instead.
+pub struct OutputFile {
+    target: Target,
+}
+
+enum Target {
+    File {
+        inner: BufWriter<NamedTempFile>,
+        path: PathBuf,
+    },
+    Stdout(BufWriter<io::Stdout>),
+}
+
+impl OutputFile {
+    pub fn create(path: &Path, opts: WriteOptions) -> Result<Self> {
+        if is_stdout(path) {
+            return Ok(Self {
+                target: Target::Stdout(BufWriter::with_capacity(opts.buffer_size, io::stdout())),
+            });
+        }
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let prefix = format!(
+            ".{}.",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(&prefix).suffix(".tmp");
+        // Temp files are private by default; give the output the usual umask-based mode.
+        #[cfg(unix)]
+        builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o666));
+        let temp = builder
+            .tempfile_in(dir)
+            .with_context(|| format!("Cannot create output file '{}'", path.display()))?;
+        Ok(Self {
+            target: Target::File {
+                inner: BufWriter::with_capacity(opts.buffer_size, temp),
+                path: path.to_path_buf(),
+            },
+        })
+    }
+
+    /// Flushes the buffer and moves the file into place.
+    pub fn finish(self) -> Result<()> {
+        let (inner, path) = match self.target {
+            Target::File { inner, path } => (inner, path),
+            Target::Stdout(mut out) => return out.flush().context("Failed to flush stdout"),
+        };
+        let temp = inner
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .context("Failed to flush output file")?;
+        match temp.persist(&path) {
+            Ok(_) => Ok(()),
+            // The temp file lives next to `path`, but bind mounts can still put them on
+            // different filesystems; copy instead and let the temp file be removed on drop.
+            Err(e) if e.error.kind() == io::ErrorKind::CrossesDevices => {
+                std::fs::copy(e.file.path(), &path)
+                    .map(|_| ())
+                    .with_context(|| format!("Cannot write output file '{}'", path.display()))
+            }
+            Err(e) => Err(e.error)
+                .with_context(|| format!("Cannot write output file '{}'", path.display())),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.target {
+            Target::File { inner, .. } => inner.write(buf),
+            Target::Stdout(out) => out.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.target {
+            Target::File { inner, .. } => inner.flush(),
+            Target::Stdout(out) => out.flush(),
+        }
+    }
+}
+
+/// A CSV writer that honours [`WriteOptions::line_buffered`].
+pub struct CsvOutput<W: Write = OutputFile> {
+    writer: csv::Writer<W>,
+    line_buffered: bool,
+}
+
+impl<W: Write> CsvOutput<W> {
+    pub fn new(inner: W, line_buffered: bool) -> Self {
+        Self {
+            writer: csv::Writer::from_writer(inner),
+            line_buffered,
+        }
+    }
+
+    pub fn write_record<I, T>(&mut self, record: I) -> Result<()>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        self.writer.write_record(record)?;
+        if self.line_buffered {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn into_inner(self) -> Result<W> {
+        self.writer
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .context("Failed to flush output file")
+    }
+}
+
+impl CsvOutput<OutputFile> {
+    pub fn finish(self) -> Result<()> {
+        self.into_inner()?.finish()
+    }
+}
+
+pub fn csv_writer(path: &Path, opts: WriteOptions) -> Result<CsvOutput> {
+    Ok(CsvOutput::new(
+        OutputFile::create(path, opts)?,
+        opts.line_buffered,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_appears_only_after_finish() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.csv");
+        std::fs::write(&path, "old\n").unwrap();
+
+        let opts = WriteOptions {
+            buffer_size: 4096,
+            ..Default::default()
+        };
+        let mut file = OutputFile::create(&path, opts).unwrap();
+        file.write_all(&vec![b'x'; 10_000]).unwrap();
+        file.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old\n");
+        file.finish().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap().len(), 10_000);
+
+        let abandoned = OutputFile::create(&dir.path().join("never.csv"), WriteOptions::default());
+        drop(abandoned);
+        let names: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["out.csv"]);
+    }
+
+    #[test]
+    fn test_line_buffered_rows_reach_a_pipe_before_finish() {
+        let (mut reader, writer) = io::pipe().unwrap();
+        let mut out = CsvOutput::new(BufWriter::with_capacity(1 << 16, writer), true);
+        out.write_record(["Timestamp", "SensorID", "Value"])
+            .unwrap();
+
+        // Read on another thread so an unflushed row fails the test instead of hanging it.
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut buf = [0; 64];
+            let n = std::io::Read::read(&mut reader, &mut buf).unwrap();
+            tx.send(buf[..n].to_vec()).unwrap();
+        });
+        let first = rx
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .expect("row was not flushed");
+        assert_eq!(first, b"Timestamp,SensorID,Value\n");
+        drop(out.into_inner().unwrap());
+    }
+}
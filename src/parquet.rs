@@ -0,0 +1,793 @@
+//! `--format parquet`: the filtered rows as a Parquet file.
+//!
+//! The schema is fixed: `Timestamp` and `SensorID` as UTF-8 strings, exactly as read,
+//! and `Value` as a double, all required. Each row group holds one Snappy-compressed,
+//! PLAIN-encoded data page per column. The footer is Thrift compact protocol, written
+//! by hand for the handful of structs a reader needs.
+//!
+//! `--parquet-schema` reads the footer of any Parquet file back with a general Thrift
+//! compact decoder, without touching the row groups.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::output::{OutputFile, WriteOptions};
+use crate::processor::Record;
+use crate::snappy;
+
+const MAGIC: &[u8] = b"PAR1";
+
+/// Rows per row group; also keeps every page well below the 2 GB size limit.
+const ROW_GROUP_ROWS: usize = 1 << 20;
+
+// Enum values from parquet.thrift.
+const TYPE_DOUBLE: i32 = 5;
+const TYPE_BYTE_ARRAY: i32 = 6;
+const REQUIRED: i32 = 0;
+const CONVERTED_UTF8: i32 = 0;
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+const CODEC_SNAPPY: i32 = 1;
+const PAGE_DATA: i32 = 0;
+
+const COLUMNS: [(&str, i32); 3] = [
+    ("Timestamp", TYPE_BYTE_ARRAY),
+    ("SensorID", TYPE_BYTE_ARRAY),
+    ("Value", TYPE_DOUBLE),
+];
+
+/// Where one column chunk landed in the file.
+struct ChunkMeta {
+    offset: u64,
+    compressed: u64,
+    uncompressed: u64,
+}
+
+/// Writes `records` to `path` and returns how many rows were written.
+pub fn write_records(records: &[&Record], path: &Path, opts: WriteOptions) -> Result<usize> {
+    let mut file = OutputFile::create(path, opts)
+        .with_context(|| format!("Cannot create Parquet file '{}'", path.display()))?;
+    file.write_all(MAGIC)?;
+    let mut pos = MAGIC.len() as u64;
+
+    let mut row_groups = Vec::new();
+    for group in records.chunks(ROW_GROUP_ROWS) {
+        let mut chunks = Vec::with_capacity(COLUMNS.len());
+        for column in 0..COLUMNS.len() {
+            let values = plain_values(group, column);
+            let page = snappy::compress(&values);
+            let header = page_header(group.len(), values.len(), page.len());
+            file.write_all(&header)?;
+            file.write_all(&page)?;
+            chunks.push(ChunkMeta {
+                offset: pos,
+                compressed: (header.len() + page.len()) as u64,
+                uncompressed: (header.len() + values.len()) as u64,
+            });
+            pos += (header.len() + page.len()) as u64;
+        }
+        row_groups.push((group.len(), chunks));
+    }
+
+    let footer = file_metadata(records.len(), &row_groups);
+    file.write_all(&footer)?;
+    file.write_all(&(footer.len() as u32).to_le_bytes())?;
+    file.write_all(MAGIC)?;
+    file.finish()?;
+    Ok(records.len())
+}
+
+/// One column of `rows`, PLAIN-encoded. Required flat columns carry no level data.
+fn plain_values(rows: &[&Record], column: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    for r in rows {
+        let text = match column {
+            0 => &r.timestamp,
+            1 => &r.sensor_id,
+            _ => {
+                out.extend_from_slice(&r.value.to_le_bytes());
+                continue;
+            }
+        };
+        out.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        out.extend_from_slice(text.as_bytes());
+    }
+    out
+}
+
+fn page_header(rows: usize, uncompressed: usize, compressed: usize) -> Vec<u8> {
+    let mut t = Compact::new();
+    t.i32(1, PAGE_DATA);
+    t.i32(2, uncompressed as i32);
+    t.i32(3, compressed as i32);
+    t.begin_struct(5);
+    t.i32(1, rows as i32);
+    t.i32(2, ENCODING_PLAIN);
+    t.i32(3, ENCODING_RLE);
+    t.i32(4, ENCODING_RLE);
+    t.end_struct();
+    t.finish()
+}
+
+fn file_metadata(rows: usize, row_groups: &[(usize, Vec<ChunkMeta>)]) -> Vec<u8> {
+    let mut t = Compact::new();
+    t.i32(1, 1);
+
+    t.list(2, STRUCT, COLUMNS.len() + 1);
+    t.begin_element();
+    t.binary(4, b"schema");
+    t.i32(5, COLUMNS.len() as i32);
+    t.end_struct();
+    for (name, kind) in COLUMNS {
+        t.begin_element();
+        t.i32(1, kind);
+        t.i32(3, REQUIRED);
+        t.binary(4, name.as_bytes());
+        if kind == TYPE_BYTE_ARRAY {
+            t.i32(6, CONVERTED_UTF8);
+        }
+        t.end_struct();
+    }
+
+    t.i64(3, rows as i64);
+
+    t.list(4, STRUCT, row_groups.len());
+    for (group_rows, chunks) in row_groups {
+        t.begin_element();
+        t.list(1, STRUCT, chunks.len());
+        for ((name, kind), chunk) in COLUMNS.iter().zip(chunks) {
+            t.begin_element();
+            t.i64(2, chunk.offset as i64);
+            t.begin_struct(3);
+            t.i32(1, *kind);
+            t.list(2, I32, 2);
+            t.element_i32(ENCODING_PLAIN);
+            t.element_i32(ENCODING_RLE);
+            t.list(3, BINARY, 1);
+            t.element_binary(name.as_bytes());
+            t.i32(4, CODEC_SNAPPY);
+            t.i64(5, *group_rows as i64);
+            t.i64(6, chunk.uncompressed as i64);
+            t.i64(7, chunk.compressed as i64);
+            t.i64(9, chunk.offset as i64);
+            t.end_struct();
+            t.end_struct();
+        }
+        t.i64(2, chunks.iter().map(|c| c.uncompressed).sum::<u64>() as i64);
+        t.i64(3, *group_rows as i64);
+        t.end_struct();
+    }
+
+    let created_by = concat!(
+        env!("CARGO_PKG_NAME"),
+        " version ",
+        env!("CARGO_PKG_VERSION")
+    );
+    t.binary(6, created_by.as_bytes());
+    t.finish()
+}
+
+// Thrift compact protocol type codes.
+const BOOL_TRUE: u8 = 1;
+const BOOL_FALSE: u8 = 2;
+const BYTE: u8 = 3;
+const I16: u8 = 4;
+const I32: u8 = 5;
+const I64: u8 = 6;
+const BINARY: u8 = 8;
+const DOUBLE: u8 = 7;
+const LIST: u8 = 9;
+const SET: u8 = 10;
+const MAP: u8 = 11;
+const STRUCT: u8 = 12;
+
+/// Thrift compact protocol writer. Field IDs are delta-encoded against the previous
+/// field of the same struct, so each open struct keeps its last ID on a stack.
+struct Compact {
+    buf: Vec<u8>,
+    last_field: Vec<i16>,
+}
+
+impl Compact {
+    fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            last_field: vec![0],
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.buf.push(0);
+        self.buf
+    }
+
+    fn varint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.buf.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        self.buf.push(n as u8);
+    }
+
+    fn zigzag(&mut self, n: i64) {
+        self.varint(((n << 1) ^ (n >> 63)) as u64);
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last_field.last_mut().expect("inside a struct");
+        let delta = id - *last;
+        *last = id;
+        if (1..=15).contains(&delta) {
+            self.buf.push((delta as u8) << 4 | kind);
+        } else {
+            self.buf.push(kind);
+            self.zigzag(id.into());
+        }
+    }
+
+    fn i32(&mut self, id: i16, n: i32) {
+        self.field(id, I32);
+        self.zigzag(n.into());
+    }
+
+    fn i64(&mut self, id: i16, n: i64) {
+        self.field(id, I64);
+        self.zigzag(n);
+    }
+
+    fn binary(&mut self, id: i16, bytes: &[u8]) {
+        self.field(id, BINARY);
+        self.element_binary(bytes);
+    }
+
+    fn begin_struct(&mut self, id: i16) {
+        self.field(id, STRUCT);
+        self.begin_element();
+    }
+
+    fn end_struct(&mut self) {
+        self.buf.push(0);
+        self.last_field.pop();
+    }
+
+    fn list(&mut self, id: i16, kind: u8, len: usize) {
+        self.field(id, LIST);
+        if len < 15 {
+            self.buf.push((len as u8) << 4 | kind);
+        } else {
+            self.buf.push(0xf0 | kind);
+            self.varint(len as u64);
+        }
+    }
+
+    /// Starts a struct that is a list element; close it with [`Compact::end_struct`].
+    fn begin_element(&mut self) {
+        self.last_field.push(0);
+    }
+
+    fn element_i32(&mut self, n: i32) {
+        self.zigzag(n.into());
+    }
+
+    fn element_binary(&mut self, bytes: &[u8]) {
+        self.varint(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+}
+
+/// One column of a Parquet schema, in schema (depth-first) order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaColumn {
+    /// Nesting below the root: 0 for top-level columns.
+    pub depth: usize,
+    pub name: String,
+    /// Physical type, or `group` for a nested column's parent.
+    pub kind: String,
+    /// Converted or logical type, e.g. `UTF8` or `TIMESTAMP_MICROS`.
+    pub annotation: Option<String>,
+    pub repetition: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParquetSchema {
+    pub rows: i64,
+    pub row_groups: usize,
+    pub created_by: Option<String>,
+    pub columns: Vec<SchemaColumn>,
+}
+
+/// Reads the schema from the footer of the Parquet file at `path`: the last 8 bytes give
+/// the footer's length, and only the footer itself is read.
+pub fn read_schema(path: &Path) -> Result<ParquetSchema> {
+    let not_parquet = || format!("'{}' is not a Parquet file", path.display());
+    let mut file = File::open(path).with_context(|| format!("Cannot open '{}'", path.display()))?;
+    let len = file.metadata()?.len();
+    if len < 12 {
+        anyhow::bail!("{}: only {len} bytes", not_parquet());
+    }
+    let mut head = [0; 4];
+    file.read_exact(&mut head)?;
+    let mut tail = [0; 8];
+    file.seek(SeekFrom::End(-8))?;
+    file.read_exact(&mut tail)?;
+    if head != MAGIC || tail[4..] != *MAGIC {
+        anyhow::bail!("{}: no PAR1 magic", not_parquet());
+    }
+    let footer_len = u64::from(u32::from_le_bytes(tail[..4].try_into().unwrap()));
+    if footer_len + 12 > len {
+        anyhow::bail!(
+            "{}: footer length {footer_len} exceeds the file",
+            not_parquet()
+        );
+    }
+    let mut footer = vec![0; footer_len as usize];
+    file.seek(SeekFrom::End(-8 - footer_len as i64))?;
+    file.read_exact(&mut footer)?;
+
+    let meta = Reader::new(&footer)
+        .value(STRUCT, 0)
+        .with_context(|| format!("{}: unreadable footer", not_parquet()))?;
+    schema_from_metadata(&meta).with_context(not_parquet)
+}
+
+fn schema_from_metadata(meta: &Value) -> Result<ParquetSchema> {
+    let elements = meta
+        .field(2)
+        .and_then(Value::as_list)
+        .context("footer has no schema")?;
+    let (root, elements) = elements.split_first().context("schema is empty")?;
+
+    let mut columns = Vec::with_capacity(elements.len());
+    let mut remaining = vec![root.field(5).and_then(Value::as_int).unwrap_or(0)];
+    for e in elements {
+        while remaining.last() == Some(&0) {
+            remaining.pop();
+        }
+        let depth = remaining
+            .len()
+            .checked_sub(1)
+            .context("schema has more columns than its groups declare")?;
+        *remaining.last_mut().unwrap() -= 1;
+
+        let children = e.field(5).and_then(Value::as_int).unwrap_or(0);
+        let kind = match e.field(1).and_then(Value::as_int) {
+            _ if children > 0 => "group".to_string(),
+            Some(kind) => enum_name(&PHYSICAL_TYPES, kind),
+            None => "group".to_string(),
+        };
+        let annotation = match (e.field(6).and_then(Value::as_int), e.field(10)) {
+            (Some(converted), _) => Some(enum_name(&CONVERTED_TYPES, converted)),
+            (None, Some(Value::Struct(logical))) => logical
+                .keys()
+                .next()
+                .map(|&id| enum_name(&LOGICAL_TYPES, id.into())),
+            _ => None,
+        };
+        columns.push(SchemaColumn {
+            depth,
+            name: e.field(4).and_then(Value::as_string).unwrap_or_default(),
+            kind,
+            annotation,
+            repetition: e
+                .field(3)
+                .and_then(Value::as_int)
+                .map_or_else(|| "required".to_string(), |r| enum_name(&REPETITIONS, r)),
+        });
+        if children > 0 {
+            remaining.push(children);
+        }
+    }
+
+    Ok(ParquetSchema {
+        rows: meta.field(3).and_then(Value::as_int).unwrap_or(0),
+        row_groups: meta.field(4).and_then(Value::as_list).map_or(0, <[_]>::len),
+        created_by: meta.field(6).and_then(Value::as_string),
+        columns,
+    })
+}
+
+// Enum names from parquet.thrift, indexed by value.
+const PHYSICAL_TYPES: [&str; 8] = [
+    "BOOLEAN",
+    "INT32",
+    "INT64",
+    "INT96",
+    "FLOAT",
+    "DOUBLE",
+    "BYTE_ARRAY",
+    "FIXED_LEN_BYTE_ARRAY",
+];
+const REPETITIONS: [&str; 3] = ["required", "optional", "repeated"];
+const CONVERTED_TYPES: [&str; 22] = [
+    "UTF8",
+    "MAP",
+    "MAP_KEY_VALUE",
+    "LIST",
+    "ENUM",
+    "DECIMAL",
+    "DATE",
+    "TIME_MILLIS",
+    "TIME_MICROS",
+    "TIMESTAMP_MILLIS",
+    "TIMESTAMP_MICROS",
+    "UINT_8",
+    "UINT_16",
+    "UINT_32",
+    "UINT_64",
+    "INT_8",
+    "INT_16",
+    "INT_32",
+    "INT_64",
+    "JSON",
+    "BSON",
+    "INTERVAL",
+];
+/// The LogicalType union, by field ID (1-based; 9 is unused).
+const LOGICAL_TYPES: [&str; 17] = [
+    "",
+    "STRING",
+    "MAP",
+    "LIST",
+    "ENUM",
+    "DECIMAL",
+    "DATE",
+    "TIME",
+    "TIMESTAMP",
+    "",
+    "INTEGER",
+    "NULL",
+    "JSON",
+    "BSON",
+    "UUID",
+    "FLOAT16",
+    "VARIANT",
+];
+
+fn enum_name(names: &[&str], value: i64) -> String {
+    usize::try_from(value)
+        .ok()
+        .and_then(|i| names.get(i))
+        .filter(|name| !name.is_empty())
+        .map_or_else(|| format!("UNKNOWN({value})"), |name| name.to_string())
+}
+
+/// A decoded Thrift compact value.
+#[derive(Debug)]
+enum Value {
+    Int(i64),
+    Binary(Vec<u8>),
+    List(Vec<Value>),
+    Struct(BTreeMap<i16, Value>),
+    /// A bool, double or map: read past, since no footer field the schema needs has them.
+    Other,
+}
+
+impl Value {
+    fn field(&self, id: i16) -> Option<&Value> {
+        match self {
+            Value::Struct(fields) => fields.get(&id),
+            _ => None,
+        }
+    }
+
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn as_string(&self) -> Option<String> {
+        match self {
+            Value::Binary(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+            _ => None,
+        }
+    }
+
+    fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Structs nested deeper than this are rejected rather than recursed into.
+const MAX_DEPTH: usize = 64;
+
+/// Thrift compact protocol reader over an in-memory buffer.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos.saturating_add(n))
+            .context("Thrift data ends early")?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut n = 0;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            n |= u64::from(b & 0x7f) << shift;
+            if b < 0x80 {
+                return Ok(n);
+            }
+        }
+        anyhow::bail!("Thrift varint is too long")
+    }
+
+    fn zigzag(&mut self) -> Result<i64> {
+        let n = self.varint()?;
+        Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        let len = self.varint()? as usize;
+        if len > self.bytes.len() - self.pos {
+            anyhow::bail!("Thrift length {len} exceeds the data");
+        }
+        Ok(len)
+    }
+
+    /// Reads a value of `kind`; a bool read this way is a list, set or map element.
+    fn value(&mut self, kind: u8, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            anyhow::bail!("Thrift structs nested more than {MAX_DEPTH} deep");
+        }
+        Ok(match kind {
+            BOOL_TRUE | BOOL_FALSE => {
+                self.byte()?;
+                Value::Other
+            }
+            BYTE => Value::Int(i64::from(self.byte()? as i8)),
+            I16 | I32 | I64 => Value::Int(self.zigzag()?),
+            DOUBLE => {
+                self.take(8)?;
+                Value::Other
+            }
+            BINARY => {
+                let len = self.len()?;
+                Value::Binary(self.take(len)?.to_vec())
+            }
+            LIST | SET => {
+                let header = self.byte()?;
+                let len = match header >> 4 {
+                    15 => self.len()?,
+                    n => n as usize,
+                };
+                let items = (0..len)
+                    .map(|_| self.value(header & 0x0f, depth + 1))
+                    .collect::<Result<_>>()?;
+                Value::List(items)
+            }
+            MAP => {
+                let len = self.len()?;
+                let types = if len > 0 { self.byte()? } else { 0 };
+                for _ in 0..len {
+                    self.value(types >> 4, depth + 1)?;
+                    self.value(types & 0x0f, depth + 1)?;
+                }
+                Value::Other
+            }
+            STRUCT => {
+                let mut fields = BTreeMap::new();
+                let mut last = 0i16;
+                loop {
+                    let header = self.byte()?;
+                    if header == 0 {
+                        break Value::Struct(fields);
+                    }
+                    last = match header >> 4 {
+                        0 => self.zigzag()? as i16,
+                        delta => last.wrapping_add(i16::from(delta)),
+                    };
+                    let value = match header & 0x0f {
+                        // A bool field's value is its type code, with nothing after it.
+                        BOOL_TRUE | BOOL_FALSE => Value::Other,
+                        kind => self.value(kind, depth + 1)?,
+                    };
+                    fields.insert(last, value);
+                }
+            }
+            _ => anyhow::bail!("unknown Thrift type {kind}"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Value {
+        fn get(&self, id: i16) -> &Value {
+            self.field(id)
+                .unwrap_or_else(|| panic!("no field {id} in {self:?}"))
+        }
+
+        fn int(&self) -> i64 {
+            self.as_int()
+                .unwrap_or_else(|| panic!("not an integer: {self:?}"))
+        }
+
+        fn list(&self) -> &[Value] {
+            self.as_list()
+                .unwrap_or_else(|| panic!("not a list: {self:?}"))
+        }
+    }
+
+    fn record(timestamp: &str, sensor_id: &str, value: f64) -> Record {
+        Record {
+            timestamp: timestamp.to_string(),
+            sensor_id: sensor_id.to_string(),
+            value,
+            time: None,
+        }
+    }
+
+    #[test]
+    fn test_written_file_reads_back() {
+        let records: Vec<Record> = (0..1_000)
+            .map(|i| {
+                record(
+                    &format!("2024-01-01T00:{:02}:{:02}", i / 60 % 60, i % 60),
+                    &format!("S{}", i % 3),
+                    i as f64 * 0.5,
+                )
+            })
+            .collect();
+        let refs: Vec<&Record> = records.iter().collect();
+        let out = tempfile::NamedTempFile::new().unwrap();
+        assert_eq!(
+            write_records(&refs, out.path(), WriteOptions::default()).unwrap(),
+            1_000
+        );
+
+        let bytes = std::fs::read(out.path()).unwrap();
+        assert!(bytes.starts_with(MAGIC) && bytes.ends_with(MAGIC));
+        let footer_len =
+            u32::from_le_bytes(bytes[bytes.len() - 8..bytes.len() - 4].try_into().unwrap());
+        let mut reader = Reader {
+            bytes: &bytes,
+            pos: bytes.len() - 8 - footer_len as usize,
+        };
+        let meta = reader.value(STRUCT, 0).unwrap();
+        assert_eq!(meta.get(3).int(), 1_000);
+
+        let schema = meta.get(2).list();
+        let names: Vec<_> = schema
+            .iter()
+            .map(|s| match s.get(4) {
+                Value::Binary(name) => String::from_utf8(name.clone()).unwrap(),
+                other => panic!("{other:?}"),
+            })
+            .collect();
+        assert_eq!(names, ["schema", "Timestamp", "SensorID", "Value"]);
+
+        let chunks = meta.get(4).list()[0].get(1).list();
+        let mut columns = Vec::new();
+        for chunk in chunks {
+            let chunk_meta = chunk.get(3);
+            assert_eq!(chunk_meta.get(4).int(), i64::from(CODEC_SNAPPY));
+            let mut reader = Reader {
+                bytes: &bytes,
+                pos: chunk_meta.get(9).int() as usize,
+            };
+            let header = reader.value(STRUCT, 0).unwrap();
+            assert_eq!(header.get(5).get(1).int(), 1_000);
+            let page = &bytes[reader.pos..reader.pos + header.get(3).int() as usize];
+            let values = snappy::decompress(page);
+            assert_eq!(values.len() as i64, header.get(2).int());
+            columns.push(values);
+        }
+
+        let doubles: Vec<f64> = columns[2]
+            .chunks(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(doubles.len(), 1_000);
+        assert_eq!(doubles[999], 499.5);
+
+        let mut strings = Vec::new();
+        let mut rest = &columns[1][..];
+        while !rest.is_empty() {
+            let len = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+            strings.push(std::str::from_utf8(&rest[4..4 + len]).unwrap());
+            rest = &rest[4 + len..];
+        }
+        assert_eq!(strings.len(), 1_000);
+        assert_eq!(&strings[..4], ["S0", "S1", "S2", "S0"]);
+    }
+
+    #[test]
+    fn test_schema_read_from_footer() {
+        let records = [record("2024-01-01T00:00:00", "S1", 1.5)];
+        let refs: Vec<&Record> = records.iter().collect();
+        let out = tempfile::NamedTempFile::new().unwrap();
+        write_records(&refs, out.path(), WriteOptions::default()).unwrap();
+
+        let schema = read_schema(out.path()).unwrap();
+        assert_eq!((schema.rows, schema.row_groups), (1, 1));
+        let printed = crate::report::render_parquet_schema("rows.parquet", &schema);
+        for line in [
+            "  Timestamp                    BYTE_ARRAY           UTF8             required",
+            "  SensorID                     BYTE_ARRAY           UTF8             required",
+            "  Value                        DOUBLE               -                required",
+        ] {
+            assert!(printed.contains(line), "{printed}");
+        }
+
+        // A nested, optional schema with a logical type, as other writers produce.
+        let mut t = Compact::new();
+        t.list(2, STRUCT, 4);
+        t.begin_element();
+        t.binary(4, b"root");
+        t.i32(5, 2);
+        t.end_struct();
+        t.begin_element();
+        t.binary(4, b"location");
+        t.i32(5, 1);
+        t.end_struct();
+        t.begin_element();
+        t.i32(1, 2);
+        t.i32(3, 1);
+        t.binary(4, b"time");
+        t.begin_struct(10);
+        t.begin_struct(8);
+        t.field(1, BOOL_TRUE);
+        t.end_struct();
+        t.end_struct();
+        t.end_struct();
+        t.begin_element();
+        t.i32(1, 5);
+        t.i32(3, 1);
+        t.binary(4, b"reading");
+        t.end_struct();
+        let footer = t.finish();
+
+        let meta = Reader::new(&footer).value(STRUCT, 0).unwrap();
+        let schema = schema_from_metadata(&meta).unwrap();
+        let columns: Vec<_> = schema
+            .columns
+            .iter()
+            .map(|c| {
+                (
+                    c.depth,
+                    c.name.as_str(),
+                    c.kind.as_str(),
+                    c.annotation.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            columns,
+            [
+                (0, "location", "group", None),
+                (1, "time", "INT64", Some("TIMESTAMP")),
+                (0, "reading", "DOUBLE", None),
+            ]
+        );
+        assert_eq!(schema.columns[2].repetition, "optional");
+
+        std::fs::write(out.path(), b"Timestamp,SensorID,Value\n").unwrap();
+        let err = read_schema(out.path()).unwrap_err().to_string();
+        assert!(err.contains("is not a Parquet file"), "{err}");
+    }
+}
@@ -0,0 +1,280 @@
+use anyhow::{Context, Result};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+/// Approximate bytes retained per buffered value (the f64 plus `Vec` growth slack).
+const BYTES_PER_VALUE: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct SensorPercentiles {
+    pub sensor_id: String,
+    /// One value per requested percentile, in request order.
+    pub values: Vec<f64>,
+}
+
+/// Collects per-sensor values for percentile computation. With a memory budget, buffered
+/// values are written out as sorted runs whenever the budget is exceeded and merged back
+/// when computing, so memory stays bounded by the budget plus one count per sensor.
+pub struct ValueStore {
+    budget: Option<usize>,
+    buffered: HashMap<String, Vec<f64>>,
+    buffered_bytes: usize,
+    counts: HashMap<String, usize>,
+    runs: Vec<File>,
+}
+
+impl ValueStore {
+    pub fn new(budget: Option<usize>) -> Self {
+        Self {
+            budget,
+            buffered: HashMap::new(),
+            buffered_bytes: 0,
+            counts: HashMap::new(),
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, sensor_id: &str, value: f64) -> Result<()> {
+        match self.buffered.get_mut(sensor_id) {
+            Some(values) => values.push(value),
+            None => {
+                self.buffered_bytes += sensor_id.len();
+                self.buffered.insert(sensor_id.to_string(), vec![value]);
+            }
+        }
+        self.buffered_bytes += BYTES_PER_VALUE;
+        if self.budget.is_some_and(|b| self.buffered_bytes > b) {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    pub fn spilled_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        let mut run = BufWriter::new(tempfile::tempfile().context("Cannot create spill file")?);
+        let mut sensors: Vec<_> = self.buffered.drain().collect();
+        sensors.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        for (sensor_id, mut values) in sensors {
+            values.sort_unstable_by(f64::total_cmp);
+            *self.counts.entry(sensor_id.clone()).or_default() += values.len();
+            for value in values {
+                write_entry(&mut run, &sensor_id, value)?;
+            }
+        }
+        let mut file = run
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .context("Failed to write spill file")?;
+        file.seek(SeekFrom::Start(0))?;
+        self.runs.push(file);
+        self.buffered_bytes = 0;
+        Ok(())
+    }
+
+    /// Computes the requested percentiles (0-100) for every sensor, sorted by sensor ID.
+    pub fn percentiles(mut self, ps: &[f64]) -> Result<Vec<SensorPercentiles>> {
+        if self.runs.is_empty() {
+            let sorted: BTreeMap<_, _> = self.buffered.into_iter().collect();
+            return Ok(sorted
+                .into_iter()
+                .map(|(sensor_id, mut values)| {
+                    values.sort_unstable_by(f64::total_cmp);
+                    let values = from_sorted(values.iter().copied(), values.len(), ps);
+                    SensorPercentiles { sensor_id, values }
+                })
+                .collect());
+        }
+
+        if !self.buffered.is_empty() {
+            self.spill()?;
+        }
+        let mut merge = Merge::new(self.runs)?;
+        let mut sensors: Vec<_> = self.counts.into_iter().collect();
+        sensors.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        sensors
+            .into_iter()
+            .map(|(sensor_id, count)| {
+                let values = from_sorted(
+                    std::iter::from_fn(|| merge.next_value()).take(count),
+                    count,
+                    ps,
+                );
+                merge.error.take().map_or(Ok(()), Err)?;
+                Ok(SensorPercentiles { sensor_id, values })
+            })
+            .collect()
+    }
+}
+
+/// Percentiles (0-100) of `values`, interpolated like [`ValueStore::percentiles`].
+pub fn of_values(mut values: Vec<f64>, ps: &[f64]) -> Vec<f64> {
+    values.sort_unstable_by(f64::total_cmp);
+    let n = values.len();
+    from_sorted(values.into_iter(), n, ps)
+}
+
+/// Linear interpolation between closest ranks over `n` ascending values.
+fn from_sorted(values: impl Iterator<Item = f64>, n: usize, ps: &[f64]) -> Vec<f64> {
+    let ranks: Vec<f64> = ps.iter().map(|p| p / 100.0 * (n - 1) as f64).collect();
+    let mut needed: Vec<usize> = ranks
+        .iter()
+        .flat_map(|r| [r.floor() as usize, r.ceil() as usize])
+        .collect();
+    needed.sort_unstable();
+    needed.dedup();
+
+    let mut picked = HashMap::new();
+    let mut next = needed.iter().peekable();
+    // Consume every value even after the last needed rank: the merged stream is shared
+    // between sensors.
+    for (i, value) in values.enumerate() {
+        if next.next_if(|&&idx| idx == i).is_some() {
+            picked.insert(i, value);
+        }
+    }
+
+    ranks
+        .iter()
+        .map(|r| {
+            let lo = picked[&(r.floor() as usize)];
+            let hi = picked[&(r.ceil() as usize)];
+            lo + (hi - lo) * r.fract()
+        })
+        .collect()
+}
+
+fn write_entry(out: &mut impl Write, sensor_id: &str, value: f64) -> Result<()> {
+    out.write_all(&(sensor_id.len() as u32).to_le_bytes())?;
+    out.write_all(sensor_id.as_bytes())?;
+    out.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn read_entry(input: &mut impl Read) -> Result<Option<Entry>> {
+    let mut len = [0; 4];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let mut sensor_id = vec![0; u32::from_le_bytes(len) as usize];
+    input.read_exact(&mut sensor_id)?;
+    let mut value = [0; 8];
+    input.read_exact(&mut value)?;
+    Ok(Some(Entry {
+        sensor_id: String::from_utf8(sensor_id).context("Corrupt spill file")?,
+        value: f64::from_le_bytes(value),
+        run: 0,
+    }))
+}
+
+struct Entry {
+    sensor_id: String,
+    value: f64,
+    run: usize,
+}
+
+// Reversed so `BinaryHeap` pops the smallest (sensor, value) first.
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .sensor_id
+            .cmp(&self.sensor_id)
+            .then_with(|| other.value.total_cmp(&self.value))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+/// k-way merge of sorted runs, yielding values in (sensor, value) order.
+struct Merge {
+    readers: Vec<BufReader<File>>,
+    heap: BinaryHeap<Entry>,
+    error: Option<anyhow::Error>,
+}
+
+impl Merge {
+    fn new(runs: Vec<File>) -> Result<Self> {
+        let mut readers: Vec<_> = runs.into_iter().map(BufReader::new).collect();
+        let mut heap = BinaryHeap::new();
+        for (run, reader) in readers.iter_mut().enumerate() {
+            if let Some(entry) = read_entry(reader)? {
+                heap.push(Entry { run, ..entry });
+            }
+        }
+        Ok(Self {
+            readers,
+            heap,
+            error: None,
+        })
+    }
+
+    fn next_value(&mut self) -> Option<f64> {
+        let entry = self.heap.pop()?;
+        match read_entry(&mut self.readers[entry.run]) {
+            Ok(Some(next)) => self.heap.push(Entry {
+                run: entry.run,
+                ..next
+            }),
+            Ok(None) => {}
+            Err(e) => self.error = Some(e),
+        }
+        Some(entry.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compute(budget: Option<usize>) -> (Vec<SensorPercentiles>, usize) {
+        let mut store = ValueStore::new(budget);
+        for i in 0..500 {
+            let value = ((i * 7919) % 500) as f64 / 4.0;
+            store.push(["S2", "S1", "S3"][i % 3], value).unwrap();
+        }
+        let runs = store.spilled_runs();
+        (
+            store.percentiles(&[0.0, 50.0, 90.0, 99.0, 100.0]).unwrap(),
+            runs,
+        )
+    }
+
+    #[test]
+    fn test_spilled_percentiles_match_in_memory() {
+        let (in_memory, runs) = compute(None);
+        assert_eq!(runs, 0);
+        let (spilled, runs) = compute(Some(256));
+        assert!(runs > 10, "expected many runs, got {runs}");
+
+        assert_eq!(in_memory.len(), 3);
+        for (a, b) in in_memory.iter().zip(&spilled) {
+            assert_eq!(a.sensor_id, b.sensor_id);
+            assert_eq!(a.values, b.values);
+        }
+    }
+
+    #[test]
+    fn test_interpolates_between_ranks() {
+        let values = [1.0, 2.0, 3.0, 4.0];
+        let ps = from_sorted(values.iter().copied(), 4, &[0.0, 50.0, 90.0, 100.0]);
+        assert_eq!(ps, [1.0, 2.5, 3.7, 4.0]);
+    }
+}
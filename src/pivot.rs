@@ -0,0 +1,258 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::output::{self, WriteOptions};
+use crate::processor::Record;
+use crate::timestamp;
+
+/// Sensors beyond this many columns need `--force`; wider files are rarely what was meant.
+pub const MAX_PIVOT_COLUMNS: usize = 1_000;
+
+#[derive(Debug)]
+pub struct PivotTable {
+    pub sensors: Vec<String>,
+    pub rows: Vec<(i64, Vec<Option<f64>>)>,
+}
+
+/// Reshapes long-format records into one row per timestamp and one column per sensor.
+/// Several readings of a sensor at the same timestamp are averaged into one cell.
+pub fn build<'a>(records: impl Iterator<Item = &'a Record>) -> PivotTable {
+    let mut cells: BTreeMap<i64, HashMap<&'a str, (f64, usize)>> = BTreeMap::new();
+    let mut sensors = BTreeSet::new();
+
+    for r in records {
+        let time = r.time.expect("timestamps are parsed for --pivot");
+        let cell = cells
+            .entry(time)
+            .or_default()
+            .entry(r.sensor_id.as_str())
+            .or_insert((0.0, 0));
+        cell.0 += r.value;
+        cell.1 += 1;
+        sensors.insert(r.sensor_id.as_str());
+    }
+
+    let rows = cells
+        .into_iter()
+        .map(|(time, by_sensor)| {
+            let values = sensors
+                .iter()
+                .map(|s| by_sensor.get(s).map(|(sum, n)| sum / *n as f64))
+                .collect();
+            (time, values)
+        })
+        .collect();
+
+    PivotTable {
+        sensors: sensors.into_iter().map(str::to_string).collect(),
+        rows,
+    }
+}
+
+impl PivotTable {
+    /// Carries each sensor's last value down into at most `limit` empty cells after it.
+    /// Cells before a sensor's first reading stay empty.
+    pub fn forward_fill(&mut self, limit: usize) {
+        for col in 0..self.sensors.len() {
+            let mut last = None;
+            let mut carried = 0;
+            for (_, values) in &mut self.rows {
+                match values[col] {
+                    Some(v) => {
+                        last = Some(v);
+                        carried = 0;
+                    }
+                    None if carried < limit => {
+                        values[col] = last;
+                        carried += 1;
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+}
+
+pub fn write(table: &PivotTable, path: &Path, opts: WriteOptions) -> Result<()> {
+    let mut writer = output::csv_writer(path, opts)
+        .with_context(|| format!("Cannot create pivot file '{}'", path.display()))?;
+
+    let mut header = vec!["Timestamp"];
+    header.extend(table.sensors.iter().map(String::as_str));
+    writer.write_record(&header)?;
+
+    for (time, values) in &table.rows {
+        let mut row = vec![timestamp::format_iso8601(*time)];
+        row.extend(
+            values
+                .iter()
+                .map(|v| v.map(|v| v.to_string()).unwrap_or_default()),
+        );
+        writer.write_record(&row)?;
+    }
+
+    writer.finish()
+}
+
+/// Buckets beyond this many columns need `--force`, as for the pivot's sensor columns.
+pub const MAX_MATRIX_BUCKETS: usize = 1_000;
+
+/// Which sensors have readings in which `--resample` buckets, for `--coverage-matrix`.
+#[derive(Debug)]
+pub struct PresenceMatrix {
+    /// Bucket starts, every bucket from the first reading's to the last's.
+    pub buckets: Vec<i64>,
+    /// One row per sensor, sorted by ID.
+    pub sensors: Vec<(String, Vec<bool>)>,
+}
+
+/// Number of `step`-wide buckets, aligned to the epoch, from the bucket of the earliest
+/// reading to that of the latest; 0 without readings.
+pub fn matrix_bucket_count(records: &[Record], step: i64) -> usize {
+    let times = records.iter().filter_map(|r| r.time);
+    match (times.clone().min(), times.max()) {
+        (Some(first), Some(last)) => (last.div_euclid(step) - first.div_euclid(step)) as usize + 1,
+        _ => 0,
+    }
+}
+
+pub fn presence_matrix(records: &[Record], step: i64) -> PresenceMatrix {
+    let count = matrix_bucket_count(records, step);
+    let first = records
+        .iter()
+        .filter_map(|r| r.time)
+        .min()
+        .map_or(0, |t| t.div_euclid(step));
+
+    let mut sensors: BTreeMap<&str, Vec<bool>> = BTreeMap::new();
+    for r in records {
+        let time = r.time.expect("timestamps are parsed for --coverage-matrix");
+        sensors
+            .entry(r.sensor_id.as_str())
+            .or_insert_with(|| vec![false; count])[(time.div_euclid(step) - first) as usize] = true;
+    }
+
+    PresenceMatrix {
+        buckets: (0..count as i64).map(|i| (first + i) * step).collect(),
+        sensors: sensors
+            .into_iter()
+            .map(|(id, present)| (id.to_string(), present))
+            .collect(),
+    }
+}
+
+/// Writes one row per sensor and one 0/1 column per bucket, headed by its start.
+pub fn write_presence(matrix: &PresenceMatrix, path: &Path, opts: WriteOptions) -> Result<()> {
+    let mut writer = output::csv_writer(path, opts)
+        .with_context(|| format!("Cannot create coverage matrix '{}'", path.display()))?;
+
+    let mut header = vec!["SensorID".to_string()];
+    header.extend(matrix.buckets.iter().map(|&t| timestamp::format_iso8601(t)));
+    writer.write_record(&header)?;
+
+    for (sensor_id, present) in &matrix.sensors {
+        let mut row = vec![sensor_id.as_str()];
+        row.extend(present.iter().map(|&p| if p { "1" } else { "0" }));
+        writer.write_record(&row)?;
+    }
+
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(ts: &str, sensor: &str, value: f64) -> Record {
+        Record {
+            timestamp: ts.to_string(),
+            sensor_id: sensor.to_string(),
+            value,
+            time: Some(timestamp::parse_iso8601(ts).unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_pivot_two_sensors_two_timestamps() {
+        let records = [
+            record("2024-01-01T00:00:01", "S2", 5.0),
+            record("2024-01-01T00:00:00", "S1", 1.0),
+            record("2024-01-01T00:00:00", "S2", 2.0),
+            record("2024-01-01T00:00:01", "S1", 3.0),
+            record("2024-01-01T00:00:01", "S1", 4.0),
+        ];
+        let table = build(records.iter());
+
+        assert_eq!(table.sensors, ["S1", "S2"]);
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(table.rows[0].1, [Some(1.0), Some(2.0)]);
+        assert_eq!(table.rows[1].1, [Some(3.5), Some(5.0)]);
+
+        let out = tempfile::NamedTempFile::new().unwrap();
+        write(&table, out.path(), WriteOptions::default()).unwrap();
+        let written = std::fs::read_to_string(out.path()).unwrap();
+        assert_eq!(
+            written,
+            "Timestamp,S1,S2\n2024-01-01T00:00:00,1,2\n2024-01-01T00:00:01,3.5,5\n"
+        );
+    }
+
+    #[test]
+    fn test_presence_matrix_two_sensors_two_buckets() {
+        let records = [
+            record("2024-01-01T00:00:10", "S2", 1.0),
+            record("2024-01-01T00:00:20", "S1", 1.0),
+            record("2024-01-01T00:00:50", "S1", 1.0),
+            record("2024-01-01T00:01:30", "S1", 1.0),
+        ];
+        let step = timestamp::parse_duration("1m").unwrap();
+        let matrix = presence_matrix(&records, step);
+
+        assert_eq!(matrix.buckets.len(), 2);
+        assert_eq!(
+            matrix.sensors,
+            [
+                ("S1".to_string(), vec![true, true]),
+                ("S2".to_string(), vec![true, false]),
+            ]
+        );
+
+        let out = tempfile::NamedTempFile::new().unwrap();
+        write_presence(&matrix, out.path(), WriteOptions::default()).unwrap();
+        let written = std::fs::read_to_string(out.path()).unwrap();
+        assert_eq!(
+            written,
+            "SensorID,2024-01-01T00:00:00,2024-01-01T00:01:00\nS1,1,1\nS2,1,0\n"
+        );
+    }
+
+    #[test]
+    fn test_missing_cells_are_blank() {
+        let records = [
+            record("2024-01-01T00:00:00", "S1", 1.0),
+            record("2024-01-01T00:00:01", "S2", 2.0),
+        ];
+        let table = build(records.iter());
+
+        assert_eq!(table.rows[0].1, [Some(1.0), None]);
+        assert_eq!(table.rows[1].1, [None, Some(2.0)]);
+    }
+
+    #[test]
+    fn test_forward_fill_carries_up_to_limit() {
+        let records = [
+            record("2024-01-01T00:00:00", "S1", 1.0),
+            record("2024-01-01T00:00:01", "S2", 2.0),
+            record("2024-01-01T00:00:02", "S2", 3.0),
+            record("2024-01-01T00:00:03", "S2", 4.0),
+        ];
+        let mut table = build(records.iter());
+        table.forward_fill(2);
+
+        let s1: Vec<Option<f64>> = table.rows.iter().map(|(_, v)| v[0]).collect();
+        let s2: Vec<Option<f64>> = table.rows.iter().map(|(_, v)| v[1]).collect();
+        assert_eq!(s1, [Some(1.0), Some(1.0), Some(1.0), None]);
+        assert_eq!(s2, [None, Some(2.0), Some(3.0), Some(4.0)]);
+    }
+}
@@ -2,12 +2,38 @@ use anyhow::{Context, Result};
 use csv::ReaderBuilder;
 use rayon::prelude::*;
 use serde::Deserialize;
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use crate::buckets::{self, BucketScope, CategoryCounts, SweepRow, ValueBin, ValueBuckets};
+use crate::dedup::{self, MergePolicy, SameTimestamp};
+use crate::expr::{BoundExpr, Expr};
+use crate::fastfloat;
+use crate::input::{self, Chunks, SkipBlankLines};
+use crate::json;
+use crate::metadata::Calibration;
+use crate::output::{self, OutputFile, WriteOptions};
+use crate::parquet;
+use crate::percentile::{SensorPercentiles, ValueStore};
+use crate::pivot;
+use crate::regex::Regex;
+use crate::resample::{self, WindowStats};
+use crate::series::{
+    self, ClockReset, Coverage, DroppedCount, Flatline, OnlineStats, PairDelta, QualityScore,
+    QualityWeights, Timeline, Transform, ValueDrop,
+};
+use crate::signals::{self, Progress};
+use crate::timestamp::{self, TimeUnit, TimestampFormat};
+use crate::tz::Zone;
+use crate::warnings::Warnings;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Record {
     #[serde(rename = "Timestamp")]
-    #[allow(dead_code)]
     pub timestamp: String,
 
     #[serde(rename = "SensorID")]
@@ -15,52 +41,750 @@ pub struct Record {
 
     #[serde(rename = "Value")]
     pub value: f64,
+
+    /// Microseconds since the Unix epoch, only parsed when a time-based option needs it.
+    #[serde(skip)]
+    pub time: Option<i64>,
+}
+
+/// A row whose value is a category name, for `--categorical`.
+#[derive(Deserialize)]
+struct CategoryRecord {
+    #[serde(rename = "SensorID")]
+    sensor_id: String,
+
+    #[serde(rename = "Value")]
+    value: String,
+}
+
+/// The non-value columns of a row, for `--value-expr`.
+#[derive(Deserialize)]
+struct RowKeys {
+    #[serde(rename = "Timestamp")]
+    timestamp: String,
+
+    #[serde(rename = "SensorID")]
+    sensor_id: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProcessOptions {
+    pub threshold: f64,
+    pub verbose: bool,
+    pub truncate_timestamp: Option<TimeUnit>,
+    pub timestamp_format: TimestampFormat,
+    /// Zone that timestamps without an offset are local to; UTC when `None`.
+    pub from_tz: Option<Zone>,
+    /// Zone whose wall-clock time timestamps are rewritten in; UTC when `None`.
+    pub to_tz: Option<Zone>,
+    pub skip_empty_lines: bool,
+    pub retries: u32,
+    /// Read and parse rows on the calling thread rather than pipelining them from a
+    /// reader thread (see [`read_rows`]).
+    pub no_pipeline: bool,
+    pub nth: Option<usize>,
+    /// `--roster-db`: only rows from these sensors are processed.
+    pub roster: Option<HashSet<String>>,
+    pub pivot: Option<PathBuf>,
+    /// Write which sensors have readings in each bucket of this many microseconds.
+    pub coverage_matrix: Option<(PathBuf, i64)>,
+    pub force: bool,
+    pub clamp_min: Option<f64>,
+    pub clamp_max: Option<f64>,
+    /// Only count the values `clamp_min`/`clamp_max` would change; leave them as read.
+    pub clamp_report: bool,
+    /// Drop values outside each sensor's Tukey fences at this K times the IQR.
+    pub iqr_filter: Option<f64>,
+    /// Only count the values `iqr_filter` would drop; keep them.
+    pub iqr_report: bool,
+    pub merge_duplicates: Option<MergePolicy>,
+    /// Collapse each sensor's readings closer than this many microseconds into one.
+    pub dedup_window: Option<(i64, MergePolicy)>,
+    pub anomalies: Option<PathBuf>,
+    pub zscore: f64,
+    pub window: usize,
+    /// (width, step) in microseconds.
+    pub sliding_window: Option<(i64, i64)>,
+    /// (window, every) in filtered rows for `--online-window`.
+    pub online_window: Option<(usize, usize)>,
+    pub quantize: Option<f64>,
+    pub skip_bad_rows: bool,
+    /// Check each row's bytes for UTF-8 and name the offending row and column.
+    pub strict_utf8: bool,
+    /// Build records from the row's fields and parse `Value` with [`fastfloat::parse`]
+    /// instead of deserializing through serde. Ignored with `value_expr`.
+    pub fast_float: bool,
+    /// Match column names against the header row ignoring case and surrounding spaces.
+    pub header_ignore_case: bool,
+    /// With `process_chunks`, drop a row whose (timestamp, sensor, value) appeared in any
+    /// earlier row of any chunk.
+    pub cross_file_dedup: bool,
+    /// Sniff the field delimiter from the first lines instead of assuming a comma.
+    pub autodetect: bool,
+    pub errors_to: Option<PathBuf>,
+    /// Bad rows kept in `ProcessingStats::row_errors`; later ones are only counted.
+    pub max_errors: usize,
+    pub transform: Option<Transform>,
+    pub output: Option<PathBuf>,
+    /// Indices into [`OUTPUT_COLUMNS`] for `--output-order`; all of them when `None`.
+    pub output_order: Option<Vec<usize>>,
+    /// Per-sensor `--offsets` applied to every reading as it is read.
+    pub calibration: Option<Calibration>,
+    /// Write the filtered rows to `output` as Parquet.
+    pub parquet: bool,
+    /// Minimum flatline span in microseconds.
+    pub flatline_duration: Option<i64>,
+    pub flatline_epsilon: f64,
+    /// Expected time between a sensor's readings, in microseconds, for coverage.
+    pub expected_interval: Option<i64>,
+    /// Coverage percentage below which a sensor is flagged.
+    pub min_coverage: f64,
+    /// Combine coverage, noise floor and flatlines into a per-sensor score with these
+    /// weights; needs `expected_interval` and `flatline_duration`.
+    pub quality_weights: Option<QualityWeights>,
+    pub min_quality: f64,
+    pub detect_clock_resets: bool,
+    /// Report each reading whose value is below the sensor's previous one (counters).
+    pub expect_monotonic_values: bool,
+    /// (left, right) sensor IDs for `--pair-delta`.
+    pub pair_delta: Option<(String, String)>,
+    /// Slots per sensor for the `--timeline` sparkline.
+    pub timeline: Option<usize>,
+    pub write: WriteOptions,
+    /// Values are percentages: count those outside [0, 100].
+    pub percent: bool,
+    /// With `percent`, divide values by 100 before clamping, quantizing and filtering.
+    pub renormalize: bool,
+    /// Per-sensor percentiles (0-100) to compute over the filtered values.
+    pub percentiles: Vec<f64>,
+    /// Bytes of percentile values to hold in memory before spilling sorted runs to disk.
+    pub spill_budget: Option<usize>,
+    /// Largest plausible change per second between a sensor's consecutive readings.
+    pub max_rate: Option<f64>,
+    /// Return every filtered value in `ProcessingStats::values`.
+    pub keep_values: bool,
+    /// Return each breakdown group's filtered values in `ProcessingStats::group_values`.
+    pub group_values: bool,
+    /// Round values to this many decimal places, after `quantize`.
+    pub round_values: Option<u32>,
+    pub value_buckets: Option<BucketScope>,
+    /// Distinct values reported per bucket group; the rest are counted as "other".
+    pub max_buckets: usize,
+    /// Width of the `--value-bin-width` bins.
+    pub value_bin_width: Option<f64>,
+    /// Ascending thresholds to report filtered rows and averages at, alongside `threshold`.
+    pub threshold_sweep: Option<Vec<f64>>,
+    /// Setpoint the `--target` deviations are measured from.
+    pub target: Option<f64>,
+    /// Estimate each sensor's noise floor from its consecutive readings.
+    pub noise_floor: bool,
+    /// Restrict the per-sensor breakdown (not the global stats) to these sensor IDs.
+    pub only_sensors: Option<HashSet<String>>,
+    /// Keep only each sensor's first N readings by timestamp.
+    pub max_per_sensor: Option<usize>,
+    /// Group the per-sensor breakdown by this regex's first capture of the sensor ID.
+    pub group_regex: Option<Regex>,
+    /// Leave sensors that `group_regex` does not match out of the breakdown instead of
+    /// collecting them under [`NO_MATCH_GROUP`].
+    pub skip_unmatched: bool,
+    /// How time-based features resolve several readings of a sensor at one timestamp.
+    pub same_timestamp: SameTimestamp,
+    /// Count each sensor's distinct values in the per-sensor breakdown.
+    pub distinct_values: bool,
+    /// Compute each row's value from other columns instead of reading `Value`.
+    pub value_expr: Option<Expr>,
+    /// Treat `Value` as a category name and only count categories.
+    pub categorical: bool,
+    /// Forward-fill at most this many empty `--pivot` cells after each reading.
+    pub ffill: Option<usize>,
+    /// Print running stats of the read pass to stderr when SIGUSR1 arrives.
+    pub stats_on_signal: bool,
+    /// Stop reading on SIGINT and report the rows read so far.
+    pub partial_on_interrupt: bool,
+}
+
+pub const NO_MATCH_GROUP: &str = "(no match)";
+
+impl ProcessOptions {
+    fn needs_timestamps(&self) -> bool {
+        self.truncate_timestamp.is_some()
+            || self.from_tz.is_some()
+            || self.to_tz.is_some()
+            || self.pivot.is_some()
+            || self.coverage_matrix.is_some()
+            || self.merge_duplicates.is_some()
+            || self.dedup_window.is_some()
+            || self.anomalies.is_some()
+            || self.sliding_window.is_some()
+            || self.transform.is_some()
+            || self.flatline_duration.is_some()
+            || self.expected_interval.is_some()
+            || self.timeline.is_some()
+            || self.detect_clock_resets
+            || self.expect_monotonic_values
+            || self.pair_delta.is_some()
+            || self.max_rate.is_some()
+            || self.max_per_sensor.is_some()
+            || self.noise_floor
+    }
+
+    /// Features that assume at most one reading per sensor and timestamp.
+    fn has_time_series_features(&self) -> bool {
+        self.pivot.is_some()
+            || self.anomalies.is_some()
+            || self.sliding_window.is_some()
+            || self.transform.is_some()
+            || self.flatline_duration.is_some()
+            || self.pair_delta.is_some()
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone)]
 pub struct ProcessingStats {
     pub total_rows: usize,
     pub filtered_rows: usize,
     pub average: Option<f64>,
-    #[allow(dead_code)]
+    /// Deviation of the filtered values from `--target`.
+    pub target_deviation: Option<TargetDeviation>,
     pub per_sensor: Vec<SensorStats>,
+    pub blank_lines_skipped: usize,
+    pub rows_skipped_by_nth: usize,
+    /// Rows dropped because their sensor is not in the `--roster-db` roster.
+    pub rows_outside_roster: Option<usize>,
+    /// (timestamp rows, sensor columns) of the table written by `--pivot`.
+    pub pivot_shape: Option<(usize, usize)>,
+    /// (sensor rows, bucket columns) of the `--coverage-matrix` file.
+    pub coverage_matrix_shape: Option<(usize, usize)>,
+    pub clamp: Option<ClampReport>,
+    pub duplicate_groups_merged: usize,
+    /// Readings folded into an earlier one by `--dedup-window`.
+    pub near_duplicates_collapsed: Option<usize>,
+    /// Rows dropped by `cross_file_dedup` as repeats of an earlier chunk's (or row's) triple.
+    pub cross_file_duplicates: Option<usize>,
+    /// Values outside the `iqr_filter` fences (dropped unless `iqr_report`).
+    pub iqr_outliers: Option<usize>,
+    /// Same-timestamp collisions resolved before the time-based features ran.
+    pub same_timestamp_resolved: Option<usize>,
+    pub anomalies_found: Option<usize>,
+    pub windows: Vec<WindowStats>,
+    pub online: Vec<OnlineStats>,
+    pub bad_rows_skipped: usize,
+    pub row_errors: Vec<RowError>,
+    pub output_rows: Option<usize>,
+    pub flatlines: Vec<Flatline>,
+    pub coverage: Vec<Coverage>,
+    pub quality: Vec<QualityScore>,
+    pub timeline: Option<Timeline>,
+    /// Backward timestamp jumps, in file order, when `detect_clock_resets` is set.
+    pub clock_resets: Option<Vec<ClockReset>>,
+    /// Value decreases per sensor, when `expect_monotonic_values` is set.
+    pub value_drops: Option<Vec<ValueDrop>>,
+    pub pair_delta: Option<PairDelta>,
+    /// Values outside [0, 100] when `--percent` is set.
+    pub percent_out_of_range: Option<usize>,
+    pub percentiles: Vec<SensorPercentiles>,
+    pub percentile_spill_runs: usize,
+    /// Readings dropped by `--max-rate`, per sensor.
+    pub glitches: Option<Vec<DroppedCount>>,
+    /// Readings dropped by `--max-per-sensor`, per sensor.
+    pub capped: Option<Vec<DroppedCount>>,
+    /// Rows calibrated by `--offsets`, per listed sensor that had readings.
+    pub calibrated: Option<Vec<(String, usize)>>,
+    /// Filtered values in file order, when `keep_values` is set.
+    pub values: Vec<f64>,
+    /// Filtered values in file order per breakdown group, when `group_values` is set.
+    pub group_values: BTreeMap<String, Vec<f64>>,
+    pub value_buckets: Vec<ValueBuckets>,
+    pub value_bins: Vec<ValueBin>,
+    pub threshold_sweep: Vec<SweepRow>,
+    /// `--categorical` counts: fleet-wide first, then per sensor.
+    pub categories: Vec<CategoryCounts>,
+    pub warnings: Warnings,
+    /// Only the rows read before a SIGINT are included.
+    pub partial: bool,
+}
+
+impl ProcessingStats {
+    /// Combines the stats of two inputs processed with the same options, as if they had
+    /// been one. Counts, averages, per-sensor stats and per-sensor drop counts are merged
+    /// exactly, as are the calibrated row counts; distinct values keep the larger count, a
+    /// lower bound. Results tied to one input (pivot and coverage matrix shapes, windows, online stats,
+    /// percentiles, coverage, timeline, clock resets, pair delta, value buckets and bins,
+    /// categories) are kept from `self`.
+    pub fn merge(mut self, other: ProcessingStats) -> ProcessingStats {
+        let add = |a: Option<usize>, b: Option<usize>| match (a, b) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+
+        self.average = match (self.average, other.average) {
+            (Some(a), Some(b)) => Some(
+                (a * self.filtered_rows as f64 + b * other.filtered_rows as f64)
+                    / (self.filtered_rows + other.filtered_rows) as f64,
+            ),
+            (a, b) => a.or(b),
+        };
+        self.target_deviation = match (self.target_deviation, other.target_deviation) {
+            (Some(a), Some(b)) => {
+                let (n, m) = (self.filtered_rows as f64, other.filtered_rows as f64);
+                Some(TargetDeviation {
+                    mad: (a.mad * n + b.mad * m) / (n + m),
+                    rmsd: ((a.rmsd * a.rmsd * n + b.rmsd * b.rmsd * m) / (n + m)).sqrt(),
+                })
+            }
+            (a, b) => a.or(b),
+        };
+        self.total_rows += other.total_rows;
+        self.filtered_rows += other.filtered_rows;
+        self.per_sensor = merge_sensor_stats(self.per_sensor, other.per_sensor);
+        for (key, values) in other.group_values {
+            self.group_values.entry(key).or_default().extend(values);
+        }
+        self.blank_lines_skipped += other.blank_lines_skipped;
+        self.rows_skipped_by_nth += other.rows_skipped_by_nth;
+        self.rows_outside_roster = add(self.rows_outside_roster, other.rows_outside_roster);
+        self.bad_rows_skipped += other.bad_rows_skipped;
+        self.row_errors.extend(other.row_errors);
+        self.duplicate_groups_merged += other.duplicate_groups_merged;
+        self.cross_file_duplicates = add(self.cross_file_duplicates, other.cross_file_duplicates);
+        self.iqr_outliers = add(self.iqr_outliers, other.iqr_outliers);
+        self.same_timestamp_resolved =
+            add(self.same_timestamp_resolved, other.same_timestamp_resolved);
+        self.near_duplicates_collapsed = add(
+            self.near_duplicates_collapsed,
+            other.near_duplicates_collapsed,
+        );
+        self.anomalies_found = add(self.anomalies_found, other.anomalies_found);
+        self.output_rows = add(self.output_rows, other.output_rows);
+        self.percent_out_of_range = add(self.percent_out_of_range, other.percent_out_of_range);
+        self.percentile_spill_runs += other.percentile_spill_runs;
+        self.flatlines.extend(other.flatlines);
+        self.values.extend(other.values);
+        self.warnings.extend(other.warnings);
+        self.partial |= other.partial;
+
+        self.clamp = match (self.clamp, other.clamp) {
+            (Some(mut a), Some(b)) => {
+                a.below += b.below;
+                a.above += b.above;
+                let mut per_sensor: BTreeMap<String, ClampCount> = BTreeMap::new();
+                for c in a.per_sensor.into_iter().chain(b.per_sensor) {
+                    let merged = per_sensor.entry(c.sensor_id.clone()).or_default();
+                    merged.sensor_id = c.sensor_id;
+                    merged.below += c.below;
+                    merged.above += c.above;
+                }
+                a.per_sensor = per_sensor.into_values().collect();
+                Some(a)
+            }
+            (a, b) => a.or(b),
+        };
+        self.glitches = merge_dropped(self.glitches, other.glitches);
+        self.capped = merge_dropped(self.capped, other.capped);
+        self.calibrated = match (self.calibrated, other.calibrated) {
+            (Some(a), Some(b)) => {
+                let mut merged: BTreeMap<String, usize> = a.into_iter().collect();
+                for (sensor_id, n) in b {
+                    *merged.entry(sensor_id).or_default() += n;
+                }
+                Some(merged.into_iter().collect())
+            }
+            (a, b) => a.or(b),
+        };
+        self
+    }
+}
+
+fn merge_sensor_stats(a: Vec<SensorStats>, b: Vec<SensorStats>) -> Vec<SensorStats> {
+    let mut merged: BTreeMap<String, SensorStats> = BTreeMap::new();
+    for s in a.into_iter().chain(b) {
+        match merged.get_mut(&s.sensor_id) {
+            Some(m) => {
+                let acc = Accumulator::from_stats(m).merge(Accumulator::from_stats(&s));
+                m.count = acc.count;
+                m.average = acc.mean;
+                m.min = acc.min;
+                m.max = acc.max;
+                m.range = acc.max - acc.min;
+                m.sem = acc.sem();
+                m.target_deviation = acc.target_deviation();
+                m.distinct_values = m.distinct_values.max(s.distinct_values);
+            }
+            None => {
+                merged.insert(s.sensor_id.clone(), s);
+            }
+        }
+    }
+    merged.into_values().collect()
+}
+
+fn merge_dropped(
+    a: Option<Vec<DroppedCount>>,
+    b: Option<Vec<DroppedCount>>,
+) -> Option<Vec<DroppedCount>> {
+    let (a, b) = match (a, b) {
+        (Some(a), Some(b)) => (a, b),
+        (a, b) => return a.or(b),
+    };
+    let mut merged: BTreeMap<String, usize> = BTreeMap::new();
+    for d in a.into_iter().chain(b) {
+        *merged.entry(d.sensor_id).or_default() += d.dropped;
+    }
+    Some(
+        merged
+            .into_iter()
+            .map(|(sensor_id, dropped)| DroppedCount { sensor_id, dropped })
+            .collect(),
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct RowError {
+    pub line: u64,
+    pub error: String,
+    pub raw: String,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ClampReport {
+    pub below: usize,
+    pub above: usize,
+    /// Sensors with at least one out-of-bounds value, sorted by ID.
+    pub per_sensor: Vec<ClampCount>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ClampCount {
+    pub sensor_id: String,
+    pub below: usize,
+    pub above: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SensorStats {
     pub sensor_id: String,
     pub count: usize,
     pub average: f64,
+    pub min: f64,
+    pub max: f64,
+    pub range: f64,
+    /// Distinct filtered values, counted with `--distinct-values`; 0 otherwise.
+    pub distinct_values: usize,
+    /// Standard error of the mean (sample std-dev / sqrt(count)); `None` below 2 readings.
+    pub sem: Option<f64>,
+    pub target_deviation: Option<TargetDeviation>,
+    /// `--noise-floor` estimate; `None` below three readings or without the flag.
+    pub noise_floor: Option<f64>,
 }
 
-#[derive(Default, Clone)]
+/// How far values sit from the `--target` setpoint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TargetDeviation {
+    /// Mean absolute deviation: the mean of |v - target|.
+    pub mad: f64,
+    /// Root-mean-square deviation: the square root of the mean of (v - target)^2.
+    pub rmsd: f64,
+}
+
+#[derive(Debug, Default)]
+struct ReadSummary {
+    blank_lines_skipped: usize,
+    rows_skipped_by_nth: usize,
+    rows_outside_roster: Option<usize>,
+    bad_rows_skipped: usize,
+    row_errors: Vec<RowError>,
+    /// Reading stopped early on SIGINT (`--partial-on-interrupt`).
+    interrupted: bool,
+    /// `--autodetect` could not settle on a delimiter and fell back to a comma.
+    ambiguous_delimiter: bool,
+}
+
+impl ReadSummary {
+    fn record_bad_row(&mut self, line: u64, error: String, raw: String, max_errors: usize) {
+        self.bad_rows_skipped += 1;
+        if self.row_errors.len() < max_errors {
+            self.row_errors.push(RowError { line, error, raw });
+        }
+    }
+}
+
+#[derive(Clone)]
 struct Accumulator {
     count: usize,
     sum: f64,
+    min: f64,
+    max: f64,
+    /// Running mean and sum of squared deviations from it (Welford), for the variance.
+    mean: f64,
+    m2: f64,
+    /// Sums of |v - target| and (v - target)^2, once a value was added with a target.
+    deviation: Option<(f64, f64)>,
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            mean: 0.0,
+            m2: 0.0,
+            deviation: None,
+        }
+    }
 }
 
 impl Accumulator {
-    fn add(&mut self, value: f64) {
+    fn add(&mut self, value: f64, target: Option<f64>) {
+        if let Some(target) = target {
+            let d = value - target;
+            let (abs, sq) = self.deviation.get_or_insert((0.0, 0.0));
+            *abs += d.abs();
+            *sq += d * d;
+        }
         self.count += 1;
         self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
     }
 
     fn merge(mut self, other: Self) -> Self {
-        self.count += other.count;
+        let count = self.count + other.count;
+        if count > 0 {
+            let delta = other.mean - self.mean;
+            let weight = other.count as f64 / count as f64;
+            self.m2 += other.m2 + delta * delta * self.count as f64 * weight;
+            self.mean += delta * weight;
+        }
+        self.count = count;
         self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.deviation = match (self.deviation, other.deviation) {
+            (Some(a), Some(b)) => Some((a.0 + b.0, a.1 + b.1)),
+            (a, b) => a.or(b),
+        };
         self
     }
+
+    /// Rebuilds the accumulator a sensor's stats came from (the variance from its SEM).
+    fn from_stats(s: &SensorStats) -> Self {
+        let n = s.count as f64;
+        Self {
+            count: s.count,
+            sum: s.average * n,
+            min: s.min,
+            max: s.max,
+            mean: s.average,
+            m2: s.sem.map_or(0.0, |sem| sem * sem * n * (n - 1.0)),
+            deviation: s.target_deviation.map(|d| (d.mad * n, d.rmsd * d.rmsd * n)),
+        }
+    }
+
+    fn target_deviation(&self) -> Option<TargetDeviation> {
+        let n = self.count as f64;
+        self.deviation.map(|(abs, sq)| TargetDeviation {
+            mad: abs / n,
+            rmsd: (sq / n).sqrt(),
+        })
+    }
+
+    /// Standard error of the mean, from the sample standard deviation.
+    fn sem(&self) -> Option<f64> {
+        (self.count > 1).then(|| {
+            let n = self.count as f64;
+            (self.m2 / (n - 1.0)).sqrt() / n.sqrt()
+        })
+    }
+}
+
+pub fn process(path: &Path, opts: &ProcessOptions) -> Result<ProcessingStats> {
+    let file = input::open_file(path, opts.retries)
+        .with_context(|| format!("Cannot open CSV file '{}'", path.display()))?;
+    process_reader(Box::new(file), &path.display().to_string(), opts)
+}
+
+/// (timestamp, sensor ID, value bits) of every row kept so far, for `cross_file_dedup`.
+type SeenRows = HashSet<(String, String, u64)>;
+
+/// Processes each CSV document in `input`, split on `delimiter` lines (see [`Chunks`]),
+/// returning one result per chunk. Fold them with [`ProcessingStats::merge`] for a total.
+pub fn process_chunks(
+    input: impl BufRead,
+    delimiter: &str,
+    source: &str,
+    opts: &ProcessOptions,
+) -> Result<Vec<ProcessingStats>> {
+    // Spans every chunk, so a row repeated at the boundary of two exports counts once.
+    let mut seen = opts.cross_file_dedup.then(SeenRows::new);
+    Chunks::new(input, delimiter)
+        .take_while(|_| !(opts.partial_on_interrupt && signals::interrupted()))
+        .enumerate()
+        .map(|(i, chunk)| {
+            let chunk = chunk.with_context(|| format!("Cannot read '{source}'"))?;
+            let name = format!("{source} (chunk {})", i + 1);
+            process_input(Box::new(io::Cursor::new(chunk)), &name, opts, seen.as_mut())
+                .with_context(|| format!("Failed to process {name}"))
+        })
+        .collect()
+}
+
+/// Like [`process`], for CSV data that does not come from a local file.
+pub fn process_reader(
+    input: Box<dyn Read + Send>,
+    source: &str,
+    opts: &ProcessOptions,
+) -> Result<ProcessingStats> {
+    process_input(input, source, opts, None)
 }
 
-pub fn process(path: &Path, threshold: f64, verbose: bool) -> Result<ProcessingStats> {
-    let records = read_csv(path)?;
+fn process_input(
+    input: Box<dyn Read + Send>,
+    source: &str,
+    opts: &ProcessOptions,
+    seen: Option<&mut SeenRows>,
+) -> Result<ProcessingStats> {
+    if opts.categorical {
+        return process_categorical(input, source, opts);
+    }
+
+    let threshold = opts.threshold;
+    let (mut records, summary) = read_csv(input, source, opts)?;
+
+    if let Some(out) = &opts.errors_to {
+        write_row_errors(&summary.row_errors, out, opts.write)?;
+    }
     let total_rows = records.len();
 
+    let cross_file_duplicates = seen.map(|seen| {
+        let before = records.len();
+        records.retain(|r| {
+            // -0.0 and 0.0 are the same reading.
+            let value = if r.value == 0.0 { 0.0 } else { r.value };
+            seen.insert((r.timestamp.clone(), r.sensor_id.clone(), value.to_bits()))
+        });
+        before - records.len()
+    });
+
+    // Row numbers refer to the file order, before anything below reorders the records.
+    let clock_resets = opts
+        .detect_clock_resets
+        .then(|| series::clock_resets(&records));
+    // Checked on the values as read, before calibration or any transform.
+    let value_drops = opts
+        .expect_monotonic_values
+        .then(|| series::value_drops(&records));
+
+    let calibrated = opts
+        .calibration
+        .as_ref()
+        .map(|calibration| calibration.apply(&mut records));
+
+    let mut duplicate_groups_merged = 0;
+    if let Some(policy) = opts.merge_duplicates {
+        (records, duplicate_groups_merged) = dedup::merge_duplicates(records, policy);
+    }
+
+    let mut near_duplicates_collapsed = None;
+    if let Some((window, policy)) = opts.dedup_window {
+        let (kept, collapsed) = dedup::collapse_window(records, window, policy);
+        records = kept;
+        near_duplicates_collapsed = Some(collapsed);
+    }
+
+    let glitches = match opts.max_rate {
+        Some(max_rate) => {
+            let (kept, counts) = series::drop_glitches(records, max_rate);
+            records = kept;
+            Some(counts)
+        }
+        None => None,
+    };
+
+    let capped = match opts.max_per_sensor {
+        Some(n) => {
+            let (kept, counts) = series::cap_per_sensor(records, n);
+            records = kept;
+            Some(counts)
+        }
+        None => None,
+    };
+
+    let mut warnings = Warnings::default();
+    if summary.ambiguous_delimiter {
+        warnings.push(ambiguous_delimiter_warning(source));
+    }
+    if summary.interrupted {
+        warnings.push(format!(
+            "Interrupted: PARTIAL results from the first {total_rows} rows of '{source}'."
+        ));
+    }
+
+    let percent_out_of_range = if opts.percent {
+        let out_of_range = records
+            .par_iter()
+            .filter(|r| !(0.0..=100.0).contains(&r.value))
+            .count();
+        if out_of_range > 0 {
+            warnings.push(format!(
+                "{out_of_range} value(s) are outside the 0-100% range."
+            ));
+        }
+        if opts.renormalize {
+            records.par_iter_mut().for_each(|r| r.value /= 100.0);
+        }
+        Some(out_of_range)
+    } else {
+        None
+    };
+
+    let clamp = if opts.clamp_min.is_some() || opts.clamp_max.is_some() {
+        let report = clamp_report(&records, opts.clamp_min, opts.clamp_max);
+        if !opts.clamp_report {
+            apply_clamp(&mut records, opts.clamp_min, opts.clamp_max);
+        }
+        Some(report)
+    } else {
+        None
+    };
+
+    if let Some(step) = opts.quantize {
+        records
+            .par_iter_mut()
+            .for_each(|r| r.value = quantize(r.value, step));
+    }
+
+    if let Some(decimals) = opts.round_values {
+        records
+            .par_iter_mut()
+            .for_each(|r| r.value = round_to(r.value, decimals));
+    }
+
+    // Fences come from the values the filter keeps, after every value transform.
+    let iqr_outliers = opts.iqr_filter.map(|k| {
+        let fences = series::iqr_fences(records.iter(), threshold, k);
+        let outside = |r: &Record| {
+            r.value > threshold
+                && fences
+                    .get(&r.sensor_id)
+                    .is_some_and(|&(lower, upper)| r.value < lower || r.value > upper)
+        };
+        let count = records.iter().filter(|r| outside(r)).count();
+        if !opts.iqr_report {
+            records.retain(|r| !outside(r));
+        }
+        count
+    });
+
     let global_acc = records
         .par_iter()
         .filter(|r| r.value > threshold)
         .fold(Accumulator::default, |mut acc, r| {
-            acc.add(r.value);
+            acc.add(r.value, opts.target);
             acc
         })
         .reduce(Accumulator::default, Accumulator::merge);
@@ -72,60 +796,1022 @@ pub fn process(path: &Path, threshold: f64, verbose: bool) -> Result<ProcessingS
         None
     };
 
-    let per_sensor = if verbose {
-        compute_per_sensor_stats(&records, threshold)
+    let value_bins = match opts.value_bin_width {
+        Some(width) if global_acc.count > 0 => {
+            let count = buckets::value_bin_count(global_acc.min, global_acc.max, width);
+            if count > buckets::MAX_VALUE_BINS && !opts.force {
+                anyhow::bail!(
+                    "--value-bin-width would produce {count} bins (limit {}); use a wider bin or pass --force",
+                    buckets::MAX_VALUE_BINS
+                );
+            }
+            buckets::value_bins(
+                records
+                    .iter()
+                    .filter(|r| r.value > threshold)
+                    .map(|r| r.value),
+                global_acc.min,
+                global_acc.max,
+                width,
+            )
+        }
+        _ => Vec::new(),
+    };
+
+    // Every transform has run, so these are the values the filter itself compares.
+    let threshold_sweep = match &opts.threshold_sweep {
+        Some(thresholds) => buckets::threshold_sweep(records.iter().map(|r| r.value), thresholds),
+        None => Vec::new(),
+    };
+
+    let values = if opts.keep_values {
+        records
+            .iter()
+            .filter(|r| r.value > threshold)
+            .map(|r| r.value)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut group_values: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    if opts.group_values {
+        let keys = group_keys(&records, opts);
+        for r in &records {
+            if let Some(key) = breakdown_key(r, &keys, opts) {
+                group_values
+                    .entry(key.to_string())
+                    .or_default()
+                    .push(r.value);
+            }
+        }
+    }
+
+    let online = match opts.online_window {
+        Some((window, every)) => series::online_stats(
+            records
+                .iter()
+                .filter(|r| r.value > threshold)
+                .map(|r| r.value),
+            window,
+            every,
+        ),
+        None => Vec::new(),
+    };
+
+    let value_buckets = match opts.value_buckets {
+        Some(scope) => buckets::tally(
+            records.iter().filter(|r| r.value > threshold),
+            scope,
+            opts.max_buckets,
+        ),
+        None => Vec::new(),
+    };
+
+    let mut per_sensor = if opts.verbose {
+        compute_per_sensor_stats(&records, opts)
     } else {
         Vec::new()
     };
+    if opts.noise_floor {
+        // Like the quality checks, the estimate uses every reading: dropping those below
+        // the threshold would leave gaps that read as noise.
+        let series = series::by_sensor(records.iter());
+        for s in &mut per_sensor {
+            s.noise_floor = series
+                .get(s.sensor_id.as_str())
+                .and_then(|series| series::noise_floor(series));
+        }
+    }
+    if opts.distinct_values {
+        for s in per_sensor
+            .iter()
+            .filter(|s| s.distinct_values == 1 && s.count > 1)
+        {
+            warnings.push(format!(
+                "Sensor '{}' reported the same value in all {} readings.",
+                s.sensor_id, s.count
+            ));
+        }
+    }
+
+    let (percentiles, percentile_spill_runs) = if opts.percentiles.is_empty() {
+        (Vec::new(), 0)
+    } else {
+        let mut store = ValueStore::new(opts.spill_budget);
+        for r in records.iter().filter(|r| r.value > threshold) {
+            store.push(&r.sensor_id, r.value)?;
+        }
+        let runs = store.spilled_runs();
+        (store.percentiles(&opts.percentiles)?, runs)
+    };
+
+    // Activity counts every reading, including those below the threshold.
+    let timeline = opts
+        .timeline
+        .and_then(|slots| series::timeline(&records, slots));
+
+    // Like the other quality checks, presence counts readings below the threshold too.
+    let coverage_matrix_shape = match &opts.coverage_matrix {
+        Some((out, step)) => {
+            let buckets = pivot::matrix_bucket_count(&records, *step);
+            if buckets > pivot::MAX_MATRIX_BUCKETS && !opts.force {
+                anyhow::bail!(
+                    "--coverage-matrix would produce {buckets} bucket columns (limit {}); use a wider --resample or pass --force",
+                    pivot::MAX_MATRIX_BUCKETS
+                );
+            }
+            let matrix = pivot::presence_matrix(&records, *step);
+            pivot::write_presence(&matrix, out, opts.write)?;
+            Some((matrix.sensors.len(), matrix.buckets.len()))
+        }
+        None => None,
+    };
+
+    let mut output_rows = None;
+    if let (true, Some(out)) = (opts.parquet, &opts.output) {
+        let kept: Vec<&Record> = records.iter().filter(|r| r.value > threshold).collect();
+        output_rows = Some(parquet::write_records(&kept, out, opts.write)?);
+    }
+
+    // The stats above still count every reading; only the time-based features below see
+    // one reading per sensor and timestamp.
+    let mut same_timestamp_resolved = None;
+    if opts.has_time_series_features() {
+        let (resolved, collisions) = dedup::resolve_same_timestamp(records, opts.same_timestamp)?;
+        records = resolved;
+        same_timestamp_resolved = Some(collisions);
+    }
+
+    let pivot_shape = match &opts.pivot {
+        Some(out) => {
+            let mut table = pivot::build(records.iter().filter(|r| r.value > threshold));
+            if let Some(limit) = opts.ffill {
+                table.forward_fill(limit);
+            }
+            if table.sensors.len() > pivot::MAX_PIVOT_COLUMNS && !opts.force {
+                anyhow::bail!(
+                    "--pivot would produce {} sensor columns (limit {}); pass --force to write it anyway",
+                    table.sensors.len(),
+                    pivot::MAX_PIVOT_COLUMNS
+                );
+            }
+            pivot::write(&table, out, opts.write)?;
+            Some((table.rows.len(), table.sensors.len()))
+        }
+        None => None,
+    };
+
+    let pair_delta = opts.pair_delta.as_ref().map(|(left, right)| {
+        series::pair_delta(records.iter().filter(|r| r.value > threshold), left, right)
+    });
+
+    let anomalies_found = match &opts.anomalies {
+        Some(out) => Some(write_anomalies(&records, opts, out)?),
+        None => None,
+    };
+
+    if let (Some(transform), Some(out)) = (opts.transform, &opts.output) {
+        output_rows = Some(write_transformed(&records, opts, transform, out)?);
+    }
+
+    let flatlines = match opts.flatline_duration {
+        // Quality checks look at every reading; a sensor frozen below the threshold is
+        // exactly what they should catch.
+        Some(min_duration) => series::by_sensor(records.iter())
+            .values()
+            .flat_map(|s| series::flatlines(s, min_duration, opts.flatline_epsilon))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let coverage: Vec<Coverage> = match opts.expected_interval {
+        Some(interval) => series::by_sensor(records.iter())
+            .values()
+            .map(|s| series::coverage(s, interval))
+            .collect(),
+        None => Vec::new(),
+    };
+    for c in coverage.iter().filter(|c| c.percent() < opts.min_coverage) {
+        warnings.push(format!(
+            "Sensor '{}' has {:.1}% coverage, below --min-coverage {}%.",
+            c.sensor_id,
+            c.percent(),
+            opts.min_coverage
+        ));
+    }
 
-    if verbose && !per_sensor.is_empty() {
-        print_sensor_table(&per_sensor);
+    let quality: Vec<QualityScore> = match opts.quality_weights {
+        Some(weights) if !coverage.is_empty() => {
+            let mut flat_readings: HashMap<&str, usize> = HashMap::new();
+            for f in &flatlines {
+                *flat_readings.entry(f.sensor_id.as_str()).or_default() += f.readings;
+            }
+            // Both follow the sensor order of `by_sensor`.
+            series::by_sensor(records.iter())
+                .values()
+                .zip(&coverage)
+                .map(|(s, c)| {
+                    let flat = flat_readings.get(c.sensor_id.as_str()).copied();
+                    series::quality_score(s, c, flat.unwrap_or(0), weights)
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+    for q in quality.iter().filter(|q| q.score < opts.min_quality) {
+        warnings.push(format!(
+            "Sensor '{}' has quality score {:.1}, below --min-quality {}.",
+            q.sensor_id, q.score, opts.min_quality
+        ));
     }
 
+    let windows = match opts.sliding_window {
+        Some((width, step)) => {
+            let kept: Vec<&Record> = records.iter().filter(|r| r.value > threshold).collect();
+            let count = resample::window_count(&kept, step);
+            if count > resample::MAX_WINDOWS && !opts.force {
+                anyhow::bail!(
+                    "--sliding-window would produce {count} windows (limit {}); use a larger --step or pass --force",
+                    resample::MAX_WINDOWS
+                );
+            }
+            resample::sliding_windows(&kept, width, step)
+        }
+        None => Vec::new(),
+    };
+
     Ok(ProcessingStats {
         total_rows,
         filtered_rows,
         average,
+        target_deviation: global_acc.target_deviation(),
         per_sensor,
+        blank_lines_skipped: summary.blank_lines_skipped,
+        rows_skipped_by_nth: summary.rows_skipped_by_nth,
+        rows_outside_roster: summary.rows_outside_roster,
+        pivot_shape,
+        coverage_matrix_shape,
+        clamp,
+        duplicate_groups_merged,
+        cross_file_duplicates,
+        iqr_outliers,
+        near_duplicates_collapsed,
+        same_timestamp_resolved,
+        anomalies_found,
+        windows,
+        online,
+        bad_rows_skipped: summary.bad_rows_skipped,
+        row_errors: summary.row_errors,
+        output_rows,
+        flatlines,
+        coverage,
+        quality,
+        timeline,
+        clock_resets,
+        value_drops,
+        pair_delta,
+        percent_out_of_range,
+        percentiles,
+        percentile_spill_runs,
+        glitches,
+        capped,
+        calibrated,
+        values,
+        group_values,
+        value_buckets,
+        value_bins,
+        threshold_sweep,
+        categories: Vec::new(),
+        warnings,
+        partial: summary.interrupted,
+    })
+}
+
+/// Columns of the `--output` CSV, in their default order.
+pub const OUTPUT_COLUMNS: [&str; 3] = ["Timestamp", "SensorID", "Value"];
+
+/// Resolves `--output-order` column names to indices into [`OUTPUT_COLUMNS`].
+pub fn output_order(names: &[String]) -> Result<Vec<usize>> {
+    let mut order = Vec::new();
+    for name in names {
+        let i = OUTPUT_COLUMNS
+            .iter()
+            .position(|c| c == name)
+            .with_context(|| {
+                format!(
+                    "--output-order: no column '{name}' (expected {})",
+                    OUTPUT_COLUMNS.join(", ")
+                )
+            })?;
+        if order.contains(&i) {
+            anyhow::bail!("--output-order lists '{name}' more than once");
+        }
+        order.push(i);
+    }
+    Ok(order)
+}
+
+fn write_transformed(
+    records: &[Record],
+    opts: &ProcessOptions,
+    transform: Transform,
+    out: &Path,
+) -> Result<usize> {
+    let order = match &opts.output_order {
+        Some(order) => order.clone(),
+        None => (0..OUTPUT_COLUMNS.len()).collect(),
+    };
+    let mut writer = output::csv_writer(out, opts.write)?;
+    writer.write_record(order.iter().map(|&i| OUTPUT_COLUMNS[i]))?;
+
+    let mut written = 0;
+    let groups = series::by_sensor(records.iter().filter(|r| r.value > opts.threshold));
+    for (sensor_id, series) in &groups {
+        for (timestamp, value) in transform.apply(series) {
+            let value = value.to_string();
+            let row = [timestamp.as_ref(), sensor_id, value.as_str()];
+            writer.write_record(order.iter().map(|&i| row[i]))?;
+            written += 1;
+        }
+    }
+
+    writer.finish()?;
+    Ok(written)
+}
+
+/// `--categorical`: counts each distinct `Value` string; there is no threshold or average.
+fn process_categorical(
+    input: Box<dyn Read + Send>,
+    source: &str,
+    opts: &ProcessOptions,
+) -> Result<ProcessingStats> {
+    let mut rows = Vec::new();
+    let mut outside_roster = None;
+    let mut summary =
+        read_rows(
+            input,
+            source,
+            opts,
+            |headers| {
+                let headers = headers.clone();
+                Ok(move |row: &csv::StringRecord| {
+                    Ok(row.deserialize::<CategoryRecord>(Some(&headers))?)
+                })
+            },
+            |mut batch| {
+                outside_roster = apply_roster(&mut batch, opts, |r| &r.sensor_id)
+                    .map(|n| outside_roster.unwrap_or(0) + n);
+                rows.append(&mut batch);
+                Ok(())
+            },
+        )?;
+    summary.rows_outside_roster = outside_roster;
+
+    if let Some(out) = &opts.errors_to {
+        write_row_errors(&summary.row_errors, out, opts.write)?;
+    }
+
+    let mut warnings = Warnings::default();
+    if summary.ambiguous_delimiter {
+        warnings.push(ambiguous_delimiter_warning(source));
+    }
+
+    Ok(ProcessingStats {
+        total_rows: rows.len(),
+        filtered_rows: rows.len(),
+        categories: buckets::count_categories(
+            rows.iter()
+                .map(|r| (r.sensor_id.as_str(), r.value.as_str())),
+        ),
+        blank_lines_skipped: summary.blank_lines_skipped,
+        rows_skipped_by_nth: summary.rows_skipped_by_nth,
+        rows_outside_roster: summary.rows_outside_roster,
+        bad_rows_skipped: summary.bad_rows_skipped,
+        row_errors: summary.row_errors,
+        partial: summary.interrupted,
+        warnings,
+        ..Default::default()
     })
 }
 
-fn read_csv(path: &Path) -> Result<Vec<Record>> {
+fn ambiguous_delimiter_warning(source: &str) -> String {
+    format!("Could not detect the delimiter of '{source}'; reading it as comma-separated.")
+}
+
+fn write_row_errors(errors: &[RowError], out: &Path, opts: WriteOptions) -> Result<()> {
+    let mut body = String::from("[");
+    for (i, e) in errors.iter().enumerate() {
+        body.push_str(if i == 0 { "\n" } else { ",\n" });
+        body.push_str(&format!(
+            "  {{\"line\": {}, \"error\": {}, \"raw\": {}}}",
+            e.line,
+            json::string(&e.error),
+            json::string(&e.raw)
+        ));
+    }
+    body.push_str(if errors.is_empty() { "]\n" } else { "\n]\n" });
+
+    let mut file = OutputFile::create(out, opts)?;
+    file.write_all(body.as_bytes())
+        .with_context(|| format!("Cannot write errors file '{}'", out.display()))?;
+    file.finish()
+}
+
+fn write_anomalies(records: &[Record], opts: &ProcessOptions, out: &Path) -> Result<usize> {
+    let mut writer = output::csv_writer(out, opts.write)
+        .with_context(|| format!("Cannot create anomalies file '{}'", out.display()))?;
+    writer.write_record([
+        "Timestamp",
+        "SensorID",
+        "Value",
+        "RollingMean",
+        "RollingStd",
+        "ZScore",
+    ])?;
+
+    let mut found = 0;
+    let groups = series::by_sensor(records.iter().filter(|r| r.value > opts.threshold));
+    for series in groups.values() {
+        for a in series::rolling_zscore(series, opts.window, opts.zscore) {
+            writer.write_record([
+                a.record.timestamp.clone(),
+                a.record.sensor_id.clone(),
+                a.record.value.to_string(),
+                a.mean.to_string(),
+                a.std_dev.to_string(),
+                a.zscore.to_string(),
+            ])?;
+            found += 1;
+        }
+    }
+
+    writer.finish()?;
+    Ok(found)
+}
+
+/// Reads CSV rows from `input`; `source` names it in error messages.
+fn read_csv(
+    input: Box<dyn Read + Send>,
+    source: &str,
+    opts: &ProcessOptions,
+) -> Result<(Vec<Record>, ReadSummary)> {
+    let bind = |headers: &csv::StringRecord| {
+        let value_expr = opts
+            .value_expr
+            .as_ref()
+            .map(|e| e.bind(headers))
+            .transpose()?;
+        let fast_columns = opts.fast_float.then(|| fast_columns(headers)).transpose()?;
+        let headers = headers.clone();
+        let mut progress = opts.stats_on_signal.then(|| Progress::new(opts.threshold));
+        Ok(move |row: &csv::StringRecord| {
+            let record = match (fast_columns, &value_expr) {
+                (Some(columns), None) => parse_row_fast(row, columns)?,
+                _ => parse_row(row, &headers, value_expr.as_ref())?,
+            };
+            if let Some(progress) = &mut progress {
+                progress.add(record.value);
+                if signals::take_request() {
+                    eprintln!("{}", progress.snapshot());
+                }
+            }
+            Ok(record)
+        })
+    };
+
+    let mut records = Vec::new();
+    let mut outside_roster = None;
+    let mut summary = read_rows(input, source, opts, bind, |mut batch| {
+        outside_roster = apply_roster(&mut batch, opts, |r| &r.sensor_id)
+            .map(|n| outside_roster.unwrap_or(0) + n);
+        if opts.needs_timestamps() {
+            parse_timestamps(&mut batch, records.len(), opts)?;
+        }
+        records.append(&mut batch);
+        Ok(())
+    })?;
+    summary.rows_outside_roster = outside_roster;
+
+    Ok((records, summary))
+}
+
+/// Drops the rows of sensors missing from `--roster-db`, returning how many went.
+fn apply_roster<T>(
+    rows: &mut Vec<T>,
+    opts: &ProcessOptions,
+    sensor_id: impl Fn(&T) -> &String,
+) -> Option<usize> {
+    let roster = opts.roster.as_ref()?;
+    let before = rows.len();
+    rows.retain(|row| roster.contains(sensor_id(row)));
+    Some(before - rows.len())
+}
+
+/// Rows per batch the reader thread hands over, and how many batches may wait for the
+/// consumer: at most `PIPELINE_BATCH_ROWS * PIPELINE_DEPTH` parsed rows are held ahead.
+const PIPELINE_BATCH_ROWS: usize = 8192;
+const PIPELINE_DEPTH: usize = 4;
+
+/// Reads every data row with the row parser `bind` builds from the header row, applying
+/// `--skip-empty-lines`, `--nth` and `--skip-bad-rows`, and passes them to `fold` in
+/// batches, in file order.
+///
+/// Unless `no_pipeline` is set, a reader thread reads and parses the batches into a
+/// bounded channel while `fold` works through earlier ones on the calling thread, so
+/// reading overlaps with whatever `fold` does (such as parsing timestamps with rayon).
+fn read_rows<T, P>(
+    input: Box<dyn Read + Send>,
+    source: &str,
+    opts: &ProcessOptions,
+    bind: impl FnOnce(&csv::StringRecord) -> Result<P>,
+    mut fold: impl FnMut(Vec<T>) -> Result<()>,
+) -> Result<ReadSummary>
+where
+    T: Send,
+    P: FnMut(&csv::StringRecord) -> Result<T> + Send,
+{
+    let mut summary = ReadSummary::default();
+    let mut delimiter = b',';
+    let input = if opts.autodetect {
+        let (detected, input) = input::sniff(input)
+            .with_context(|| format!("Cannot read the first lines of '{source}'"))?;
+        match detected {
+            Some(d) => {
+                eprintln!("Detected delimiter {:?} in '{source}'", d as char);
+                delimiter = d;
+            }
+            None => summary.ambiguous_delimiter = true,
+        }
+        input
+    } else {
+        input
+    };
+
+    let blank_lines = Arc::new(AtomicUsize::new(0));
+    let input: Box<dyn Read + Send> = if opts.skip_empty_lines {
+        Box::new(SkipBlankLines::new(
+            BufReader::new(input),
+            Arc::clone(&blank_lines),
+        ))
+    } else {
+        input
+    };
+
     let mut reader = ReaderBuilder::new()
         .has_headers(true)
+        .delimiter(delimiter)
         .trim(csv::Trim::All)
-        .from_path(path)
-        .with_context(|| format!("Cannot open CSV file '{}'", path.display()))?;
+        .from_reader(input);
+
+    let mut headers = reader
+        .headers()
+        .with_context(|| format!("Cannot read header row of '{source}'"))?
+        .clone();
+    if opts.header_ignore_case {
+        let expr_columns = opts.value_expr.iter().flat_map(|e| e.columns());
+        let names = OUTPUT_COLUMNS
+            .into_iter()
+            .chain(expr_columns.map(String::as_str));
+        headers = match_headers_ignoring_case(&headers, names)
+            .with_context(|| format!("Cannot match the header row of '{source}'"))?;
+    }
+    let parse = bind(&headers)?;
+
+    let mut rows = RowReader {
+        reader,
+        headers,
+        parse,
+        source,
+        opts,
+        summary,
+        row: csv::StringRecord::new(),
+        bytes: csv::ByteRecord::new(),
+        index: 0,
+    };
+    if opts.no_pipeline {
+        loop {
+            let batch = rows.next_batch()?;
+            if batch.is_empty() {
+                break;
+            }
+            fold(batch)?;
+        }
+    } else {
+        rows = thread::scope(|scope| {
+            let (sender, batches) = mpsc::sync_channel(PIPELINE_DEPTH);
+            let reader = scope.spawn(move || -> Result<_> {
+                loop {
+                    let batch = rows.next_batch()?;
+                    // A closed channel means `fold` failed; its error is the one reported.
+                    if batch.is_empty() || sender.send(batch).is_err() {
+                        return Ok(rows);
+                    }
+                }
+            });
+            for batch in batches {
+                fold(batch)?;
+            }
+            reader
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })?;
+    }
 
-    let records: Result<Vec<Record>, _> = reader.deserialize().collect();
+    let mut summary = rows.summary;
+    summary.blank_lines_skipped = blank_lines.load(Ordering::Relaxed);
+    Ok(summary)
+}
+
+/// The row-by-row half of [`read_rows`], which owns it on the reader thread.
+struct RowReader<'a, P> {
+    reader: csv::Reader<Box<dyn Read + Send>>,
+    headers: csv::StringRecord,
+    parse: P,
+    source: &'a str,
+    opts: &'a ProcessOptions,
+    summary: ReadSummary,
+    row: csv::StringRecord,
+    bytes: csv::ByteRecord,
+    /// Data rows read so far, before `--nth`.
+    index: usize,
+}
+
+impl<T, P> RowReader<'_, P>
+where
+    P: FnMut(&csv::StringRecord) -> Result<T>,
+{
+    /// Up to [`PIPELINE_BATCH_ROWS`] parsed rows; empty once the input is exhausted.
+    fn next_batch(&mut self) -> Result<Vec<T>> {
+        let mut batch = Vec::with_capacity(PIPELINE_BATCH_ROWS);
+        while batch.len() < PIPELINE_BATCH_ROWS {
+            match self.next_row()? {
+                Some(record) => batch.push(record),
+                None => break,
+            }
+        }
+        Ok(batch)
+    }
+
+    fn next_row(&mut self) -> Result<Option<T>> {
+        let (opts, source) = (self.opts, self.source);
+        let nth = opts.nth.unwrap_or(1);
+        loop {
+            if opts.partial_on_interrupt && signals::interrupted() {
+                self.summary.interrupted = true;
+                return Ok(None);
+            }
+
+            let read = if opts.strict_utf8 {
+                self.reader.read_byte_record(&mut self.bytes)
+            } else {
+                self.reader.read_record(&mut self.row)
+            };
+            match read {
+                Ok(true) => {}
+                Ok(false) => return Ok(None),
+                Err(e) if opts.skip_bad_rows && !matches!(e.kind(), csv::ErrorKind::Io(_)) => {
+                    // The fields are still read into `row` when their count is wrong.
+                    let line = e.position().map_or(0, |p| p.line());
+                    let raw = if opts.strict_utf8 {
+                        lossy_join(&self.bytes)
+                    } else {
+                        self.row.iter().collect::<Vec<_>>().join(",")
+                    };
+                    self.summary
+                        .record_bad_row(line, e.to_string(), raw, opts.max_errors);
+                    continue;
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("Failed to deserialize one or more rows in '{source}'")
+                    })
+                }
+            }
+
+            // --nth keeps rows 0, N, 2N, ...; the others are never deserialized.
+            self.index += 1;
+            if !(self.index - 1).is_multiple_of(nth) {
+                self.summary.rows_skipped_by_nth += 1;
+                continue;
+            }
+
+            if opts.strict_utf8 {
+                match csv::StringRecord::from_byte_record(std::mem::take(&mut self.bytes)) {
+                    Ok(text) => self.row = text,
+                    Err(e) => {
+                        let (field, at) = (e.utf8_error().field(), e.utf8_error().valid_up_to());
+                        let bad = e.into_byte_record();
+                        let line = bad.position().map_or(0, |p| p.line());
+                        let message = format!(
+                            "Row {} (line {line}): {} is not valid UTF-8 after byte {at}",
+                            self.index,
+                            self.headers.get(field).unwrap_or("field")
+                        );
+                        if !opts.skip_bad_rows {
+                            anyhow::bail!("{message} in '{source}'");
+                        }
+                        self.summary.record_bad_row(
+                            line,
+                            message,
+                            lossy_join(&bad),
+                            opts.max_errors,
+                        );
+                        continue;
+                    }
+                }
+            }
+
+            match (self.parse)(&self.row) {
+                Ok(record) => return Ok(Some(record)),
+                Err(e) if opts.skip_bad_rows => {
+                    let line = self.row.position().map_or(0, |p| p.line());
+                    let raw = self.row.iter().collect::<Vec<_>>().join(",");
+                    self.summary
+                        .record_bad_row(line, e.to_string(), raw, opts.max_errors);
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("Failed to deserialize one or more rows in '{source}'")
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// `--header-ignore-case`: renames each header matching one of `names` ignoring case and
+/// surrounding spaces to that name, so the rest of the reader can look columns up exactly.
+/// Two headers matching the same name are ambiguous.
+fn match_headers_ignoring_case<'a>(
+    headers: &csv::StringRecord,
+    names: impl Iterator<Item = &'a str>,
+) -> Result<csv::StringRecord> {
+    let mut fields: Vec<String> = headers.iter().map(str::to_string).collect();
+    for name in names {
+        let matches: Vec<usize> = headers
+            .iter()
+            .enumerate()
+            .filter(|(_, h)| h.trim().eq_ignore_ascii_case(name.trim()))
+            .map(|(i, _)| i)
+            .collect();
+        match matches[..] {
+            [] => {}
+            [i] => fields[i] = name.to_string(),
+            _ => {
+                let found: Vec<String> = matches
+                    .iter()
+                    .map(|&i| format!("'{}'", &headers[i]))
+                    .collect();
+                anyhow::bail!(
+                    "columns {} all match '{name}' ignoring case",
+                    found.join(", ")
+                );
+            }
+        }
+    }
+    Ok(csv::StringRecord::from(fields))
+}
+
+fn lossy_join(record: &csv::ByteRecord) -> String {
+    record
+        .iter()
+        .map(String::from_utf8_lossy)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn parse_row(
+    row: &csv::StringRecord,
+    headers: &csv::StringRecord,
+    value_expr: Option<&BoundExpr>,
+) -> Result<Record> {
+    let Some(expr) = value_expr else {
+        return Ok(row.deserialize(Some(headers))?);
+    };
+    let keys: RowKeys = row.deserialize(Some(headers))?;
+    Ok(Record {
+        timestamp: keys.timestamp,
+        sensor_id: keys.sensor_id,
+        value: expr.eval(row)?,
+        time: None,
+    })
+}
+
+/// Positions of the [`OUTPUT_COLUMNS`] in the header row, for [`parse_row_fast`].
+fn fast_columns(headers: &csv::StringRecord) -> Result<[usize; 3]> {
+    let mut columns = [0; 3];
+    for (column, name) in columns.iter_mut().zip(OUTPUT_COLUMNS) {
+        *column = headers
+            .iter()
+            .position(|h| h == name)
+            .with_context(|| format!("Missing column '{name}' in the header row"))?;
+    }
+    Ok(columns)
+}
 
-    records.with_context(|| {
-        format!("Failed to deserialize one or more rows in '{}'", path.display())
+fn parse_row_fast(row: &csv::StringRecord, columns: [usize; 3]) -> Result<Record> {
+    let field = |i: usize| row.get(columns[i]).unwrap_or_default();
+    let value = field(2);
+    Ok(Record {
+        timestamp: field(0).to_string(),
+        sensor_id: field(1).to_string(),
+        value: fastfloat::parse(value)
+            .with_context(|| format!("field Value: invalid float literal '{value}'"))?,
+        time: None,
     })
 }
 
-fn compute_per_sensor_stats(records: &[Record], threshold: f64) -> Vec<SensorStats> {
-    use std::collections::HashMap;
-    use std::sync::Mutex;
+/// `first_row` is the number of rows before `records`, for the row numbers of errors.
+fn parse_timestamps(records: &mut [Record], first_row: usize, opts: &ProcessOptions) -> Result<()> {
+    records.par_iter_mut().enumerate().try_for_each(|(i, r)| {
+        let context = || {
+            format!(
+                "Row {}: cannot parse Timestamp '{}' (expected format: {})",
+                first_row + i + 1,
+                r.timestamp,
+                opts.timestamp_format
+            )
+        };
+        let shift_zone = opts.from_tz.is_some() || opts.to_tz.is_some();
+        let mut ts = if shift_zone {
+            let (ts, zoned) = opts
+                .timestamp_format
+                .parse_zoned(&r.timestamp)
+                .with_context(context)?;
+            // Only local times are in `from_tz`; the others already name an instant.
+            let utc = match (&opts.from_tz, zoned) {
+                (Some(zone), false) => zone.to_utc(ts),
+                _ => ts,
+            };
+            opts.to_tz.as_ref().map_or(utc, |zone| zone.to_local(utc))
+        } else {
+            opts.timestamp_format
+                .parse(&r.timestamp)
+                .with_context(context)?
+        };
+        // Truncation follows the conversion, so days and hours are those of `to_tz`.
+        if let Some(unit) = opts.truncate_timestamp {
+            ts = unit.truncate(ts);
+        }
+        if shift_zone || opts.truncate_timestamp.is_some() {
+            r.timestamp = timestamp::format_iso8601(ts);
+        }
+        r.time = Some(ts);
+        Ok(())
+    })
+}
+
+fn clamp_report(records: &[Record], min: Option<f64>, max: Option<f64>) -> ClampReport {
+    use std::collections::BTreeMap;
+
+    let mut report = ClampReport::default();
+    let mut by_sensor: BTreeMap<&str, ClampCount> = BTreeMap::new();
 
-    let map: Mutex<HashMap<String, Accumulator>> = Mutex::new(HashMap::new());
+    for r in records {
+        let below = min.is_some_and(|m| r.value < m);
+        let above = max.is_some_and(|m| r.value > m);
+        if !below && !above {
+            continue;
+        }
+        let count = by_sensor.entry(&r.sensor_id).or_default();
+        if below {
+            report.below += 1;
+            count.below += 1;
+        } else {
+            report.above += 1;
+            count.above += 1;
+        }
+    }
+
+    report.per_sensor = by_sensor
+        .into_iter()
+        .map(|(sensor_id, count)| ClampCount {
+            sensor_id: sensor_id.to_string(),
+            ..count
+        })
+        .collect();
+    report
+}
 
+fn apply_clamp(records: &mut [Record], min: Option<f64>, max: Option<f64>) {
+    let (min, max) = (
+        min.unwrap_or(f64::NEG_INFINITY),
+        max.unwrap_or(f64::INFINITY),
+    );
     records
+        .par_iter_mut()
+        .for_each(|r| r.value = r.value.clamp(min, max));
+}
+
+/// Snaps `value` to the nearest multiple of `step`; halfway values round away from zero.
+fn quantize(value: f64, step: f64) -> f64 {
+    (value / step).round() * step
+}
+
+fn round_to(value: f64, decimals: u32) -> f64 {
+    let scale = 10f64.powi(decimals as i32);
+    (value * scale).round() / scale
+}
+
+type SensorGroups<'a> = HashMap<&'a str, (Accumulator, HashSet<u64>)>;
+
+/// The `group_regex` group of each distinct sensor ID, `None` for sensors left out.
+type GroupKeys<'a> = HashMap<&'a str, Option<&'a str>>;
+
+/// Runs `group_regex` once per distinct sensor rather than once per row; empty without
+/// a regex.
+fn group_keys<'a>(records: &'a [Record], opts: &ProcessOptions) -> GroupKeys<'a> {
+    let mut keys = GroupKeys::new();
+    let Some(re) = &opts.group_regex else {
+        return keys;
+    };
+    for r in records {
+        if !keys.contains_key(r.sensor_id.as_str()) {
+            let key = match re.first_capture(&r.sensor_id) {
+                Some(group) => Some(group),
+                None if opts.skip_unmatched => None,
+                None => Some(NO_MATCH_GROUP),
+            };
+            keys.insert(&r.sensor_id, key);
+        }
+    }
+    keys
+}
+
+/// The breakdown group a record counts towards, or `None` if it is left out. `keys`
+/// comes from [`group_keys`] over the same records.
+fn breakdown_key<'a>(
+    r: &'a Record,
+    keys: &GroupKeys<'a>,
+    opts: &ProcessOptions,
+) -> Option<&'a str> {
+    if r.value <= opts.threshold
+        || opts
+            .only_sensors
+            .as_ref()
+            .is_some_and(|ids| !ids.contains(&r.sensor_id))
+    {
+        return None;
+    }
+    match &opts.group_regex {
+        None => Some(&r.sensor_id),
+        Some(_) => keys[r.sensor_id.as_str()],
+    }
+}
+
+fn add_to_group<'a>(
+    groups: &mut SensorGroups<'a>,
+    key: &'a str,
+    value: f64,
+    opts: &ProcessOptions,
+) {
+    let (acc, distinct) = groups.entry(key).or_default();
+    acc.add(value, opts.target);
+    if opts.distinct_values {
+        // -0.0 and 0.0 are the same reading.
+        distinct.insert(if value == 0.0 { 0 } else { value.to_bits() });
+    }
+}
+
+/// Each rayon job folds its records into its own map and the maps are merged pairwise,
+/// so no lock is shared; a shared `Mutex<HashMap>` serialised every row, worst of all
+/// on files sorted by sensor.
+fn compute_per_sensor_stats(records: &[Record], opts: &ProcessOptions) -> Vec<SensorStats> {
+    let keys = group_keys(records, opts);
+    let groups = records
         .par_iter()
-        .filter(|r| r.value > threshold)
-        .for_each(|r| {
-            let mut guard = map.lock().expect("mutex poisoned");
-            guard.entry(r.sensor_id.clone()).or_default().add(r.value);
+        .filter_map(|r| breakdown_key(r, &keys, opts).map(|key| (key, r.value)))
+        .fold(SensorGroups::new, |mut groups, (key, value)| {
+            add_to_group(&mut groups, key, value, opts);
+            groups
+        })
+        .reduce(SensorGroups::new, |a, b| {
+            let (mut into, from) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+            for (key, (acc, distinct)) in from {
+                let entry = into.entry(key).or_default();
+                entry.0 = std::mem::take(&mut entry.0).merge(acc);
+                entry.1.extend(distinct);
+            }
+            into
         });
+    per_sensor_from_groups(groups)
+}
 
-    let mut stats: Vec<SensorStats> = map
-        .into_inner()
-        .expect("mutex poisoned")
+fn per_sensor_from_groups(groups: SensorGroups) -> Vec<SensorStats> {
+    let mut stats: Vec<SensorStats> = groups
         .into_iter()
-        .map(|(sensor_id, acc)| SensorStats {
-            sensor_id,
+        .map(|(sensor_id, (acc, distinct))| SensorStats {
+            sensor_id: sensor_id.to_string(),
             count: acc.count,
             average: acc.sum / acc.count as f64,
+            min: acc.min,
+            max: acc.max,
+            range: acc.max - acc.min,
+            distinct_values: distinct.len(),
+            sem: acc.sem(),
+            target_deviation: acc.target_deviation(),
+            noise_floor: None,
         })
         .collect();
 
@@ -133,16 +1819,6 @@ fn compute_per_sensor_stats(records: &[Record], threshold: f64) -> Vec<SensorSta
     stats
 }
 
-fn print_sensor_table(stats: &[SensorStats]) {
-    println!();
-    println!("  {:<20} {:>10} {:>16}", "Sensor ID", "Row Count", "Average Value");
-    println!("  {:-<20} {:->10} {:->16}", "", "", "");
-    for s in stats {
-        println!("  {:<20} {:>10} {:>16.6}", s.sensor_id, s.count, s.average);
-    }
-    println!();
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -155,6 +1831,19 @@ mod tests {
         f
     }
 
+    fn read_file(path: &Path, opts: &ProcessOptions) -> Result<(Vec<Record>, ReadSummary)> {
+        let file = std::fs::File::open(path).expect("open");
+        read_csv(Box::new(file), &path.display().to_string(), opts)
+    }
+
+    fn opts(threshold: f64, verbose: bool) -> ProcessOptions {
+        ProcessOptions {
+            threshold,
+            verbose,
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_filter_and_average() {
         let csv = "\
@@ -165,7 +1854,7 @@ Timestamp,SensorID,Value
 2024-01-01T00:00:03,S3,30.0
 ";
         let file = make_temp_csv(csv);
-        let stats = process(file.path(), 50.0, false).expect("process");
+        let stats = process(file.path(), &opts(50.0, false)).expect("process");
 
         assert_eq!(stats.total_rows, 4);
         assert_eq!(stats.filtered_rows, 2);
@@ -181,7 +1870,7 @@ Timestamp,SensorID,Value
 2024-01-01T00:00:01,S2,2.0
 ";
         let file = make_temp_csv(csv);
-        let stats = process(file.path(), 100.0, false).expect("process");
+        let stats = process(file.path(), &opts(100.0, false)).expect("process");
 
         assert_eq!(stats.filtered_rows, 0);
         assert!(stats.average.is_none());
@@ -195,7 +1884,7 @@ Timestamp,SensorID,Value
 2024-01-01T00:00:01,S2,200.0
 ";
         let file = make_temp_csv(csv);
-        let stats = process(file.path(), 0.0, false).expect("process");
+        let stats = process(file.path(), &opts(0.0, false)).expect("process");
 
         assert_eq!(stats.total_rows, 2);
         assert_eq!(stats.filtered_rows, 2);
@@ -213,14 +1902,1260 @@ Timestamp,SensorID,Value
 2024-01-01T00:00:03,S2,10.0
 ";
         let file = make_temp_csv(csv);
-        let stats = process(file.path(), 50.0, true).expect("process");
+        let stats = process(file.path(), &opts(50.0, true)).expect("process");
 
         assert_eq!(stats.per_sensor.len(), 2);
-        let s1 = stats.per_sensor.iter().find(|s| s.sensor_id == "S1").unwrap();
+        let s1 = stats
+            .per_sensor
+            .iter()
+            .find(|s| s.sensor_id == "S1")
+            .unwrap();
         assert_eq!(s1.count, 2);
         assert!((s1.average - 70.0).abs() < 1e-9);
-        let s2 = stats.per_sensor.iter().find(|s| s.sensor_id == "S2").unwrap();
+        let s2 = stats
+            .per_sensor
+            .iter()
+            .find(|s| s.sensor_id == "S2")
+            .unwrap();
         assert_eq!(s2.count, 1);
         assert!((s2.average - 90.0).abs() < 1e-9);
     }
+
+    #[test]
+    fn test_truncate_timestamp_collapses_subsecond_readings() {
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00.120431,S1,10.0
+2024-01-01T00:00:00.987002,S1,20.0
+2024-01-01T00:00:01.000001,S1,30.0
+";
+        let file = make_temp_csv(csv);
+        let opts = ProcessOptions {
+            truncate_timestamp: Some(TimeUnit::Second),
+            ..Default::default()
+        };
+        let (records, _) = read_file(file.path(), &opts).expect("read");
+
+        assert_eq!(records[0].time, records[1].time);
+        assert_ne!(records[1].time, records[2].time);
+        assert_eq!(records[0].timestamp, "2024-01-01T00:00:00");
+    }
+
+    #[test]
+    fn test_skip_empty_lines_counts_blank_lines() {
+        let csv = "Timestamp,SensorID,Value\n\
+2024-01-01T00:00:00,S1,10.0\n\
+\n\
+   \n\
+2024-01-01T00:00:01,S2,30.0\n\
+\t\n\
+2024-01-01T00:00:02,S1,50.0\n";
+        let file = make_temp_csv(csv);
+
+        assert!(process(file.path(), &opts(0.0, false)).is_err());
+
+        let opts = ProcessOptions {
+            skip_empty_lines: true,
+            ..Default::default()
+        };
+        let stats = process(file.path(), &opts).expect("process");
+
+        assert_eq!(stats.total_rows, 3);
+        assert_eq!(stats.blank_lines_skipped, 3);
+        assert!((stats.average.unwrap() - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_skip_empty_lines_keeps_blank_lines_inside_quotes() {
+        let csv = "Timestamp,SensorID,Value,Note\n\
+2024-01-01T00:00:00,S1,10.0,\"first\n\
+\n\
+\"\"quoted\"\" last\"\n\
+\n\
+2024-01-01T00:00:01,S2,30.0,plain\n";
+        let file = make_temp_csv(csv);
+        let opts = ProcessOptions {
+            skip_empty_lines: true,
+            ..Default::default()
+        };
+        let stats = process(file.path(), &opts).expect("process");
+
+        assert_eq!(stats.total_rows, 2);
+        assert_eq!(stats.blank_lines_skipped, 1);
+    }
+
+    #[test]
+    fn test_per_sensor_range() {
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,S1,12.5
+2024-01-01T00:00:01,S1,40.0
+2024-01-01T00:00:02,S1,27.0
+2024-01-01T00:00:03,S2,55.0
+";
+        let file = make_temp_csv(csv);
+        let stats = process(file.path(), &opts(0.0, true)).expect("process");
+
+        let s1 = stats
+            .per_sensor
+            .iter()
+            .find(|s| s.sensor_id == "S1")
+            .unwrap();
+        assert_eq!(s1.min, 12.5);
+        assert_eq!(s1.max, 40.0);
+        assert!((s1.range - 27.5).abs() < 1e-9);
+        let s2 = stats
+            .per_sensor
+            .iter()
+            .find(|s| s.sensor_id == "S2")
+            .unwrap();
+        assert_eq!(s2.range, 0.0);
+    }
+
+    #[test]
+    fn test_nth_keeps_every_nth_row_before_filtering() {
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,S1,10.0
+2024-01-01T00:00:01,S1,20.0
+2024-01-01T00:00:02,S1,60.0
+2024-01-01T00:00:03,S1,40.0
+";
+        let file = make_temp_csv(csv);
+        let opts = ProcessOptions {
+            nth: Some(2),
+            threshold: 5.0,
+            ..Default::default()
+        };
+        let stats = process(file.path(), &opts).expect("process");
+
+        assert_eq!(stats.total_rows, 2);
+        assert_eq!(stats.rows_skipped_by_nth, 2);
+        assert_eq!(stats.filtered_rows, 2);
+        assert!((stats.average.unwrap() - 35.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_threshold_sweep_matches_individual_runs() {
+        let mut csv = String::from("Timestamp,SensorID,Value\n");
+        for i in 0..500 {
+            let value = (i * 37 % 101) as f64 / 10.0 - 2.0;
+            csv.push_str(&format!("2024-01-01T00:00:00,S{},{value}\n", i % 7));
+        }
+        let file = make_temp_csv(&csv);
+        let sweep: buckets::ThresholdSweep = "-2:8.5:0.5".parse().unwrap();
+        assert_eq!(sweep.0.len(), 22);
+        assert_eq!(sweep.0[5], 0.5);
+        let sweep_opts = ProcessOptions {
+            threshold_sweep: Some(sweep.0.clone()),
+            ..Default::default()
+        };
+        let swept = process(file.path(), &sweep_opts)
+            .expect("process")
+            .threshold_sweep;
+        assert_eq!(swept.len(), sweep.0.len());
+
+        for row in swept {
+            let single = process(file.path(), &opts(row.threshold, false)).expect("process");
+            assert_eq!(
+                row.filtered_rows, single.filtered_rows,
+                "at {}",
+                row.threshold
+            );
+            match (row.average, single.average) {
+                (Some(a), Some(b)) => assert!((a - b).abs() < 1e-9, "at {}", row.threshold),
+                (a, b) => assert_eq!(a, b, "at {}", row.threshold),
+            }
+        }
+    }
+
+    #[test]
+    fn test_roster_db_restricts_processed_sensors() {
+        use crate::sqlite::{self, Value};
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("meta.db");
+        sqlite::write_test_db(
+            &db,
+            "CREATE TABLE sensors (id INTEGER PRIMARY KEY, sensor_id TEXT NOT NULL)",
+            "sensors",
+            &[
+                vec![Value::Null, Value::Text("S1".to_string())],
+                vec![Value::Null, Value::Text("S3".to_string())],
+            ],
+        );
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,S1,10.0
+2024-01-01T00:00:00,S2,20.0
+2024-01-01T00:00:00,S3,30.0
+2024-01-01T00:00:01,S2,40.0
+";
+        let file = make_temp_csv(csv);
+        let roster = sqlite::read_column(&db, "sensors", "sensor_id").unwrap();
+        let opts = ProcessOptions {
+            roster: Some(roster.into_iter().collect()),
+            verbose: true,
+            ..Default::default()
+        };
+        let stats = process(file.path(), &opts).expect("process");
+
+        assert_eq!((stats.total_rows, stats.rows_outside_roster), (2, Some(2)));
+        let sensors: Vec<&str> = stats
+            .per_sensor
+            .iter()
+            .map(|s| s.sensor_id.as_str())
+            .collect();
+        assert_eq!(sensors, ["S1", "S3"]);
+        assert_eq!(stats.average, Some(20.0));
+    }
+
+    #[test]
+    fn test_clamp_report_counts_without_modifying_values() {
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,S1,-5.0
+2024-01-01T00:00:01,S1,50.0
+2024-01-01T00:00:02,S2,150.0
+2024-01-01T00:00:03,S2,120.0
+";
+        let file = make_temp_csv(csv);
+        let mut opts = ProcessOptions {
+            threshold: -100.0,
+            clamp_min: Some(0.0),
+            clamp_max: Some(100.0),
+            clamp_report: true,
+            ..Default::default()
+        };
+        let stats = process(file.path(), &opts).expect("process");
+
+        let report = stats.clamp.expect("clamp report");
+        assert_eq!((report.below, report.above), (1, 2));
+        assert_eq!(report.per_sensor.len(), 2);
+        assert_eq!(report.per_sensor[0].sensor_id, "S1");
+        assert_eq!(
+            (report.per_sensor[0].below, report.per_sensor[0].above),
+            (1, 0)
+        );
+        assert_eq!(
+            (report.per_sensor[1].below, report.per_sensor[1].above),
+            (0, 2)
+        );
+        assert!((stats.average.unwrap() - 78.75).abs() < 1e-9);
+
+        opts.clamp_report = false;
+        let stats = process(file.path(), &opts).expect("process");
+        assert!((stats.average.unwrap() - 62.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_merge_duplicates_before_filtering() {
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,S1,40.0
+2024-01-01T00:00:00,S1,80.0
+2024-01-01T00:00:01,S1,20.0
+";
+        let file = make_temp_csv(csv);
+        let opts = ProcessOptions {
+            threshold: 50.0,
+            merge_duplicates: Some(MergePolicy::Average),
+            ..Default::default()
+        };
+        let stats = process(file.path(), &opts).expect("process");
+
+        assert_eq!(stats.duplicate_groups_merged, 1);
+        assert_eq!(stats.filtered_rows, 1);
+        assert!((stats.average.unwrap() - 60.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_timestamp_format_applies_when_reading() {
+        let csv = "\
+Timestamp,SensorID,Value
+1704067200,S1,10.0
+1704067201,S1,20.0
+";
+        let file = make_temp_csv(csv);
+        let opts = ProcessOptions {
+            truncate_timestamp: Some(TimeUnit::Minute),
+            timestamp_format: TimestampFormat::Epoch,
+            ..Default::default()
+        };
+        let (records, _) = read_file(file.path(), &opts).expect("read");
+        assert_eq!(records[1].timestamp, "2024-01-01T00:00:00");
+
+        let csv = "\
+Timestamp,SensorID,Value
+01/01/2024 10:00,S1,10.0
+2024-01-01 10:01,S1,20.0
+";
+        let file = make_temp_csv(csv);
+        let opts = ProcessOptions {
+            truncate_timestamp: Some(TimeUnit::Minute),
+            timestamp_format: "%d/%m/%Y %H:%M".parse().unwrap(),
+            ..Default::default()
+        };
+        let err = format!("{:#}", read_file(file.path(), &opts).unwrap_err());
+        assert!(err.contains("Row 2"), "{err}");
+        assert!(err.contains("%d/%m/%Y %H:%M"), "{err}");
+    }
+
+    #[test]
+    fn test_quantize_to_nearest_half() {
+        assert_eq!(quantize(1.26, 0.5), 1.5);
+        assert_eq!(quantize(1.24, 0.5), 1.0);
+        assert_eq!(quantize(-0.8, 0.5), -1.0);
+
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,S1,1.2
+2024-01-01T00:00:01,S1,2.7
+2024-01-01T00:00:02,S1,0.2
+";
+        let file = make_temp_csv(csv);
+        let opts = ProcessOptions {
+            quantize: Some(0.5),
+            threshold: 0.1,
+            ..Default::default()
+        };
+        let stats = process(file.path(), &opts).expect("process");
+
+        // 1.2 -> 1.0, 2.7 -> 2.5, 0.2 -> 0.0 (then removed by the threshold)
+        assert_eq!(stats.filtered_rows, 2);
+        assert!((stats.average.unwrap() - 1.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_warnings_fail_only_with_abort() {
+        let file = make_temp_csv("Timestamp,SensorID,Value\n2024-01-01T00:00:00,S1,150\n");
+        let opts = ProcessOptions {
+            percent: true,
+            ..Default::default()
+        };
+        let stats = process(file.path(), &opts).expect("process");
+        assert!(stats.warnings.emit(false).is_ok());
+        let err = stats.warnings.emit(true).unwrap_err().to_string();
+        assert!(err.contains("1 warning(s)"), "{err}");
+        assert!(err.contains("outside the 0-100% range"), "{err}");
+
+        let file = make_temp_csv("Timestamp,SensorID,Value\n2024-01-01T00:00:00,S1,50\n");
+        let stats = process(file.path(), &opts).expect("process");
+        assert!(stats.warnings.is_empty());
+        assert!(stats.warnings.emit(true).is_ok());
+    }
+
+    #[test]
+    fn test_percent_flags_out_of_range_and_renormalizes() {
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,S1,150
+2024-01-01T00:00:01,S2,50
+";
+        let file = make_temp_csv(csv);
+        let mut opts = ProcessOptions {
+            percent: true,
+            threshold: -1.0,
+            verbose: true,
+            ..Default::default()
+        };
+        let stats = process(file.path(), &opts).expect("process");
+        assert_eq!(stats.percent_out_of_range, Some(1));
+        assert_eq!(stats.per_sensor[0].max, 150.0);
+
+        opts.renormalize = true;
+        let stats = process(file.path(), &opts).expect("process");
+        assert_eq!(stats.percent_out_of_range, Some(1));
+        assert!((stats.per_sensor[0].max - 1.5).abs() < 1e-12);
+        assert!((stats.per_sensor[1].max - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_max_per_sensor_caps_chatty_sensor() {
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:04,S1,100.0
+2024-01-01T00:00:00,S1,10.0
+2024-01-01T00:00:01,S1,20.0
+2024-01-01T00:00:02,S1,100.0
+2024-01-01T00:00:03,S1,100.0
+2024-01-01T00:00:00,S2,30.0
+";
+        let file = make_temp_csv(csv);
+        let opts = ProcessOptions {
+            max_per_sensor: Some(2),
+            ..opts(0.0, false)
+        };
+        let stats = process(file.path(), &opts).expect("process");
+
+        assert_eq!(stats.filtered_rows, 3);
+        assert!((stats.average.unwrap() - 20.0).abs() < 1e-9);
+        let capped = stats.capped.unwrap();
+        assert_eq!((capped[0].sensor_id.as_str(), capped[0].dropped), ("S1", 3));
+    }
+
+    #[test]
+    fn test_group_regex_merges_sensors_by_floor() {
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,floor3-room12-temp,10.0
+2024-01-01T00:00:00,floor3-room14-temp,20.0
+2024-01-01T00:00:00,floor4-room1-temp,40.0
+2024-01-01T00:00:00,roof-temp,5.0
+";
+        let file = make_temp_csv(csv);
+        let mut opts = ProcessOptions {
+            group_regex: Some(Regex::new(r"^(floor\d+)-").unwrap()),
+            ..opts(0.0, true)
+        };
+        let stats = process(file.path(), &opts).expect("process");
+        let groups: Vec<(&str, usize, f64)> = stats
+            .per_sensor
+            .iter()
+            .map(|s| (s.sensor_id.as_str(), s.count, s.average))
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                (NO_MATCH_GROUP, 1, 5.0),
+                ("floor3", 2, 15.0),
+                ("floor4", 1, 40.0)
+            ]
+        );
+
+        opts.skip_unmatched = true;
+        let stats = process(file.path(), &opts).expect("process");
+        assert_eq!(stats.per_sensor.len(), 2);
+        assert_eq!(stats.filtered_rows, 4);
+    }
+
+    #[test]
+    fn test_only_sensors_limits_breakdown_not_global() {
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,S1,10.0
+2024-01-01T00:00:00,S2,20.0
+2024-01-01T00:00:00,S3,30.0
+2024-01-01T00:00:00,S4,40.0
+";
+        let file = make_temp_csv(csv);
+        let opts = ProcessOptions {
+            only_sensors: Some(["S1".to_string(), "S3".to_string()].into()),
+            ..opts(0.0, true)
+        };
+        let stats = process(file.path(), &opts).expect("process");
+
+        assert!((stats.average.unwrap() - 25.0).abs() < 1e-9);
+        let ids: Vec<&str> = stats
+            .per_sensor
+            .iter()
+            .map(|s| s.sensor_id.as_str())
+            .collect();
+        assert_eq!(ids, ["S1", "S3"]);
+    }
+
+    #[test]
+    fn test_value_expr_averages_derived_values() {
+        let csv = "\
+Timestamp,SensorID,systolic,diastolic
+2024-01-01T00:00:00,P1,120,80
+2024-01-01T00:00:01,P1,130,85
+2024-01-01T00:00:02,P2,110,75
+";
+        let file = make_temp_csv(csv);
+        let opts = ProcessOptions {
+            value_expr: Some("systolic - diastolic".parse().unwrap()),
+            verbose: true,
+            ..Default::default()
+        };
+        let stats = process(file.path(), &opts).expect("process");
+        assert_eq!(stats.total_rows, 3);
+        assert!((stats.average.unwrap() - (40.0 + 45.0 + 35.0) / 3.0).abs() < 1e-9);
+        assert_eq!(stats.per_sensor[0].average, 42.5);
+
+        let missing = ProcessOptions {
+            value_expr: Some("systolic - pulse".parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(process(file.path(), &missing).is_err());
+    }
+
+    #[test]
+    fn test_categorical_counts_per_sensor() {
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,S1,OK
+2024-01-01T00:00:01,S1,FAIL
+2024-01-01T00:00:02,S1,OK
+2024-01-01T00:00:03,S2,FAIL
+";
+        let file = make_temp_csv(csv);
+        let opts = ProcessOptions {
+            categorical: true,
+            ..Default::default()
+        };
+        let stats = process(file.path(), &opts).expect("process");
+
+        assert_eq!(stats.total_rows, 4);
+        assert_eq!(stats.average, None);
+        let counts = |i: usize| -> Vec<(&str, usize)> {
+            stats.categories[i]
+                .counts
+                .iter()
+                .map(|(c, n)| (c.as_str(), *n))
+                .collect()
+        };
+        assert_eq!(stats.categories[0].sensor_id, None);
+        assert_eq!(counts(0), [("FAIL", 2), ("OK", 2)]);
+        assert_eq!(stats.categories[1].sensor_id.as_deref(), Some("S1"));
+        assert_eq!(counts(1), [("OK", 2), ("FAIL", 1)]);
+        assert_eq!(counts(2), [("FAIL", 1)]);
+    }
+
+    #[test]
+    fn test_chunks_and_merged_total() {
+        let input = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,S1,10
+2024-01-01T00:00:01,S2,20
+#END
+Timestamp,SensorID,Value
+2024-01-01T00:00:02,S1,40
+2024-01-01T00:00:03,S1,-5
+#END
+";
+        let chunks =
+            process_chunks(input.as_bytes(), "#END", "stdin", &opts(0.0, true)).expect("process");
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].total_rows, chunks[0].average), (2, Some(15.0)));
+        assert_eq!((chunks[1].total_rows, chunks[1].average), (2, Some(40.0)));
+
+        let total = chunks.into_iter().reduce(ProcessingStats::merge).unwrap();
+        assert_eq!((total.total_rows, total.filtered_rows), (4, 3));
+        assert!((total.average.unwrap() - 70.0 / 3.0).abs() < 1e-9);
+        let s1 = &total.per_sensor[0];
+        assert_eq!(
+            (s1.sensor_id.as_str(), s1.count, s1.average),
+            ("S1", 2, 25.0)
+        );
+        assert_eq!((s1.min, s1.max, s1.range), (10.0, 40.0, 30.0));
+        assert_eq!(total.per_sensor[1].count, 1);
+    }
+
+    #[test]
+    fn test_quality_score_separates_clean_and_flatlined_sensors() {
+        // S1 ramps smoothly every minute for 30 minutes; S2 sends the same value five
+        // times over 20 minutes.
+        let mut csv = String::from("Timestamp,SensorID,Value\n");
+        for minute in 0..30 {
+            csv.push_str(&format!(
+                "2024-01-01T00:{minute:02}:00,S1,{}\n",
+                20 + minute
+            ));
+        }
+        for minute in [0, 1, 2, 3, 20] {
+            csv.push_str(&format!("2024-01-01T00:{minute:02}:00,S2,5\n"));
+        }
+        let file = make_temp_csv(&csv);
+        let options = ProcessOptions {
+            expected_interval: Some(60 * timestamp::MICROS_PER_SECOND),
+            flatline_duration: Some(120 * timestamp::MICROS_PER_SECOND),
+            flatline_epsilon: 1e-9,
+            quality_weights: Some(QualityWeights::default()),
+            min_quality: 50.0,
+            ..opts(0.0, false)
+        };
+        let stats = process(file.path(), &options).expect("process");
+
+        let [clean, flat] = &stats.quality[..] else {
+            panic!("{:?}", stats.quality);
+        };
+        assert_eq!(clean.sensor_id, "S1");
+        assert_eq!(
+            (clean.coverage, clean.noise, clean.flatline),
+            (1.0, 1.0, 1.0)
+        );
+        assert_eq!(clean.score, 100.0);
+        // 5 of 21 slots, no noise, every reading in a flatline run.
+        assert_eq!((flat.noise, flat.flatline), (1.0, 0.0));
+        assert!((flat.score - 100.0 * (5.0 / 21.0 + 1.0) / 3.0).abs() < 1e-9);
+        let low: Vec<&str> = stats
+            .warnings
+            .iter()
+            .filter(|w| w.contains("quality"))
+            .collect();
+        assert_eq!(low.len(), 1);
+        assert!(low[0].contains("'S2'"), "{low:?}");
+    }
+
+    #[test]
+    fn test_iqr_filter_drops_values_beyond_fences() {
+        // S1: Q1 11.25, Q3 13.75, so the fences at K = 1.5 are 7.5 and 17.5. S2's 100 is
+        // within its own fences.
+        let file = make_temp_csv(
+            "Timestamp,SensorID,Value\n\
+             t,S1,10\nt,S1,11\nt,S1,12\nt,S1,13\nt,S1,14\nt,S1,100\n\
+             t,S2,90\nt,S2,100\nt,S2,110\n",
+        );
+        let options = ProcessOptions {
+            iqr_filter: Some(1.5),
+            ..opts(0.0, true)
+        };
+        let stats = process(file.path(), &options).expect("process");
+        assert_eq!(stats.iqr_outliers, Some(1));
+        assert_eq!(stats.filtered_rows, 8);
+        let s1 = &stats.per_sensor[0];
+        assert_eq!((s1.count, s1.average, s1.max), (5, 12.0, 14.0));
+        assert_eq!(stats.per_sensor[1].count, 3);
+
+        let report_only = ProcessOptions {
+            iqr_report: true,
+            ..options
+        };
+        let stats = process(file.path(), &report_only).expect("process");
+        assert_eq!((stats.iqr_outliers, stats.filtered_rows), (Some(1), 9));
+    }
+
+    #[test]
+    fn test_cross_file_dedup_counts_boundary_row_once() {
+        let input = "\
+Timestamp,SensorID,Value
+2024-01-01T00:59:00,S1,10
+2024-01-01T01:00:00,S1,20
+#END
+Timestamp,SensorID,Value
+2024-01-01T01:00:00,S1,20
+2024-01-01T01:00:00,S2,20
+2024-01-01T01:01:00,S1,30
+#END
+";
+        let options = ProcessOptions {
+            cross_file_dedup: true,
+            ..opts(0.0, true)
+        };
+        let chunks = process_chunks(input.as_bytes(), "#END", "stdin", &options).expect("process");
+        assert_eq!(chunks[0].cross_file_duplicates, Some(0));
+        assert_eq!(chunks[1].cross_file_duplicates, Some(1));
+
+        let total = chunks.into_iter().reduce(ProcessingStats::merge).unwrap();
+        assert_eq!((total.total_rows, total.filtered_rows), (5, 4));
+        assert_eq!(total.cross_file_duplicates, Some(1));
+        assert_eq!(total.per_sensor[0].count, 3);
+        assert_eq!(total.average, Some(20.0));
+
+        // Without the flag the boundary row counts in both chunks.
+        let chunks =
+            process_chunks(input.as_bytes(), "#END", "stdin", &opts(0.0, true)).expect("process");
+        let total = chunks.into_iter().reduce(ProcessingStats::merge).unwrap();
+        assert_eq!(
+            (total.filtered_rows, total.cross_file_duplicates),
+            (5, None)
+        );
+    }
+
+    /// The shared-mutex grouping `compute_per_sensor_stats` used to do.
+    fn per_sensor_with_mutex(records: &[Record], opts: &ProcessOptions) -> Vec<SensorStats> {
+        let groups = std::sync::Mutex::new(SensorGroups::new());
+        let keys = group_keys(records, opts);
+        records.par_iter().for_each(|r| {
+            if let Some(key) = breakdown_key(r, &keys, opts) {
+                add_to_group(&mut groups.lock().unwrap(), key, r.value, opts);
+            }
+        });
+        per_sensor_from_groups(groups.into_inner().unwrap())
+    }
+
+    #[test]
+    fn test_fold_reduce_matches_mutex_grouping() {
+        // Clustered like a file sorted by sensor, with one sensor owning most rows. Values
+        // are multiples of 0.25 so sums are exact in any order.
+        let records: Vec<Record> = (0..20_000)
+            .map(|i| Record {
+                timestamp: String::new(),
+                sensor_id: format!("S{}", if i < 15_000 { 0 } else { i / 1_000 }),
+                value: (i % 97) as f64 * 0.25 - 2.0,
+                time: None,
+            })
+            .collect();
+        let opts = ProcessOptions {
+            distinct_values: true,
+            ..opts(0.0, true)
+        };
+
+        let mut folded = compute_per_sensor_stats(&records, &opts);
+        let mut locked = per_sensor_with_mutex(&records, &opts);
+        assert_eq!(folded.len(), 6);
+        // The variance depends on the order values are combined in, in the last bits.
+        for (a, b) in folded.iter_mut().zip(&mut locked) {
+            let (x, y) = (a.sem.take().unwrap(), b.sem.take().unwrap());
+            assert!((x - y).abs() < 1e-12 * y, "{x} vs {y}");
+        }
+        assert_eq!(format!("{folded:?}"), format!("{locked:?}"));
+    }
+
+    #[test]
+    fn test_round_values_feeds_value_buckets() {
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,S1,0.98
+2024-01-01T00:00:01,S1,1.04
+2024-01-01T00:00:02,S1,2.01
+";
+        let file = make_temp_csv(csv);
+        let opts = ProcessOptions {
+            threshold: -1.0,
+            round_values: Some(0),
+            value_buckets: Some(BucketScope::Sensor),
+            max_buckets: 10,
+            ..Default::default()
+        };
+        let stats = process(file.path(), &opts).expect("process");
+        assert_eq!(stats.value_buckets[0].counts, [(1.0, 2), (2.0, 1)]);
+        assert_eq!(round_to(1.23456, 2), 1.23);
+    }
+
+    #[test]
+    fn test_distinct_values_per_sensor() {
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,S1,5.0
+2024-01-01T00:00:01,S1,5.0
+2024-01-01T00:00:02,S1,5.01
+2024-01-01T00:00:03,S1,7.0
+2024-01-01T00:00:04,S1,8.0
+2024-01-01T00:00:05,S2,3.0
+2024-01-01T00:00:06,S2,3.0
+";
+        let file = make_temp_csv(csv);
+        let opts = ProcessOptions {
+            distinct_values: true,
+            ..opts(0.0, true)
+        };
+        let stats = process(file.path(), &opts).expect("process");
+        let distinct: Vec<usize> = stats.per_sensor.iter().map(|s| s.distinct_values).collect();
+        assert_eq!(distinct, [4, 1]);
+
+        let rounded = ProcessOptions {
+            round_values: Some(0),
+            ..opts
+        };
+        let stats = process(file.path(), &rounded).expect("process");
+        assert_eq!(stats.per_sensor[0].distinct_values, 3);
+    }
+
+    #[test]
+    fn test_sem_is_std_over_sqrt_count() {
+        let mut csv = String::from("Timestamp,SensorID,Value\n");
+        for (i, v) in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0].iter().enumerate() {
+            csv.push_str(&format!("2024-01-01T00:00:{i:02},S1,{v}\n"));
+        }
+        csv.push_str("2024-01-01T00:00:10,S2,3.0\n");
+        let file = make_temp_csv(&csv);
+        let stats = process(file.path(), &opts(0.0, true)).expect("process");
+
+        let std = (32.0f64 / 7.0).sqrt();
+        let sem = stats.per_sensor[0].sem.expect("S1 has a SEM");
+        assert!((sem - std / 8.0f64.sqrt()).abs() < 1e-12, "got {sem}");
+        assert_eq!(stats.per_sensor[1].sem, None);
+    }
+
+    #[test]
+    fn test_target_deviation_mad_and_rmsd() {
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,S1,9.0
+2024-01-01T00:00:01,S1,11.0
+2024-01-01T00:00:02,S1,13.0
+2024-01-01T00:00:03,S2,12.0
+2024-01-01T00:00:04,S2,12.0
+";
+        let file = make_temp_csv(csv);
+        let options = ProcessOptions {
+            target: Some(10.0),
+            ..opts(0.0, true)
+        };
+        let stats = process(file.path(), &options).expect("process");
+
+        let close = |d: TargetDeviation, mad: f64, rmsd: f64| {
+            (d.mad - mad).abs() < 1e-12 && (d.rmsd - rmsd).abs() < 1e-12
+        };
+        let s1 = stats.per_sensor[0].target_deviation.expect("S1 deviation");
+        assert!(close(s1, 5.0 / 3.0, (11.0f64 / 3.0).sqrt()), "{s1:?}");
+        let s2 = stats.per_sensor[1].target_deviation.expect("S2 deviation");
+        assert!(close(s2, 2.0, 2.0), "{s2:?}");
+        let global = stats.target_deviation.expect("global deviation");
+        assert!(
+            close(global, 9.0 / 5.0, (19.0f64 / 5.0).sqrt()),
+            "{global:?}"
+        );
+
+        // Merging two runs gives the deviation of the combined data.
+        let merged = stats.clone().merge(stats);
+        let twice = merged.target_deviation.expect("merged deviation");
+        assert!(close(twice, global.mad, global.rmsd), "{twice:?}");
+        let s1 = merged.per_sensor[0].target_deviation.expect("merged S1");
+        assert!(close(s1, 5.0 / 3.0, (11.0f64 / 3.0).sqrt()), "{s1:?}");
+    }
+
+    #[test]
+    fn test_skip_bad_rows_writes_error_json() {
+        let csv = "\
+Timestamp,SensorID,Value
+2024-01-01T00:00:00,S1,10.0
+2024-01-01T00:00:01,S1,oops
+2024-01-01T00:00:02,S2,30.0
+2024-01-01T00:00:03,S2
+2024-01-01T00:00:04,S2,50.0
+";
+        let file = make_temp_csv(csv);
+        let errors = NamedTempFile::new().expect("tmp file");
+        let opts = ProcessOptions {
+            skip_bad_rows: true,
+            errors_to: Some(errors.path().to_path_buf()),
+            max_errors: 10,
+            ..Default::default()
+        };
+        let stats = process(file.path(), &opts).expect("process");
+
+        assert_eq!(stats.total_rows, 3);
+        assert_eq!(stats.bad_rows_skipped, 2);
+        let lines: Vec<u64> = stats.row_errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [3, 5]);
+
+        let written = std::fs::read_to_string(errors.path()).unwrap();
+        assert!(written.starts_with("[\n  {\"line\": 3, \"error\": "));
+        assert!(written.contains("\"raw\": \"2024-01-01T00:00:01,S1,oops\""));
+        assert!(written.contains("{\"line\": 5, "));
+        assert!(written.contains("\"raw\": \"2024-01-01T00:00:03,S2\""));
+        assert_eq!(written.matches("\"line\"").count(), 2);
+
+        let capped = ProcessOptions {
+            skip_bad_rows: true,
+            max_errors: 1,
+            ..Default::default()
+        };
+        let stats = process(file.path(), &capped).expect("process");
+        assert_eq!(stats.bad_rows_skipped, 2);
+        assert_eq!(stats.row_errors.len(), 1);
+    }
+
+    #[test]
+    fn test_header_ignore_case() {
+        let file = make_temp_csv("timestamp, sensorid ,VALUE\nt,S1,10\nt,S2,30\n");
+        assert!(process(file.path(), &opts(0.0, false)).is_err());
+
+        let options = ProcessOptions {
+            header_ignore_case: true,
+            ..opts(0.0, false)
+        };
+        let stats = process(file.path(), &options).expect("process");
+        assert_eq!((stats.filtered_rows, stats.average), (2, Some(20.0)));
+
+        let ambiguous = make_temp_csv("Timestamp,SensorID,Value,value\nt,S1,10,11\n");
+        let err = format!("{:#}", process(ambiguous.path(), &options).unwrap_err());
+        assert!(
+            err.contains("columns 'Value', 'value' all match 'Value'"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_pipeline_matches_sequential_read() {
+        // Several batches, with bad rows spread across them.
+        let mut csv = String::from("Timestamp,SensorID,Value\n");
+        for i in 0..50_000 {
+            if i % 9_000 == 17 {
+                csv.push_str("2024-01-01T00:00:00,S1,not-a-number\n");
+            }
+            csv.push_str(&format!(
+                "2024-01-01T00:{:02}:{:02},S{},{}.{}\n",
+                i / 60 % 60,
+                i % 60,
+                i % 13,
+                i % 997,
+                i % 7
+            ));
+        }
+        let file = make_temp_csv(&csv);
+
+        let options = ProcessOptions {
+            skip_bad_rows: true,
+            max_errors: 100,
+            truncate_timestamp: Some(TimeUnit::Minute),
+            nth: Some(3),
+            ..opts(100.0, true)
+        };
+        let pipelined = process(file.path(), &options).expect("pipelined");
+        let options = ProcessOptions {
+            no_pipeline: true,
+            ..options
+        };
+        let sequential = process(file.path(), &options).expect("sequential");
+        // --nth never deserializes four of the six.
+        assert_eq!(pipelined.bad_rows_skipped, 2);
+        assert_eq!(format!("{pipelined:?}"), format!("{sequential:?}"));
+
+        // A row that fails later, in the consumer, is reported with its row number.
+        let csv = format!("{csv}2024-01-01T01:00:00,S1,5\nyesterday,S1,5\n");
+        let file = make_temp_csv(&csv);
+        let err = process(file.path(), &options).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("Row 16668: cannot parse Timestamp 'yesterday'"),
+            "{err:#}"
+        );
+        let options = ProcessOptions {
+            no_pipeline: false,
+            ..options
+        };
+        let err = process(file.path(), &options).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("Row 16668: cannot parse Timestamp 'yesterday'"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn test_fast_float_matches_default_parsing() {
+        let mut csv = String::from("SensorID,Value,Timestamp\n");
+        let mut state = 42u64;
+        for i in 0..2_000 {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1);
+            let v = (state >> 11) as f64 / (1u64 << 53) as f64 * 2e3 - 1e3;
+            let value = match i % 4 {
+                0 => format!("{v}"),
+                1 => format!("{v:.2}"),
+                2 => format!("{v:e}"),
+                _ => format!("{}", v * 1e-30),
+            };
+            csv.push_str(&format!("S{},{value},2024-01-01T00:00:00\n", i % 7));
+        }
+        let file = make_temp_csv(&csv);
+
+        let default = process(file.path(), &opts(0.0, true)).expect("default");
+        let fast = ProcessOptions {
+            fast_float: true,
+            ..opts(0.0, true)
+        };
+        let fast = process(file.path(), &fast).expect("fast");
+        assert_eq!(format!("{fast:?}"), format!("{default:?}"));
+
+        let bad = make_temp_csv("Timestamp,SensorID,Value\nt,S1,12a\n");
+        let options = ProcessOptions {
+            fast_float: true,
+            ..opts(0.0, true)
+        };
+        let err = format!("{:#}", process(bad.path(), &options).unwrap_err());
+        assert!(err.contains("invalid float literal '12a'"), "{err}");
+    }
+
+    #[test]
+    fn test_autodetect_semicolon_delimiter() {
+        // Decimal commas must not make the comma look like the delimiter.
+        let csv = "\
+Timestamp;SensorID;Value;Note
+2024-01-01T00:00:00;S1;10;\"a;b\"
+2024-01-01T00:00:01;S1;20;x,y
+2024-01-01T00:00:02;S2;30;
+";
+        let file = make_temp_csv(csv);
+        let options = ProcessOptions {
+            autodetect: true,
+            ..opts(0.0, true)
+        };
+        let stats = process(file.path(), &options).expect("process");
+        assert_eq!(stats.filtered_rows, 3);
+        assert_eq!(stats.average, Some(20.0));
+        assert!(stats.warnings.is_empty());
+
+        // Tied candidates, or none at all, fall back to a comma.
+        assert_eq!(input::sniff_delimiter(b"a,b;c\n1,2;3\n"), None);
+        assert_eq!(input::sniff_delimiter(b"Value\n1\n"), None);
+        assert_eq!(input::sniff_delimiter(b"a\tb\n1\t2\n"), Some(b'\t'));
+    }
+
+    #[test]
+    fn test_strict_utf8_names_row_and_column() {
+        let mut file = NamedTempFile::new().expect("tmp file");
+        file.write_all(
+            b"Timestamp,SensorID,Value\n\
+              2024-01-01T00:00:00,S1,1.0\n\
+              2024-01-01T00:00:01,S\xff2,2.0\n",
+        )
+        .expect("write");
+        let opts = ProcessOptions {
+            strict_utf8: true,
+            ..Default::default()
+        };
+        let err = process(file.path(), &opts).unwrap_err().to_string();
+        assert!(
+            err.starts_with("Row 2 (line 3): SensorID is not valid UTF-8 after byte 1"),
+            "{err}"
+        );
+
+        let skipping = ProcessOptions {
+            skip_bad_rows: true,
+            max_errors: 10,
+            ..opts
+        };
+        let stats = process(file.path(), &skipping).expect("process");
+        assert_eq!((stats.total_rows, stats.bad_rows_skipped), (1, 1));
+        assert_eq!(
+            stats.row_errors[0].raw,
+            "2024-01-01T00:00:01,S\u{fffd}2,2.0"
+        );
+    }
+
+    #[test]
+    fn test_write_buffer_size_does_not_change_output() {
+        let mut csv = String::from("Timestamp,SensorID,Value\n");
+        for i in 0..2_000 {
+            csv.push_str(&format!(
+                "2024-01-01T00:{:02}:{:02},S{},{}.25\n",
+                i / 60 % 60,
+                i % 60,
+                i % 7,
+                i
+            ));
+        }
+        let file = make_temp_csv(&csv);
+
+        let run = |buffer_size: usize| {
+            let out = NamedTempFile::new().expect("tmp file");
+            let opts = ProcessOptions {
+                transform: Some(Transform::RollingSum(3)),
+                output: Some(out.path().to_path_buf()),
+                write: WriteOptions {
+                    buffer_size,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            process(file.path(), &opts).expect("process");
+            std::fs::read(out.path()).unwrap()
+        };
+
+        let default = run(WriteOptions::default().buffer_size);
+        assert!(default.len() > 4 * 1024);
+        assert_eq!(run(4 * 1024), default);
+        assert_eq!(run(4 * 1024 * 1024), default);
+    }
+
+    /// Hands out one line per `read` call and simulates Ctrl-C while handing out line
+    /// `interrupt_at` (0 is the header).
+    struct InterruptingReader {
+        lines: Vec<String>,
+        next: usize,
+        interrupt_at: usize,
+    }
+
+    impl Read for InterruptingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some(line) = self.lines.get(self.next) else {
+                return Ok(0);
+            };
+            if self.next == self.interrupt_at {
+                signals::set_interrupted(true);
+            }
+            self.next += 1;
+            buf[..line.len()].copy_from_slice(line.as_bytes());
+            Ok(line.len())
+        }
+    }
+
+    #[test]
+    fn test_interrupt_yields_partial_stats_of_rows_read() {
+        let mut lines = vec!["Timestamp,SensorID,Value\n".to_string()];
+        for i in 1..=10 {
+            lines.push(format!("2024-01-01T00:00:{i:02},S{},{i}0\n", i % 2));
+        }
+        let reader = InterruptingReader {
+            lines,
+            next: 0,
+            interrupt_at: 4,
+        };
+        let opts = ProcessOptions {
+            partial_on_interrupt: true,
+            ..opts(15.0, true)
+        };
+        let stats = process_reader(Box::new(reader), "test", &opts);
+        signals::set_interrupted(false);
+        let stats = stats.expect("process");
+
+        // Rows 1-4 were read (10, 20, 30, 40); 20, 30 and 40 pass the filter.
+        assert!(stats.partial);
+        assert_eq!((stats.total_rows, stats.filtered_rows), (4, 3));
+        assert_eq!(stats.average, Some(30.0));
+        let counts: Vec<usize> = stats.per_sensor.iter().map(|s| s.count).collect();
+        assert_eq!(counts, [2, 1]);
+        assert!(stats.warnings.iter().any(|w| w.contains("PARTIAL")));
+    }
+
+    #[test]
+    fn test_pair_delta_of_constant_offset() {
+        let mut csv = String::from("Timestamp,SensorID,Value\n");
+        for i in 0..5 {
+            let left = 20.0 + i as f64 * 1.5;
+            csv.push_str(&format!("2024-01-01T00:00:0{i},L,{left}\n"));
+            csv.push_str(&format!("2024-01-01T00:00:0{i},R,{}\n", left - 2.5));
+        }
+        csv.push_str(
+            "2024-01-01T00:00:07,L,30\n2024-01-01T00:00:08,R,30\n2024-01-01T00:00:09,X,1\n",
+        );
+        let file = make_temp_csv(&csv);
+        let opts = ProcessOptions {
+            pair_delta: Some(("L".to_string(), "R".to_string())),
+            ..Default::default()
+        };
+        let pair = process(file.path(), &opts).unwrap().pair_delta.unwrap();
+
+        assert_eq!(pair.matched, 5);
+        assert_eq!((pair.unmatched_left, pair.unmatched_right), (1, 1));
+        assert_eq!(pair.mean, Some(2.5));
+        assert_eq!((pair.min, pair.max), (Some(2.5), Some(2.5)));
+        assert_eq!(pair.std_dev, Some(0.0));
+    }
+
+    #[test]
+    fn test_detect_clock_resets_per_sensor() {
+        let file = make_temp_csv(
+            "Timestamp,SensorID,Value\n\
+             2024-01-01T00:10:00,S1,1\n\
+             2024-01-01T00:00:00,S2,1\n\
+             2024-01-01T00:11:00,S1,1\n\
+             2024-01-01T00:01:00,S1,1\n\
+             2024-01-01T00:02:00,S1,1\n\
+             2024-01-01T00:02:00,S2,1\n",
+        );
+        let opts = ProcessOptions {
+            detect_clock_resets: true,
+            ..Default::default()
+        };
+        let resets = process(file.path(), &opts).unwrap().clock_resets.unwrap();
+
+        // S2's jump forward from 00:00 to 00:02 is not a reset, even though S1 was later.
+        assert_eq!(resets.len(), 1);
+        assert_eq!((resets[0].sensor_id.as_str(), resets[0].row), ("S1", 4));
+        assert_eq!(
+            resets[0].from - resets[0].to,
+            600 * timestamp::MICROS_PER_SECOND
+        );
+    }
+
+    #[test]
+    fn test_expect_monotonic_values_flags_counter_decrease() {
+        let file = make_temp_csv(
+            "Timestamp,SensorID,Value\n\
+             2024-01-01T00:00:00,C1,10\n\
+             2024-01-01T00:00:00,C2,100\n\
+             2024-01-01T00:02:00,C1,30\n\
+             2024-01-01T00:01:00,C1,20\n\
+             2024-01-01T00:01:00,C2,140\n\
+             2024-01-01T00:02:00,C2,5\n\
+             2024-01-01T00:03:00,C2,8\n",
+        );
+        let opts = ProcessOptions {
+            expect_monotonic_values: true,
+            ..Default::default()
+        };
+        let drops = process(file.path(), &opts).unwrap().value_drops.unwrap();
+
+        // C1 is out of order in the file but monotonic by timestamp; C2 resets once.
+        assert_eq!(drops.len(), 1);
+        assert_eq!((drops[0].sensor_id.as_str(), drops[0].row), ("C2", 6));
+        assert_eq!(drops[0].from - drops[0].to, 135.0);
+    }
+
+    #[test]
+    fn test_offsets_calibrate_each_sensor() {
+        let file = make_temp_csv(
+            "Timestamp,SensorID,Value\n\
+             2024-01-01T00:00:00,S1,10\n\
+             2024-01-01T00:00:01,S1,20\n\
+             2024-01-01T00:00:02,S2,10\n\
+             2024-01-01T00:00:03,S3,10\n",
+        );
+        let offsets = make_temp_csv("SensorID,Offset,Scale\nS1,-5,\nS2,1,2\nS9,100,1\n");
+        let opts = ProcessOptions {
+            calibration: Some(Calibration::load(offsets.path()).unwrap()),
+            ..opts(10.0, true)
+        };
+        let stats = process(file.path(), &opts).expect("process");
+
+        // S1 drops to 5 and 15, so only 15 passes; S2 becomes 21; S3 is untouched.
+        let averages: Vec<(&str, f64)> = stats
+            .per_sensor
+            .iter()
+            .map(|s| (s.sensor_id.as_str(), s.average))
+            .collect();
+        assert_eq!(averages, [("S1", 15.0), ("S2", 21.0)]);
+        assert_eq!(
+            stats.calibrated.unwrap(),
+            [("S1".to_string(), 2), ("S2".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_output_order_reorders_and_drops_columns() {
+        let file = make_temp_csv(
+            "Timestamp,SensorID,Value\n\
+             2024-01-01T00:00:00,S1,1\n\
+             2024-01-01T00:00:01,S1,2\n",
+        );
+        let out = NamedTempFile::new().expect("tmp file");
+        let names = |cols: &[&str]| cols.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        let opts = ProcessOptions {
+            transform: Some(Transform::RollingSum(1)),
+            output: Some(out.path().to_path_buf()),
+            output_order: Some(output_order(&names(&["Value", "Timestamp"])).unwrap()),
+            ..Default::default()
+        };
+        process(file.path(), &opts).expect("process");
+        assert_eq!(
+            std::fs::read_to_string(out.path()).unwrap(),
+            "Value,Timestamp\n1,2024-01-01T00:00:00\n2,2024-01-01T00:00:01\n"
+        );
+
+        assert!(output_order(&names(&["Value", "Unit"])).is_err());
+        assert!(output_order(&names(&["Value", "Value"])).is_err());
+    }
+
+    #[test]
+    fn test_same_timestamp_mean_before_resampling() {
+        let file = make_temp_csv(
+            "Timestamp,SensorID,Value\n\
+             2024-01-01T00:00:00,S1,10\n\
+             2024-01-01T00:00:00,S1,20\n\
+             2024-01-01T00:00:30,S1,60\n\
+             2024-01-01T00:01:10,S1,5\n",
+        );
+        let out = NamedTempFile::new().expect("tmp file");
+        let opts = ProcessOptions {
+            transform: Some(Transform::Resample {
+                step: 60 * timestamp::MICROS_PER_SECOND,
+                ffill: None,
+                agg: resample::ResampleAgg::Mean,
+            }),
+            output: Some(out.path().to_path_buf()),
+            ..Default::default()
+        };
+        let stats = process(file.path(), &opts).expect("process");
+
+        // The first minute averages the resolved 15 with 60, not 10, 20 and 60.
+        assert_eq!(stats.same_timestamp_resolved, Some(1));
+        assert_eq!(stats.total_rows, 4);
+        assert_eq!(
+            std::fs::read_to_string(out.path()).unwrap(),
+            "Timestamp,SensorID,Value\n\
+             2024-01-01T00:00:00,S1,37.5\n\
+             2024-01-01T00:01:00,S1,5\n"
+        );
+
+        let strict = ProcessOptions {
+            same_timestamp: SameTimestamp::Error,
+            ..opts
+        };
+        assert!(process(file.path(), &strict).is_err());
+    }
 }
@@ -0,0 +1,461 @@
+//! A small backtracking regular-expression engine for `--group-regex`.
+//!
+//! Supports literals, `.`, classes (`[a-z0-9_]`, `[^-]`), the escapes `\d \w \s \D \W \S`,
+//! anchors `^ $`, groups `( )` and `(?: )`, alternation `|`, and the greedy or lazy
+//! quantifiers `* + ? {m} {m,} {m,n}`. Matching is unanchored, like `find`.
+
+use anyhow::{bail, Result};
+use std::str::FromStr;
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    pattern: String,
+    node: Node,
+    groups: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+    }
+
+    fn from_escape(e: char) -> Option<Class> {
+        let (ranges, negated) = match e {
+            'd' | 'D' => (vec![('0', '9')], e == 'D'),
+            'w' | 'W' => (
+                vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+                e == 'W',
+            ),
+            's' | 'S' => (
+                vec![(' ', ' '), ('\t', '\r')], // \t \n \v \f \r
+                e == 'S',
+            ),
+            _ => return None,
+        };
+        Some(Class { ranges, negated })
+    }
+}
+
+type Captures = Vec<Option<(usize, usize)>>;
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+        };
+        let node = parser.alternation()?;
+        if parser.pos < parser.chars.len() {
+            bail!("unmatched ')' in regex '{pattern}'");
+        }
+        Ok(Self {
+            pattern: pattern.to_string(),
+            node,
+            groups: parser.groups,
+        })
+    }
+
+    /// Number of capturing groups.
+    pub fn groups(&self) -> usize {
+        self.groups
+    }
+
+    /// Byte ranges of the whole leftmost match (index 0) and of each capturing group.
+    pub fn captures(&self, text: &str) -> Option<Captures> {
+        let chars: Vec<char> = text.chars().collect();
+        let offsets: Vec<usize> = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain([text.len()])
+            .collect();
+        let m = Matcher { chars: &chars };
+        for start in 0..=chars.len() {
+            let mut caps = vec![None; self.groups + 1];
+            let mut end = None;
+            if m.node(&self.node, start, &mut caps, &mut |pos, _| {
+                end = Some(pos);
+                true
+            }) {
+                caps[0] = end.map(|end| (start, end));
+                return Some(
+                    caps.into_iter()
+                        .map(|c| c.map(|(s, e)| (offsets[s], offsets[e])))
+                        .collect(),
+                );
+            }
+        }
+        None
+    }
+
+    /// Text of the first capturing group, or `None` if the regex does not match
+    /// or the group did not take part in the match.
+    pub fn first_capture<'t>(&self, text: &'t str) -> Option<&'t str> {
+        let caps = self.captures(text)?;
+        let (start, end) = (*caps.get(1)?)?;
+        Some(&text[start..end])
+    }
+}
+
+impl FromStr for Regex {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Regex::new(s)
+    }
+}
+
+impl std::fmt::Display for Regex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn alternation(&mut self) -> Result<Node> {
+        let mut alts = vec![self.concat()?];
+        while self.eat('|') {
+            alts.push(self.concat()?);
+        }
+        Ok(if alts.len() == 1 {
+            alts.pop().unwrap()
+        } else {
+            Node::Alt(alts)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantifier(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn atom(&mut self) -> Result<Node> {
+        let c = self.peek().expect("caller checked for input");
+        self.pos += 1;
+        Ok(match c {
+            '(' => {
+                let index = if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let inner = self.alternation()?;
+                if !self.eat(')') {
+                    bail!("unclosed group in regex");
+                }
+                Node::Group(Box::new(inner), index)
+            }
+            '[' => Node::Class(self.class()?),
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '*' | '+' | '?' => bail!("nothing to repeat before '{c}' in regex"),
+            '\\' => {
+                let e = self.escaped()?;
+                match Class::from_escape(e) {
+                    Some(class) => Node::Class(class),
+                    None => Node::Char(e),
+                }
+            }
+            c => Node::Char(c),
+        })
+    }
+
+    fn escaped(&mut self) -> Result<char> {
+        let Some(e) = self.peek() else {
+            bail!("regex ends with a lone '\\'");
+        };
+        self.pos += 1;
+        Ok(match e {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'd' | 'D' | 'w' | 'W' | 's' | 'S' => e,
+            // Letters and digits would mean something else (\b, \p{..}, \x41) in other
+            // engines; only punctuation is escaped to itself.
+            e if e.is_ascii_alphanumeric() => bail!("unsupported escape '\\{e}' in regex"),
+            e => e,
+        })
+    }
+
+    fn class(&mut self) -> Result<Class> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let Some(c) = self.peek() else {
+                bail!("unclosed character class in regex");
+            };
+            self.pos += 1;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let lo = if c == '\\' {
+                let e = self.escaped()?;
+                if let Some(class) = Class::from_escape(e) {
+                    if class.negated {
+                        bail!("negated escape '\\{e}' is not supported inside [...]");
+                    }
+                    ranges.extend(class.ranges);
+                    continue;
+                }
+                e
+            } else {
+                c
+            };
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&n| n != ']') {
+                self.pos += 1;
+                let hi = match self.peek() {
+                    Some('\\') => {
+                        self.pos += 1;
+                        self.escaped()?
+                    }
+                    Some(hi) => {
+                        self.pos += 1;
+                        hi
+                    }
+                    None => bail!("unclosed character class in regex"),
+                };
+                if hi < lo {
+                    bail!("invalid range '{lo}-{hi}' in regex");
+                }
+                ranges.push((lo, hi));
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+        Ok(Class { ranges, negated })
+    }
+
+    fn quantifier(&mut self, atom: Node) -> Result<Node> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => match self.counted() {
+                Some(bounds) => bounds,
+                // Not a valid {m,n}: the brace is a literal.
+                None => return Ok(atom),
+            },
+            _ => return Ok(atom),
+        };
+        if matches!(atom, Node::Start | Node::End) {
+            bail!("nothing to repeat before a quantifier in regex");
+        }
+        // Step past the quantifier character (or the closing brace of {m,n}).
+        self.pos += 1;
+        let greedy = !self.eat('?');
+        if max.is_some_and(|max| max < min) {
+            bail!("invalid repetition {{{min},{}}} in regex", max.unwrap());
+        }
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    /// Parses `{m}`, `{m,}` or `{m,n}` starting at the `{`, leaving `pos` on the `}`.
+    fn counted(&mut self) -> Option<(usize, Option<usize>)> {
+        let rest: String = self.chars[self.pos + 1..].iter().collect();
+        let close = rest.find('}')?;
+        let body = &rest[..close];
+        let bounds = match body.split_once(',') {
+            None => {
+                let n = body.parse().ok()?;
+                (n, Some(n))
+            }
+            Some((min, "")) => (min.parse().ok()?, None),
+            Some((min, max)) => (min.parse().ok()?, Some(max.parse().ok()?)),
+        };
+        self.pos += 1 + body.chars().count();
+        Some(bounds)
+    }
+}
+
+struct Matcher<'a> {
+    chars: &'a [char],
+}
+
+type Cont<'k> = &'k mut dyn FnMut(usize, &mut Captures) -> bool;
+
+impl Matcher<'_> {
+    /// Tries to match `node` at `pos`, calling `k` with each possible end position
+    /// until it returns true.
+    fn node(&self, node: &Node, pos: usize, caps: &mut Captures, k: Cont) -> bool {
+        match node {
+            Node::Char(c) => self.chars.get(pos) == Some(c) && k(pos + 1, caps),
+            Node::Any => pos < self.chars.len() && self.chars[pos] != '\n' && k(pos + 1, caps),
+            Node::Class(class) => {
+                self.chars.get(pos).is_some_and(|&c| class.matches(c)) && k(pos + 1, caps)
+            }
+            Node::Start => pos == 0 && k(pos, caps),
+            Node::End => pos == self.chars.len() && k(pos, caps),
+            Node::Concat(nodes) => self.seq(nodes, pos, caps, k),
+            Node::Alt(alts) => alts.iter().any(|alt| self.node(alt, pos, caps, k)),
+            Node::Group(inner, index) => self.node(inner, pos, caps, &mut |end, caps| {
+                let Some(i) = *index else {
+                    return k(end, caps);
+                };
+                let saved = caps[i];
+                caps[i] = Some((pos, end));
+                if k(end, caps) {
+                    return true;
+                }
+                caps[i] = saved;
+                false
+            }),
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => self.repeat(node, *min, *max, *greedy, 0, pos, caps, k),
+        }
+    }
+
+    fn seq(&self, nodes: &[Node], pos: usize, caps: &mut Captures, k: Cont) -> bool {
+        match nodes.split_first() {
+            None => k(pos, caps),
+            Some((first, rest)) => {
+                self.node(first, pos, caps, &mut |p, caps| self.seq(rest, p, caps, k))
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn repeat(
+        &self,
+        node: &Node,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+        count: usize,
+        pos: usize,
+        caps: &mut Captures,
+        k: Cont,
+    ) -> bool {
+        let enough = count >= min;
+        if !greedy && enough && k(pos, caps) {
+            return true;
+        }
+        if max.is_none_or(|max| count < max) {
+            let more = self.node(node, pos, caps, &mut |p, caps| {
+                // An empty iteration past the minimum can repeat forever; stop it.
+                if p == pos && enough {
+                    return false;
+                }
+                self.repeat(node, min, max, greedy, count + 1, p, caps, k)
+            });
+            if more {
+                return true;
+            }
+        }
+        greedy && enough && k(pos, caps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<String> {
+        let caps = Regex::new(pattern).unwrap().captures(text)?;
+        let (s, e) = caps[0].unwrap();
+        Some(text[s..e].to_string())
+    }
+
+    #[test]
+    fn test_matching() {
+        assert_eq!(find("b+", "abbbc").as_deref(), Some("bbb"));
+        assert_eq!(find("b+?", "abbbc").as_deref(), Some("b"));
+        assert_eq!(find("^a.c$", "abc").as_deref(), Some("abc"));
+        assert_eq!(find("^b", "abc"), None);
+        assert_eq!(find(r"\d{2,3}", "x12345").as_deref(), Some("123"));
+        assert_eq!(find("[^-]+$", "a-b-cd").as_deref(), Some("cd"));
+        assert_eq!(find("cat|dog", "hotdog").as_deref(), Some("dog"));
+        assert_eq!(find("(?:ab)*c", "ababc").as_deref(), Some("ababc"));
+        assert_eq!(find("a{x}", "a{x}").as_deref(), Some("a{x}"));
+        assert_eq!(find("(a*)*b", "aaab").as_deref(), Some("aaab"));
+        assert_eq!(find("é+", "caféé").as_deref(), Some("éé"));
+    }
+
+    #[test]
+    fn test_first_capture() {
+        let re = Regex::new(r"^(floor\d+)-").unwrap();
+        assert_eq!(re.groups(), 1);
+        assert_eq!(re.first_capture("floor3-room12-temp"), Some("floor3"));
+        assert_eq!(re.first_capture("roof-temp"), None);
+
+        let optional = Regex::new("x(y)?").unwrap();
+        assert_eq!(optional.first_capture("x"), None);
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        for bad in ["(ab", "ab)", "*a", "[abc", r"a\", "[z-a]", "a{3,1}"] {
+            assert!(Regex::new(bad).is_err(), "{bad}");
+        }
+        for bad in [
+            r"\bfloor", r"a\B", r"\Aa", r"a\z", r"\p{L}", r"\x41", r"[\b]",
+        ] {
+            let err = Regex::new(bad).unwrap_err().to_string();
+            assert!(err.starts_with("unsupported escape '\\"), "{bad}: {err}");
+        }
+        assert!(Regex::new(r"^floor\d+\.\-\[x\]\t").is_ok());
+    }
+}
@@ -0,0 +1,1337 @@
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::Path;
+
+use crate::buckets::{CategoryCounts, SweepRow, ValueBin, ValueBuckets};
+use crate::json;
+use crate::ks::KsResult;
+use crate::metadata::SensorMetadata;
+use crate::output::{self, OutputFile, WriteOptions};
+use crate::parquet::ParquetSchema;
+use crate::percentile::SensorPercentiles;
+use crate::processor::{ProcessingStats, SensorStats};
+use crate::resample::WindowStats;
+use crate::series::{
+    ClockReset, Coverage, Flatline, OnlineStats, PairDelta, QualityScore, Timeline, ValueDrop,
+};
+use crate::timestamp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Html,
+    Json,
+    Markdown,
+    /// The text report, plus the filtered rows written to `--output` as Parquet.
+    Parquet,
+}
+
+impl OutputFormat {
+    /// Whether the report on stdout is the plain-text one.
+    pub fn is_text(self) -> bool {
+        matches!(self, OutputFormat::Text | OutputFormat::Parquet)
+    }
+}
+
+/// Bumped whenever a field in the `--json-envelope` output changes meaning or is removed.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortKey {
+    Id,
+    Count,
+    Average,
+    Range,
+}
+
+/// Orders sensors by `key` (numeric keys descending, ties by sensor ID) and keeps the first `top_n`.
+pub fn select_sensors(
+    stats: &[SensorStats],
+    key: SortKey,
+    top_n: Option<usize>,
+) -> Vec<&SensorStats> {
+    let mut selected: Vec<&SensorStats> = stats.iter().collect();
+    selected.sort_by(|a, b| {
+        let by_key = match key {
+            SortKey::Id => std::cmp::Ordering::Equal,
+            SortKey::Count => b.count.cmp(&a.count),
+            SortKey::Average => b.average.total_cmp(&a.average),
+            SortKey::Range => b.range.total_cmp(&a.range),
+        };
+        by_key.then_with(|| a.sensor_id.cmp(&b.sensor_id))
+    });
+    if let Some(n) = top_n {
+        selected.truncate(n);
+    }
+    selected
+}
+
+/// Which sensors the `--compare` per-sensor comparison lists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Join {
+    /// Only sensors present in both inputs.
+    Inner,
+    /// Every sensor of `--input`.
+    Left,
+    /// Every sensor of either input.
+    #[default]
+    Outer,
+}
+
+/// One sensor's stats in `--input` and in `--compare`; `None` where it has no rows.
+#[derive(Debug)]
+pub struct SensorComparison<'a> {
+    pub sensor_id: &'a str,
+    pub primary: Option<&'a SensorStats>,
+    pub other: Option<&'a SensorStats>,
+}
+
+/// Pairs up the per-sensor stats of two inputs by sensor ID, keeping the sensors `join`
+/// selects, sorted by ID.
+pub fn compare_sensors<'a>(
+    primary: &'a [SensorStats],
+    other: &'a [SensorStats],
+    join: Join,
+) -> Vec<SensorComparison<'a>> {
+    let mut rows: BTreeMap<&str, SensorComparison> = BTreeMap::new();
+    for (s, in_primary) in primary
+        .iter()
+        .map(|s| (s, true))
+        .chain(other.iter().map(|s| (s, false)))
+    {
+        let row = rows.entry(&s.sensor_id).or_insert(SensorComparison {
+            sensor_id: &s.sensor_id,
+            primary: None,
+            other: None,
+        });
+        if in_primary {
+            row.primary = Some(s);
+        } else {
+            row.other = Some(s);
+        }
+    }
+    rows.into_values()
+        .filter(|r| match join {
+            Join::Inner => r.primary.is_some() && r.other.is_some(),
+            Join::Left => r.primary.is_some(),
+            Join::Outer => true,
+        })
+        .collect()
+}
+
+/// Decimal places for averages and percentages in the report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Precision {
+    pub average: usize,
+    pub percent: usize,
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Self {
+            average: 6,
+            percent: 2,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RenderOptions<'a> {
+    /// Suffix value columns with the sensor's unit, when it has one.
+    pub units: Option<&'a SensorMetadata>,
+    /// Add the `--distinct-values` column.
+    pub distinct_values: bool,
+    /// Add the `--target` MAD and RMSD columns.
+    pub target: bool,
+    /// Add the `--noise-floor` column.
+    pub noise_floor: bool,
+    pub precision: Precision,
+}
+
+const COLUMNS: [(&str, usize); 7] = [
+    ("Sensor ID", 20),
+    ("Row Count", 10),
+    ("Average Value", 16),
+    ("Min", 14),
+    ("Max", 14),
+    ("Range", 14),
+    ("SEM", 14),
+];
+
+fn columns(opts: &RenderOptions) -> Vec<(&'static str, usize)> {
+    let mut columns = COLUMNS.to_vec();
+    if opts.distinct_values {
+        columns.push(("Distinct", 10));
+    }
+    if opts.target {
+        columns.extend([("MAD", 14), ("RMSD", 14)]);
+    }
+    if opts.noise_floor {
+        columns.push(("Noise", 14));
+    }
+    columns
+}
+
+fn sensor_cells(s: &SensorStats, opts: &RenderOptions) -> Vec<String> {
+    let unit = opts.units.and_then(|m| m.unit(&s.sensor_id));
+    let with_decimals = |v: f64, decimals: usize| match unit {
+        Some(unit) => format!("{v:.decimals$} {unit}"),
+        None => format!("{v:.decimals$}"),
+    };
+    let value = |v: f64| with_decimals(v, 6);
+    let mut cells = vec![
+        s.sensor_id.clone(),
+        s.count.to_string(),
+        with_decimals(s.average, opts.precision.average),
+        value(s.min),
+        value(s.max),
+        value(s.range),
+        s.sem.map_or_else(|| "N/A".to_string(), value),
+    ];
+    if opts.distinct_values {
+        cells.push(s.distinct_values.to_string());
+    }
+    if opts.target {
+        match s.target_deviation {
+            Some(d) => cells.extend([value(d.mad), value(d.rmsd)]),
+            None => cells.extend(["N/A".to_string(), "N/A".to_string()]),
+        }
+    }
+    if opts.noise_floor {
+        cells.push(s.noise_floor.map_or_else(|| "N/A".to_string(), value));
+    }
+    cells
+}
+
+pub fn print_sensor_table(stats: &[&SensorStats], opts: &RenderOptions) {
+    print!("{}", render_text_table(stats, opts));
+}
+
+fn render_text_table(stats: &[&SensorStats], opts: &RenderOptions) -> String {
+    let columns = columns(opts);
+    let mut out = String::from("\n ");
+    for (i, (header, width)) in columns.iter().enumerate() {
+        let _ = if i == 0 {
+            write!(out, " {header:<width$}")
+        } else {
+            write!(out, " {header:>width$}")
+        };
+    }
+    out.push_str("\n ");
+    for &(_, width) in &columns {
+        let _ = write!(out, " {:-<width$}", "");
+    }
+    out.push('\n');
+    for s in stats {
+        out.push(' ');
+        for (i, (cell, (_, width))) in sensor_cells(s, opts).iter().zip(&columns).enumerate() {
+            let _ = if i == 0 {
+                write!(out, " {cell:<width$}")
+            } else {
+                write!(out, " {cell:>width$}")
+            };
+        }
+        out.push('\n');
+    }
+    out.push('\n');
+    out
+}
+
+pub fn render_range_ranking(sensors: &[&SensorStats]) -> String {
+    let mut out = format!("Top {} sensors by range\n", sensors.len());
+    for (rank, s) in sensors.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "  {:>3}. {:<20} {:>14.6}  ({:.6} .. {:.6})",
+            rank + 1,
+            s.sensor_id,
+            s.range,
+            s.min,
+            s.max
+        );
+    }
+    out
+}
+
+/// Log-scale upper bucket edges used when `--count-edges` is not given.
+pub const DEFAULT_COUNT_EDGES: [usize; 5] = [10, 100, 1_000, 10_000, 100_000];
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CountBucket {
+    pub low: usize,
+    /// Inclusive; `None` for the open-ended last bucket.
+    pub high: Option<usize>,
+    pub sensors: usize,
+}
+
+/// Buckets sensors by row count. `edges` are strictly increasing inclusive upper bounds.
+pub fn count_histogram(stats: &[SensorStats], edges: &[usize]) -> Vec<CountBucket> {
+    let mut buckets: Vec<CountBucket> = edges
+        .iter()
+        .scan(1, |low, &high| {
+            let bucket = CountBucket {
+                low: *low,
+                high: Some(high),
+                sensors: 0,
+            };
+            *low = high + 1;
+            Some(bucket)
+        })
+        .collect();
+    buckets.push(CountBucket {
+        low: edges.last().map_or(1, |e| e + 1),
+        high: None,
+        sensors: 0,
+    });
+    for s in stats {
+        let i = edges.partition_point(|&e| e < s.count);
+        buckets[i].sensors += 1;
+    }
+    buckets
+}
+
+pub fn render_count_histogram(buckets: &[CountBucket]) -> String {
+    let mut out = String::from("Sensors by reading count\n");
+    for b in buckets {
+        let label = match b.high {
+            Some(high) => format!("{}-{}", b.low, high),
+            None => format!("{}+", b.low),
+        };
+        let _ = writeln!(out, "  {:>15} readings: {} sensors", label, b.sensors);
+    }
+    out
+}
+
+pub fn render_percentiles(ps: &[f64], sensors: &[SensorPercentiles]) -> String {
+    let mut out = format!("  {:<20}", "Sensor ID");
+    for p in ps {
+        let _ = write!(out, " {:>14}", format!("p{p}"));
+    }
+    let _ = write!(out, "\n  {:-<20}", "");
+    for _ in ps {
+        let _ = write!(out, " {:->14}", "");
+    }
+    out.push('\n');
+    for s in sensors {
+        let _ = write!(out, "  {:<20}", s.sensor_id);
+        for v in &s.values {
+            let _ = write!(out, " {v:>14.6}");
+        }
+        out.push('\n');
+    }
+    out
+}
+
+pub fn render_ks(result: Option<&KsResult>, alpha: f64) -> String {
+    let Some(ks) = result else {
+        return "KS test: skipped (a distribution has no values after filtering)\n".to_string();
+    };
+    let verdict = if ks.p_value < alpha {
+        "distributions differ"
+    } else {
+        "no significant difference"
+    };
+    format!(
+        "KS test (n = {}, m = {})\n  Statistic D : {:.6}\n  p-value     : {:.6}\n  Result      : {verdict} at alpha = {alpha}\n",
+        ks.n, ks.m, ks.statistic, ks.p_value
+    )
+}
+
+pub fn render_value_buckets(groups: &[ValueBuckets]) -> String {
+    let mut out = String::from("Value buckets\n");
+    for g in groups {
+        let _ = writeln!(
+            out,
+            "  {} ({} distinct)",
+            g.sensor_id.as_deref().unwrap_or("All sensors"),
+            g.distinct
+        );
+        for (value, count) in &g.counts {
+            let _ = writeln!(out, "    {value:>16} : {count}");
+        }
+        if g.other > 0 {
+            let _ = writeln!(out, "    {:>16} : {}", "other", g.other);
+        }
+    }
+    out
+}
+
+pub fn render_categories(groups: &[CategoryCounts]) -> String {
+    let mut out = String::from("Categories\n");
+    for g in groups {
+        let _ = writeln!(
+            out,
+            "  {} ({} categories)",
+            g.sensor_id.as_deref().unwrap_or("All sensors"),
+            g.counts.len()
+        );
+        for (category, count) in &g.counts {
+            let _ = writeln!(out, "    {category:>16} : {count}");
+        }
+    }
+    out
+}
+
+pub fn render_chunks(chunks: &[ProcessingStats]) -> String {
+    let mut out = format!("Chunks: {}\n", chunks.len());
+    for (i, c) in chunks.iter().enumerate() {
+        let average = c
+            .average
+            .map_or_else(|| "N/A".to_string(), |avg| format!("{avg:.6}"));
+        let _ = writeln!(
+            out,
+            "  {:<8} {:>10} rows {:>10} after filter   average {average}",
+            format!("#{}", i + 1),
+            c.total_rows,
+            c.filtered_rows
+        );
+    }
+    out
+}
+
+pub fn render_windows(windows: &[WindowStats]) -> String {
+    let mut out = format!(
+        "  {:<26} {:>10} {:>16}\n",
+        "Window Start", "Count", "Average Value"
+    );
+    let _ = writeln!(out, "  {:-<26} {:->10} {:->16}", "", "", "");
+    for w in windows {
+        let average = w
+            .average
+            .map_or_else(|| "-".to_string(), |a| format!("{a:.6}"));
+        let _ = writeln!(
+            out,
+            "  {:<26} {:>10} {:>16}",
+            timestamp::format_iso8601(w.start),
+            w.count,
+            average
+        );
+    }
+    out
+}
+
+pub fn render_online(online: &[OnlineStats], window: usize) -> String {
+    let mut out = format!("Online stats (last {window} filtered rows)\n");
+    let _ = writeln!(
+        out,
+        "  {:>12} {:>8} {:>16} {:>16}",
+        "Row", "Window", "Mean", "Variance"
+    );
+    let _ = writeln!(out, "  {:->12} {:->8} {:->16} {:->16}", "", "", "", "");
+    for s in online {
+        let variance = s
+            .variance
+            .map_or_else(|| "N/A".to_string(), |v| format!("{v:.6}"));
+        let _ = writeln!(
+            out,
+            "  {:>12} {:>8} {:>16.6} {:>16}",
+            s.row, s.count, s.mean, variance
+        );
+    }
+    out
+}
+
+pub fn render_value_bins(bins: &[ValueBin], width: f64) -> String {
+    let mut out = format!("Value bins (width {width})\n");
+    let _ = writeln!(
+        out,
+        "  {:<24} {:>10} {:>16}",
+        "Bin", "Count", "Average Value"
+    );
+    let _ = writeln!(out, "  {:-<24} {:->10} {:->16}", "", "", "");
+    for b in bins {
+        let average = b
+            .mean
+            .map_or_else(|| "-".to_string(), |m| format!("{m:.6}"));
+        let range = format!("[{}, {})", b.start, b.start + width);
+        let _ = writeln!(out, "  {range:<24} {:>10} {average:>16}", b.count);
+    }
+    out
+}
+
+pub fn render_threshold_sweep(rows: &[SweepRow], precision: Precision) -> String {
+    let mut out = String::from("Threshold sweep\n");
+    let _ = writeln!(
+        out,
+        "  {:>14} {:>14} {:>16}",
+        "Threshold", "Rows > thr", "Average Value"
+    );
+    let _ = writeln!(out, "  {:->14} {:->14} {:->16}", "", "", "");
+    for row in rows {
+        let average = row.average.map_or_else(
+            || "N/A".to_string(),
+            |a| format!("{a:.decimals$}", decimals = precision.average),
+        );
+        let _ = writeln!(
+            out,
+            "  {:>14} {:>14} {average:>16}",
+            row.threshold, row.filtered_rows
+        );
+    }
+    out
+}
+
+pub fn render_pair_delta(p: &PairDelta) -> String {
+    let stat = |v: Option<f64>| v.map_or_else(|| "N/A".to_string(), |v| format!("{v:.6}"));
+    let mut out = format!("Pair delta ({} - {})\n", p.left, p.right);
+    let _ = writeln!(out, "  Matched timestamps   : {}", p.matched);
+    let _ = writeln!(
+        out,
+        "  Unmatched timestamps : {} only {}, {} only {}",
+        p.unmatched_left, p.left, p.unmatched_right, p.right
+    );
+    let _ = writeln!(out, "  Mean delta           : {}", stat(p.mean));
+    let _ = writeln!(out, "  Min delta            : {}", stat(p.min));
+    let _ = writeln!(out, "  Max delta            : {}", stat(p.max));
+    let _ = writeln!(out, "  Std dev of delta     : {}", stat(p.std_dev));
+    out
+}
+
+/// Ends a rendered report with exactly one newline, or none for `--no-trailing-newline`.
+pub fn finish(report: &str, trailing_newline: bool) -> String {
+    let mut out = report.trim_end_matches(['\n', '\r']).to_string();
+    if trailing_newline {
+        out.push('\n');
+    }
+    out
+}
+
+/// Column names are indented by their nesting depth.
+pub fn render_parquet_schema(source: &str, schema: &ParquetSchema) -> String {
+    let mut out = format!(
+        "Parquet schema of '{source}' ({} rows in {} row group(s))\n",
+        schema.rows, schema.row_groups
+    );
+    if let Some(created_by) = &schema.created_by {
+        let _ = writeln!(out, "  Created by: {created_by}");
+    }
+    let _ = writeln!(
+        out,
+        "  {:<28} {:<20} {:<16} Repetition",
+        "Column", "Type", "Annotation"
+    );
+    let _ = writeln!(out, "  {:-<28} {:-<20} {:-<16} {:-<10}", "", "", "", "");
+    for c in &schema.columns {
+        let name = format!("{:indent$}{}", "", c.name, indent = 2 * c.depth);
+        let _ = writeln!(
+            out,
+            "  {name:<28} {:<20} {:<16} {}",
+            c.kind,
+            c.annotation.as_deref().unwrap_or("-"),
+            c.repetition
+        );
+    }
+    out
+}
+
+pub fn render_clock_resets(resets: &[ClockReset]) -> String {
+    let mut out = format!("Clock resets detected: {}\n", resets.len());
+    for r in resets {
+        let _ = writeln!(
+            out,
+            "  {:<20} row {}: {} -> {}  (back {:.3}s)",
+            r.sensor_id,
+            r.row,
+            timestamp::format_iso8601(r.from),
+            timestamp::format_iso8601(r.to),
+            (r.from - r.to) as f64 / timestamp::MICROS_PER_SECOND as f64
+        );
+    }
+    out
+}
+
+pub fn render_value_drops(drops: &[ValueDrop]) -> String {
+    let mut out = format!("Value decreases detected: {}\n", drops.len());
+    for d in drops {
+        let _ = writeln!(
+            out,
+            "  {:<20} row {} at {}: {} -> {}  (drop {})",
+            d.sensor_id,
+            d.row,
+            timestamp::format_iso8601(d.time),
+            d.from,
+            d.to,
+            d.from - d.to
+        );
+    }
+    out
+}
+
+pub fn render_flatlines(flatlines: &[Flatline]) -> String {
+    let mut out = format!("Flatlines detected: {}\n", flatlines.len());
+    for f in flatlines {
+        let _ = writeln!(
+            out,
+            "  {:<20} {} .. {}  {} readings at {:.6}",
+            f.sensor_id,
+            timestamp::format_iso8601(f.start),
+            timestamp::format_iso8601(f.end),
+            f.readings,
+            f.value
+        );
+    }
+    out
+}
+
+pub fn render_coverage(coverage: &[Coverage], min_coverage: f64, precision: Precision) -> String {
+    let low = coverage
+        .iter()
+        .filter(|c| c.percent() < min_coverage)
+        .count();
+    let mut out = format!("Coverage ({low} sensor(s) below {min_coverage}%)\n");
+    for c in coverage {
+        let flag = if c.percent() < min_coverage {
+            "  LOW"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            out,
+            "  {:<20} {:>7.decimals$}%  ({} of {} slots){flag}",
+            c.sensor_id,
+            c.percent(),
+            c.filled,
+            c.expected,
+            decimals = precision.percent
+        );
+    }
+    out
+}
+
+pub fn render_comparison(rows: &[SensorComparison], join: Join, precision: Precision) -> String {
+    let join = clap::ValueEnum::to_possible_value(&join).expect("no skipped variants");
+    let mut out = format!(
+        "Per-sensor comparison (--join {}): {} sensor(s)\n",
+        join.get_name(),
+        rows.len()
+    );
+    let _ = writeln!(
+        out,
+        "  {:<20} {:>8} {:>14} {:>9} {:>14} {:>14}",
+        "Sensor", "Count", "Average", "Cmp count", "Cmp average", "Difference"
+    );
+    let count =
+        |s: Option<&SensorStats>| s.map_or_else(|| "-".to_string(), |s| s.count.to_string());
+    let average = |s: Option<&SensorStats>| {
+        s.map_or_else(
+            || "-".to_string(),
+            |s| format!("{:.decimals$}", s.average, decimals = precision.average),
+        )
+    };
+    for r in rows {
+        let difference = match (r.primary, r.other) {
+            (Some(a), Some(b)) => format!(
+                "{:+.decimals$}",
+                b.average - a.average,
+                decimals = precision.average
+            ),
+            _ => "-".to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "  {:<20} {:>8} {:>14} {:>9} {:>14} {:>14}",
+            r.sensor_id,
+            count(r.primary),
+            average(r.primary),
+            count(r.other),
+            average(r.other),
+            difference
+        );
+    }
+    out
+}
+
+pub fn render_quality(quality: &[QualityScore], min_quality: f64) -> String {
+    let low = quality.iter().filter(|q| q.score < min_quality).count();
+    let mut out = format!("Quality score ({low} sensor(s) below {min_quality})\n");
+    for q in quality {
+        let flag = if q.score < min_quality { "  LOW" } else { "" };
+        let _ = writeln!(
+            out,
+            "  {:<20} {:>5.1}  (coverage {:.2}, noise {:.2}, flatline {:.2}){flag}",
+            q.sensor_id, q.score, q.coverage, q.noise, q.flatline
+        );
+    }
+    out
+}
+
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// One character per slot, scaled to the busiest slot; slots without readings are blank.
+pub fn sparkline(counts: &[usize]) -> String {
+    let max = counts.iter().copied().max().unwrap_or(0);
+    counts
+        .iter()
+        .map(|&n| match n {
+            0 => ' ',
+            n => SPARK_LEVELS[(n * SPARK_LEVELS.len()).div_ceil(max) - 1],
+        })
+        .collect()
+}
+
+pub fn render_timeline(timeline: &Timeline) -> String {
+    let mut out = format!(
+        "Timeline ({} to {}, {} slots)\n",
+        timestamp::format_iso8601(timeline.start),
+        timestamp::format_iso8601(timeline.end),
+        timeline.sensors.first().map_or(0, |(_, c)| c.len())
+    );
+    for (sensor_id, counts) in &timeline.sensors {
+        let _ = writeln!(out, "  {sensor_id:<20} |{}|", sparkline(counts));
+    }
+    out
+}
+
+pub fn render_html(
+    stats: &ProcessingStats,
+    sensors: &[&SensorStats],
+    opts: &RenderOptions,
+) -> String {
+    let mut out = String::new();
+    let average = match stats.average {
+        Some(avg) => format!("{avg:.decimals$}", decimals = opts.precision.average),
+        None => "N/A".to_string(),
+    };
+
+    out.push_str("<div class=\"summary\">\n<ul>\n");
+    let _ = writeln!(out, "  <li>Total rows read: {}</li>", stats.total_rows);
+    let _ = writeln!(out, "  <li>Rows after filter: {}</li>", stats.filtered_rows);
+    let _ = writeln!(out, "  <li>Average value: {average}</li>");
+    out.push_str("</ul>\n</div>\n");
+
+    out.push_str("<table>\n  <thead>\n    <tr>");
+    for (header, _) in columns(opts) {
+        let _ = write!(out, "<th>{header}</th>");
+    }
+    out.push_str("</tr>\n  </thead>\n  <tbody>\n");
+    for s in sensors {
+        out.push_str("    <tr>");
+        for cell in sensor_cells(s, opts) {
+            let _ = write!(out, "<td>{}</td>", html_escape(&cell));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("  </tbody>\n</table>\n");
+    out
+}
+
+pub fn render_json(stats: &ProcessingStats, sensors: &[&SensorStats]) -> String {
+    let per_sensor: Vec<String> = sensors.iter().map(|s| sensor_json(s)).collect();
+    let mut fields = vec![
+        ("total_rows", stats.total_rows.to_string()),
+        ("filtered_rows", stats.filtered_rows.to_string()),
+        (
+            "rows_removed",
+            (stats.total_rows - stats.filtered_rows).to_string(),
+        ),
+        (
+            "average",
+            stats
+                .average
+                .map_or_else(|| "null".to_string(), json::number),
+        ),
+        ("per_sensor", format!("[{}]", per_sensor.join(","))),
+    ];
+    if let Some(d) = stats.target_deviation {
+        fields.push(("target_mad", json::number(d.mad)));
+        fields.push(("target_rmsd", json::number(d.rmsd)));
+    }
+    if stats.partial {
+        fields.push(("partial", "true".to_string()));
+    }
+    json::object(&fields)
+}
+
+fn sensor_json(s: &SensorStats) -> String {
+    json::object(&sensor_fields(s))
+}
+
+fn sensor_fields(s: &SensorStats) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("sensor_id", json::string(&s.sensor_id)),
+        ("count", s.count.to_string()),
+        ("average", json::number(s.average)),
+        ("min", json::number(s.min)),
+        ("max", json::number(s.max)),
+        ("range", json::number(s.range)),
+        (
+            "sem",
+            s.sem.map_or_else(|| "null".to_string(), json::number),
+        ),
+    ];
+    if s.distinct_values > 0 {
+        fields.push(("distinct_values", s.distinct_values.to_string()));
+    }
+    if let Some(d) = s.target_deviation {
+        fields.push(("target_mad", json::number(d.mad)));
+        fields.push(("target_rmsd", json::number(d.rmsd)));
+    }
+    if let Some(noise) = s.noise_floor {
+        fields.push(("noise_floor", json::number(noise)));
+    }
+    fields
+}
+
+/// One JSON object per line (NDJSON), in the same shape as `per_sensor` in `--format json`.
+pub fn render_sensor_ndjson(sensors: &[&SensorStats]) -> String {
+    sensors.iter().map(|s| sensor_json(s) + "\n").collect()
+}
+
+/// Per-sensor stats as CSV with unformatted numbers.
+pub fn write_sensor_csv(sensors: &[&SensorStats], path: &Path, opts: WriteOptions) -> Result<()> {
+    let mut writer = output::csv_writer(path, opts)?;
+    writer.write_record(SENSOR_CSV_HEADER)?;
+    for s in sensors {
+        writer.write_record(sensor_csv_row(s))?;
+    }
+    writer.finish()
+}
+
+const SENSOR_CSV_HEADER: [&str; 7] = ["SensorID", "Count", "Average", "Min", "Max", "Range", "SEM"];
+
+fn sensor_csv_row(s: &SensorStats) -> [String; 7] {
+    [
+        s.sensor_id.clone(),
+        s.count.to_string(),
+        s.average.to_string(),
+        s.min.to_string(),
+        s.max.to_string(),
+        s.range.to_string(),
+        s.sem.map_or_else(String::new, |sem| sem.to_string()),
+    ]
+}
+
+/// File name stem for a group's `--group-output-dir` files: anything but ASCII letters,
+/// digits, `-`, `_` and `.` becomes `_`, so an ID cannot reach outside the directory.
+fn group_file_stem(key: &str) -> String {
+    let stem: String = key
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    if stem.is_empty() {
+        "_".to_string()
+    } else {
+        stem
+    }
+}
+
+/// Writes each group's stats to its own file in `dir`: `<group>.json` (the `per_sensor`
+/// object of `--format json`, with the group's values under `values` when given), or a
+/// one-row `<group>.csv` with the values in `<group>.values.csv`. Files are written one
+/// at a time, so a run with thousands of sensors holds a single handle open. Returns the
+/// number of groups written.
+pub fn write_group_files(
+    dir: &Path,
+    sensors: &[&SensorStats],
+    values: Option<&BTreeMap<String, Vec<f64>>>,
+    json: bool,
+    opts: WriteOptions,
+) -> Result<usize> {
+    // Compared ignoring case: "Floor1" and "floor1" are one file on macOS and Windows.
+    let mut stems: HashMap<String, &str> = HashMap::new();
+    for s in sensors {
+        let stem = group_file_stem(&s.sensor_id);
+        if let Some(other) = stems.insert(stem.to_ascii_lowercase(), &s.sensor_id) {
+            bail!(
+                "Groups '{other}' and '{}' would both be written to '{stem}' in --group-output-dir.",
+                s.sensor_id
+            );
+        }
+    }
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Cannot create directory '{}'", dir.display()))?;
+
+    let empty = Vec::new();
+    for s in sensors {
+        let stem = group_file_stem(&s.sensor_id);
+        let group_values = values.map(|v| v.get(&s.sensor_id).unwrap_or(&empty));
+        if json {
+            let mut fields = sensor_fields(s);
+            if let Some(group_values) = group_values {
+                let encoded: Vec<String> = group_values.iter().map(|&v| json::number(v)).collect();
+                fields.push(("values", format!("[{}]", encoded.join(","))));
+            }
+            let mut file = OutputFile::create(&dir.join(format!("{stem}.json")), opts)?;
+            writeln!(file, "{}", json::object(&fields))?;
+            file.finish()?;
+        } else {
+            let mut writer = output::csv_writer(&dir.join(format!("{stem}.csv")), opts)?;
+            writer.write_record(SENSOR_CSV_HEADER)?;
+            writer.write_record(sensor_csv_row(s))?;
+            writer.finish()?;
+            if let Some(group_values) = group_values {
+                let mut writer = output::csv_writer(&dir.join(format!("{stem}.values.csv")), opts)?;
+                writer.write_record(["Value"])?;
+                for v in group_values {
+                    writer.write_record([v.to_string()])?;
+                }
+                writer.finish()?;
+            }
+        }
+    }
+    Ok(sensors.len())
+}
+
+/// Wraps `stats_json` in `{"version", "tool_version", "generated_at", "config", "stats"}`.
+/// `config` values must already be JSON-encoded.
+pub fn render_json_envelope(
+    stats_json: &str,
+    config: &[(&str, String)],
+    generated_at: i64,
+) -> String {
+    json::object(&[
+        ("version", JSON_SCHEMA_VERSION.to_string()),
+        ("tool_version", json::string(env!("CARGO_PKG_VERSION"))),
+        (
+            "generated_at",
+            json::string(&format!("{}Z", timestamp::format_iso8601(generated_at))),
+        ),
+        ("config", json::object(config)),
+        ("stats", stats_json.to_string()),
+    ])
+}
+
+/// Global summary as a bullet list followed by a GitHub-flavored Markdown table.
+pub fn render_markdown(
+    stats: &ProcessingStats,
+    sensors: &[&SensorStats],
+    opts: &RenderOptions,
+) -> String {
+    let average = match stats.average {
+        Some(avg) => format!("{avg:.decimals$}", decimals = opts.precision.average),
+        None => "N/A".to_string(),
+    };
+    let mut out = String::new();
+    let _ = writeln!(out, "- Total rows read: {}", stats.total_rows);
+    let _ = writeln!(out, "- Rows after filter: {}", stats.filtered_rows);
+    let _ = writeln!(out, "- Average value: {average}");
+
+    if sensors.is_empty() {
+        return out;
+    }
+    let columns = columns(opts);
+    out.push_str("\n|");
+    for (header, _) in &columns {
+        let _ = write!(out, " {header} |");
+    }
+    out.push_str("\n|");
+    for (i, _) in columns.iter().enumerate() {
+        // Left-align the sensor ID, right-align the numbers.
+        out.push_str(if i == 0 { " --- |" } else { " ---: |" });
+    }
+    out.push('\n');
+    for s in sensors {
+        out.push('|');
+        for cell in sensor_cells(s, opts) {
+            let _ = write!(out, " {} |", cell.replace('|', "\\|"));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn html_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sensor_with_range(id: &str, min: f64, max: f64) -> SensorStats {
+        SensorStats {
+            min,
+            max,
+            range: max - min,
+            ..sensor(id, 1, min)
+        }
+    }
+
+    fn sensor(id: &str, count: usize, average: f64) -> SensorStats {
+        SensorStats {
+            sensor_id: id.to_string(),
+            count,
+            average,
+            min: average,
+            max: average,
+            range: 0.0,
+            distinct_values: 0,
+            sem: None,
+            target_deviation: None,
+            noise_floor: None,
+        }
+    }
+
+    #[test]
+    fn test_render_html_escapes_sensor_ids() {
+        let per_sensor = vec![sensor("S<1>", 2, 70.0), sensor("S2", 5, 90.0)];
+        let stats = ProcessingStats {
+            total_rows: 7,
+            filtered_rows: 7,
+            average: Some(84.285714),
+            per_sensor,
+            ..Default::default()
+        };
+        let sensors = select_sensors(&stats.per_sensor, SortKey::Count, Some(2));
+        let html = render_html(&stats, &sensors, &RenderOptions::default());
+
+        assert!(html.contains("<table>") && html.contains("</table>"));
+        assert_eq!(html.matches("<tr>").count(), 3);
+        assert_eq!(html.matches("<tr>").count(), html.matches("</tr>").count());
+        assert!(html.contains("<td>S&lt;1&gt;</td>"));
+        assert!(!html.contains("S<1>"));
+        assert!(html.find("<td>S2</td>").unwrap() < html.find("<td>S&lt;1&gt;</td>").unwrap());
+    }
+
+    #[test]
+    fn test_count_histogram_buckets_fleet() {
+        let fleet: Vec<_> = [1, 10, 11, 100, 101, 5_000]
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| sensor(&format!("S{i}"), count, 1.0))
+            .collect();
+
+        let counts: Vec<_> = count_histogram(&fleet, &[10, 100])
+            .iter()
+            .map(|b| (b.low, b.high, b.sensors))
+            .collect();
+        assert_eq!(
+            counts,
+            [(1, Some(10), 2), (11, Some(100), 2), (101, None, 2)]
+        );
+
+        let rendered = render_count_histogram(&count_histogram(&fleet, &DEFAULT_COUNT_EDGES));
+        assert!(rendered.contains("1-10 readings: 2 sensors"));
+        assert!(rendered.contains("1001-10000 readings: 1 sensors"));
+        assert!(rendered.contains("100001+ readings: 0 sensors"));
+    }
+
+    #[test]
+    fn test_json_envelope_wraps_stats() {
+        let stats = ProcessingStats {
+            total_rows: 3,
+            filtered_rows: 2,
+            average: Some(1.5),
+            per_sensor: vec![sensor("S\"1", 2, 1.5)],
+            ..Default::default()
+        };
+        let sensors = select_sensors(&stats.per_sensor, SortKey::Id, None);
+        let stats_json = render_json(&stats, &sensors);
+        let config = [("filter_threshold", json::number(0.5))];
+        let out = render_json_envelope(&stats_json, &config, 0);
+
+        assert!(out.starts_with(r#"{"version":1,"tool_version":""#));
+        assert!(out.contains(env!("CARGO_PKG_VERSION")));
+        assert!(out.contains(r#""generated_at":"1970-01-01T00:00:00Z""#));
+        assert!(out.contains(r#""config":{"filter_threshold":0.5}"#));
+        assert!(out.ends_with(&format!(r#","stats":{stats_json}}}"#)));
+        assert!(stats_json.contains(r#""total_rows":3,"filtered_rows":2,"rows_removed":1"#));
+        assert!(stats_json.contains(r#""sensor_id":"S\"1","count":2,"average":1.5"#));
+    }
+
+    #[test]
+    fn test_render_markdown_table() {
+        let per_sensor = vec![
+            sensor("A|B", 2, 70.0),
+            sensor("S2", 5, 90.0),
+            sensor("S3", 1, 1.0),
+        ];
+        let stats = ProcessingStats {
+            total_rows: 8,
+            filtered_rows: 8,
+            average: Some(71.0),
+            per_sensor,
+            ..Default::default()
+        };
+        let sensors = select_sensors(&stats.per_sensor, SortKey::Count, Some(2));
+        let md = render_markdown(&stats, &sensors, &RenderOptions::default());
+        let lines: Vec<&str> = md.lines().collect();
+
+        assert_eq!(lines[0], "- Total rows read: 8");
+        let header = lines
+            .iter()
+            .position(|l| l.starts_with("| Sensor ID |"))
+            .unwrap();
+        assert_eq!(
+            lines[header + 1],
+            "| --- | ---: | ---: | ---: | ---: | ---: | ---: |"
+        );
+        assert!(lines[header + 2].starts_with("| S2 | 5 |"));
+        assert!(lines[header + 3].starts_with("| A\\|B | 2 |"));
+        assert_eq!(lines.len(), header + 4);
+    }
+
+    #[test]
+    fn test_sensor_ndjson_has_one_object_per_line() {
+        let per_sensor = vec![
+            sensor("S1", 2, 1.5),
+            sensor("S\n2", 1, 3.0),
+            sensor("S3", 4, 0.25),
+        ];
+        let sensors = select_sensors(&per_sensor, SortKey::Id, None);
+        let out = render_sensor_ndjson(&sensors);
+        let lines: Vec<&str> = out.lines().collect();
+
+        assert_eq!(lines.len(), per_sensor.len());
+        for (line, s) in lines.iter().zip(&sensors) {
+            assert!(line.starts_with('{') && line.ends_with('}'), "{line}");
+            assert!(line.contains(&format!(r#""sensor_id":{}"#, json::string(&s.sensor_id))));
+        }
+        // A newline sorts below any digit, so the escaped ID comes first.
+        assert_eq!(
+            lines[0],
+            r#"{"sensor_id":"S\n2","count":1,"average":3,"min":3,"max":3,"range":0,"sem":null}"#
+        );
+    }
+
+    #[test]
+    fn test_select_sensors_top_n() {
+        let stats = vec![
+            sensor("B", 1, 5.0),
+            sensor("A", 1, 9.0),
+            sensor("C", 3, 1.0),
+        ];
+
+        let by_avg: Vec<_> = select_sensors(&stats, SortKey::Average, Some(2))
+            .iter()
+            .map(|s| s.sensor_id.as_str())
+            .collect();
+        assert_eq!(by_avg, ["A", "B"]);
+
+        let by_id: Vec<_> = select_sensors(&stats, SortKey::Id, None)
+            .iter()
+            .map(|s| s.sensor_id.as_str())
+            .collect();
+        assert_eq!(by_id, ["A", "B", "C"]);
+    }
+
+    #[test]
+    fn test_finish_sets_the_trailing_newline() {
+        let per_sensor = [sensor("S1", 2, 70.0)];
+        let stats = ProcessingStats {
+            total_rows: 2,
+            filtered_rows: 2,
+            average: Some(70.0),
+            ..Default::default()
+        };
+        let sensors: Vec<&SensorStats> = per_sensor.iter().collect();
+        let json = render_json(&stats, &sensors);
+        let text = render_text_table(&sensors, &RenderOptions::default()) + "\n\n";
+
+        for report in [&json, &text] {
+            let with = finish(report, true);
+            assert!(with.ends_with('\n') && !with.ends_with("\n\n"), "{with:?}");
+            let without = finish(report, false);
+            assert!(!without.ends_with('\n'), "{without:?}");
+            assert_eq!(with.trim_end(), without.trim_end());
+        }
+        assert!(finish(&json, false).ends_with('}'));
+    }
+
+    #[test]
+    fn test_average_and_percent_precision_are_independent() {
+        let opts = RenderOptions {
+            precision: Precision {
+                average: 2,
+                percent: 1,
+            },
+            ..Default::default()
+        };
+        let s = SensorStats {
+            max: 72.5,
+            ..sensor("S1", 3, 70.123_456)
+        };
+        let cells = sensor_cells(&s, &opts);
+        assert_eq!(cells[2], "70.12");
+        assert_eq!(cells[4], "72.500000");
+
+        let coverage = [Coverage {
+            sensor_id: "S1".to_string(),
+            expected: 3,
+            filled: 2,
+        }];
+        let out = render_coverage(&coverage, 50.0, opts.precision);
+        assert!(out.contains("   66.7%  (2 of 3 slots)"), "{out}");
+        let out = render_coverage(&coverage, 50.0, Precision::default());
+        assert!(out.contains("  66.67%"), "{out}");
+
+        let stats = ProcessingStats {
+            average: Some(70.123_456),
+            ..Default::default()
+        };
+        let out = render_markdown(&stats, &[&s], &opts);
+        assert!(out.contains("- Average value: 70.12\n"), "{out}");
+        let out = render_html(&stats, &[&s], &opts);
+        assert!(out.contains("<li>Average value: 70.12</li>"), "{out}");
+    }
+
+    #[test]
+    fn test_show_units_suffixes_matched_sensors_only() {
+        let mut meta = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut meta, "SensorID,Unit\nS1,°C\n".as_bytes()).unwrap();
+        let metadata = SensorMetadata::load(meta.path()).unwrap();
+        let opts = RenderOptions {
+            units: Some(&metadata),
+            distinct_values: false,
+            target: false,
+            noise_floor: false,
+            precision: Precision::default(),
+        };
+
+        let s1 = sensor("S1", 1, 36.5);
+        let s2 = sensor("S2", 1, 70.25);
+        assert_eq!(sensor_cells(&s1, &opts)[2], "36.500000 °C");
+        assert_eq!(sensor_cells(&s2, &opts)[2], "70.250000");
+
+        let table = render_text_table(&[&s1, &s2], &opts);
+        assert!(table.contains("36.500000 °C"));
+        assert!(table.contains("S2                            1        70.250000"));
+    }
+
+    #[test]
+    fn test_top_by_range_order_and_ties() {
+        let stats = vec![
+            sensor_with_range("C", 0.0, 5.0),
+            sensor_with_range("A", 10.0, 12.0),
+            sensor_with_range("B", -5.0, 0.0),
+        ];
+        let top = select_sensors(&stats, SortKey::Range, Some(2));
+        let ids: Vec<_> = top.iter().map(|s| s.sensor_id.as_str()).collect();
+        assert_eq!(ids, ["B", "C"]);
+
+        let ranking = render_range_ranking(&top);
+        assert!(ranking.starts_with("Top 2 sensors by range\n"));
+        assert!(ranking.find("1. B").unwrap() < ranking.find("2. C").unwrap());
+        assert!(!ranking.contains(" A "));
+    }
+
+    #[test]
+    fn test_timeline_sparkline_follows_density() {
+        let record = |sensor_id: &str, time: i64| crate::processor::Record {
+            timestamp: String::new(),
+            sensor_id: sensor_id.to_string(),
+            value: 1.0,
+            time: Some(time),
+        };
+        let records: Vec<_> = [0, 1, 2, 3, 45, 50, 70, 99]
+            .into_iter()
+            .map(|t| record("S1", t))
+            .chain([record("S2", 0), record("S2", 99)])
+            .collect();
+
+        let timeline = crate::series::timeline(&records, 5).unwrap();
+        assert_eq!((timeline.start, timeline.end), (0, 99));
+        assert_eq!(timeline.sensors[0].1, [4, 0, 2, 1, 1]);
+
+        let line = sparkline(&timeline.sensors[0].1);
+        assert_eq!(line.chars().count(), 5);
+        assert_eq!(line, "█ ▄▂▂");
+        assert_eq!(sparkline(&timeline.sensors[1].1), "█   █");
+        assert!(render_timeline(&timeline).contains("  S2                   |█   █|\n"));
+    }
+
+    #[test]
+    fn test_group_files_one_per_sensor() {
+        let dir = tempfile::tempdir().unwrap();
+        let per_sensor = [sensor("S1", 2, 15.0), sensor("hall/2", 1, 7.5)];
+        let sensors: Vec<&SensorStats> = per_sensor.iter().collect();
+        let values = BTreeMap::from([
+            ("S1".to_string(), vec![10.0, 20.0]),
+            ("hall/2".to_string(), vec![7.5]),
+        ]);
+
+        let json_dir = dir.path().join("json");
+        let written = write_group_files(
+            &json_dir,
+            &sensors,
+            Some(&values),
+            true,
+            WriteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(written, 2);
+        let s1 = std::fs::read_to_string(json_dir.join("S1.json")).unwrap();
+        assert!(
+            s1.starts_with(r#"{"sensor_id":"S1","count":2,"average":15,"#),
+            "{s1}"
+        );
+        assert!(s1.ends_with(",\"values\":[10,20]}\n"), "{s1}");
+        let hall = std::fs::read_to_string(json_dir.join("hall_2.json")).unwrap();
+        assert!(hall.contains(r#""sensor_id":"hall/2""#) && hall.contains(r#""values":[7.5]"#));
+
+        let csv_dir = dir.path().join("csv");
+        write_group_files(&csv_dir, &sensors, None, false, WriteOptions::default()).unwrap();
+        let mut names: Vec<String> = std::fs::read_dir(&csv_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["S1.csv", "hall_2.csv"]);
+        assert_eq!(
+            std::fs::read_to_string(csv_dir.join("hall_2.csv")).unwrap(),
+            "SensorID,Count,Average,Min,Max,Range,SEM\nhall/2,1,7.5,7.5,7.5,0,\n"
+        );
+
+        // Two IDs that sanitise to the same name are refused before anything is written.
+        let clash = [sensor("a/b", 1, 1.0), sensor("a_b", 1, 1.0)];
+        let clash: Vec<&SensorStats> = clash.iter().collect();
+        let clash_dir = dir.path().join("clash");
+        assert!(
+            write_group_files(&clash_dir, &clash, None, true, WriteOptions::default()).is_err()
+        );
+        assert!(!clash_dir.exists());
+        let clash = [sensor("Floor1", 1, 1.0), sensor("floor1", 1, 1.0)];
+        let clash: Vec<&SensorStats> = clash.iter().collect();
+        assert!(
+            write_group_files(&clash_dir, &clash, None, true, WriteOptions::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_join_selects_compared_sensors() {
+        let primary = [sensor("S1", 3, 10.0), sensor("S2", 2, 20.0)];
+        let other = [sensor("S2", 4, 25.0), sensor("S3", 1, 30.0)];
+        let ids = |join| -> Vec<&str> {
+            compare_sensors(&primary, &other, join)
+                .iter()
+                .map(|r| r.sensor_id)
+                .collect()
+        };
+        assert_eq!(ids(Join::Inner), ["S2"]);
+        assert_eq!(ids(Join::Left), ["S1", "S2"]);
+        assert_eq!(ids(Join::Outer), ["S1", "S2", "S3"]);
+
+        let rows = compare_sensors(&primary, &other, Join::Outer);
+        let text = render_comparison(&rows, Join::Outer, Precision::default());
+        assert!(text.starts_with("Per-sensor comparison (--join outer): 3 sensor(s)\n"));
+        let s2 = text
+            .lines()
+            .find(|l| l.trim_start().starts_with("S2"))
+            .unwrap();
+        assert!(s2.ends_with("+5.000000"), "{s2}");
+        let s3 = text
+            .lines()
+            .find(|l| l.trim_start().starts_with("S3"))
+            .unwrap();
+        assert_eq!(
+            s3.split_whitespace().take(3).collect::<Vec<_>>(),
+            ["S3", "-", "-"]
+        );
+    }
+}
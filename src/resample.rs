@@ -0,0 +1,280 @@
+use crate::processor::Record;
+
+/// Windows beyond this count need `--force`; a tiny step over a long span is usually a typo.
+pub const MAX_WINDOWS: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowStats {
+    pub start: i64,
+    pub count: usize,
+    pub average: Option<f64>,
+}
+
+/// Number of windows `sliding_windows` produces for the given records.
+pub fn window_count(records: &[&Record], step: i64) -> usize {
+    let times = records.iter().filter_map(|r| r.time);
+    match (times.clone().min(), times.max()) {
+        (Some(first), Some(last)) => ((last - first.div_euclid(step) * step) / step) as usize + 1,
+        _ => 0,
+    }
+}
+
+/// Aggregates records into windows `[start, start + width)` whose starts are multiples of
+/// `step` since the epoch, from the window starting at or before the first reading up to
+/// the one starting at or before the last. Windows overlap when `width > step`; empty
+/// windows are kept with no average.
+pub fn sliding_windows(records: &[&Record], width: i64, step: i64) -> Vec<WindowStats> {
+    let mut points: Vec<(i64, f64)> = records
+        .iter()
+        .map(|r| {
+            (
+                r.time.expect("timestamps are parsed for windowing"),
+                r.value,
+            )
+        })
+        .collect();
+    if points.is_empty() {
+        return Vec::new();
+    }
+    points.sort_unstable_by_key(|p| p.0);
+
+    // Prefix sums make every window O(1) once its bounds are known, without drift from
+    // repeatedly adding and subtracting values.
+    let mut prefix = Vec::with_capacity(points.len() + 1);
+    prefix.push(0.0);
+    for (_, value) in &points {
+        prefix.push(prefix[prefix.len() - 1] + value);
+    }
+
+    let last = points[points.len() - 1].0;
+    let mut start = points[0].0.div_euclid(step) * step;
+    let (mut lo, mut hi) = (0, 0);
+    let mut windows = Vec::new();
+
+    while start <= last {
+        while lo < points.len() && points[lo].0 < start {
+            lo += 1;
+        }
+        hi = hi.max(lo);
+        while hi < points.len() && points[hi].0 < start + width {
+            hi += 1;
+        }
+        let count = hi - lo;
+        windows.push(WindowStats {
+            start,
+            count,
+            average: (count > 0).then(|| (prefix[hi] - prefix[lo]) / count as f64),
+        });
+        start += step;
+    }
+
+    windows
+}
+
+/// How `--resample` reduces the readings of one bucket to a value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ResampleAgg {
+    #[default]
+    Mean,
+    Sum,
+    Min,
+    Max,
+    Count,
+    /// The mean of the two middle values for an even count.
+    Median,
+    /// Two points per bucket, the minimum and then the maximum, so peaks survive.
+    Minmax,
+}
+
+impl ResampleAgg {
+    fn reduce(self, values: &mut [f64]) -> f64 {
+        let n = values.len();
+        match self {
+            ResampleAgg::Mean => values.iter().sum::<f64>() / n as f64,
+            ResampleAgg::Sum => values.iter().sum(),
+            ResampleAgg::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+            ResampleAgg::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            ResampleAgg::Count => n as f64,
+            ResampleAgg::Median => {
+                values.sort_unstable_by(f64::total_cmp);
+                if n % 2 == 1 {
+                    values[n / 2]
+                } else {
+                    (values[n / 2 - 1] + values[n / 2]) / 2.0
+                }
+            }
+            ResampleAgg::Minmax => unreachable!("minmax is reduced as min and max"),
+        }
+    }
+}
+
+/// One sensor's timestamp-ordered series reduced with `agg` over consecutive `step`-wide
+/// buckets aligned to the epoch, as (bucket start, value). Buckets without readings are
+/// skipped, and `Minmax` yields two points per bucket. Only one bucket's values are held
+/// at a time.
+pub fn resample(series: &[&Record], step: i64, agg: ResampleAgg) -> Vec<(i64, f64)> {
+    let start = |r: &Record| {
+        r.time
+            .expect("timestamps are parsed for resampling")
+            .div_euclid(step)
+            * step
+    };
+    let mut values = Vec::new();
+    let mut points = Vec::new();
+    for bucket in series.chunk_by(|a, b| start(a) == start(b)) {
+        values.clear();
+        values.extend(bucket.iter().map(|r| r.value));
+        let start = start(bucket[0]);
+        if agg == ResampleAgg::Minmax {
+            points.push((start, ResampleAgg::Min.reduce(&mut values)));
+            points.push((start, ResampleAgg::Max.reduce(&mut values)));
+        } else {
+            points.push((start, agg.reduce(&mut values)));
+        }
+    }
+    points
+}
+
+/// Inserts the empty `step` buckets between `resample` points, carrying the previous
+/// point's value into at most `limit` of them after each reading. Buckets further into a
+/// gap stay missing.
+pub fn forward_fill(points: &[(i64, f64)], step: i64, limit: usize) -> Vec<(i64, f64)> {
+    let mut out = Vec::with_capacity(points.len());
+    for (i, &point) in points.iter().enumerate() {
+        if let Some(&(prev_start, prev_value)) = i.checked_sub(1).map(|j| &points[j]) {
+            let missing = ((point.0 - prev_start) / step - 1) as usize;
+            for k in 1..=missing.min(limit) {
+                out.push((prev_start + k as i64 * step, prev_value));
+            }
+        }
+        out.push(point);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp::MICROS_PER_SECOND;
+
+    fn record(second: i64, value: f64) -> Record {
+        Record {
+            timestamp: second.to_string(),
+            sensor_id: "S1".to_string(),
+            value,
+            time: Some(second * MICROS_PER_SECOND),
+        }
+    }
+
+    #[test]
+    fn test_overlapping_windows() {
+        let records = [
+            record(0, 1.0),
+            record(1, 2.0),
+            record(2, 3.0),
+            record(3, 4.0),
+            record(6, 10.0),
+        ];
+        let refs: Vec<&Record> = records.iter().rev().collect();
+        let windows = sliding_windows(&refs, 3 * MICROS_PER_SECOND, 2 * MICROS_PER_SECOND);
+
+        let summary: Vec<(i64, usize, Option<f64>)> = windows
+            .iter()
+            .map(|w| (w.start / MICROS_PER_SECOND, w.count, w.average))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0, 3, Some(2.0)),
+                (2, 2, Some(3.5)),
+                (4, 1, Some(10.0)),
+                (6, 1, Some(10.0)),
+            ]
+        );
+        assert_eq!(window_count(&refs, 2 * MICROS_PER_SECOND), windows.len());
+    }
+
+    #[test]
+    fn test_resample_aggregations_per_bucket() {
+        let records = [
+            record(0, 1.0),
+            record(1, 4.0),
+            record(3, 2.0),
+            record(60, 7.0),
+            record(61, 5.0),
+        ];
+        let refs: Vec<&Record> = records.iter().collect();
+        let step = 60 * MICROS_PER_SECOND;
+        let values = |agg| -> Vec<f64> {
+            resample(&refs, step, agg)
+                .into_iter()
+                .map(|(_, v)| v)
+                .collect()
+        };
+
+        assert_eq!(values(ResampleAgg::Sum), [7.0, 12.0]);
+        assert_eq!(values(ResampleAgg::Max), [4.0, 7.0]);
+        assert_eq!(values(ResampleAgg::Min), [1.0, 5.0]);
+        assert_eq!(values(ResampleAgg::Count), [3.0, 2.0]);
+        assert_eq!(values(ResampleAgg::Median), [2.0, 6.0]);
+        assert_eq!(values(ResampleAgg::Mean), [7.0 / 3.0, 6.0]);
+        let starts: Vec<i64> = resample(&refs, step, ResampleAgg::Sum)
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect();
+        assert_eq!(starts, [0, step]);
+    }
+
+    #[test]
+    fn test_minmax_keeps_each_buckets_extremes() {
+        let records = [
+            record(0, 3.0),
+            record(10, 9.5),
+            record(20, -1.0),
+            record(30, 4.0),
+            record(60, 7.0),
+            record(130, 2.0),
+            record(140, 2.0),
+        ];
+        let refs: Vec<&Record> = records.iter().collect();
+        let points: Vec<(i64, f64)> = resample(&refs, 60 * MICROS_PER_SECOND, ResampleAgg::Minmax)
+            .into_iter()
+            .map(|(t, v)| (t / MICROS_PER_SECOND, v))
+            .collect();
+
+        // A single reading, or equal ones, still give both rows; the empty 180s bucket none.
+        assert_eq!(
+            points,
+            [
+                (0, -1.0),
+                (0, 9.5),
+                (60, 7.0),
+                (60, 7.0),
+                (120, 2.0),
+                (120, 2.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_forward_fill_up_to_limit() {
+        let records = [record(0, 1.0), record(1, 3.0), record(9, 8.0)];
+        let refs: Vec<&Record> = records.iter().collect();
+        let step = 2 * MICROS_PER_SECOND;
+        let points = resample(&refs, step, ResampleAgg::Mean);
+        let seconds = |points: Vec<(i64, f64)>| -> Vec<(i64, f64)> {
+            points
+                .into_iter()
+                .map(|(t, v)| (t / MICROS_PER_SECOND, v))
+                .collect()
+        };
+        assert_eq!(seconds(points.clone()), [(0, 2.0), (8, 8.0)]);
+
+        // Buckets 2s, 4s and 6s are empty; with limit 2 the 0s mean reaches 2s and 4s.
+        assert_eq!(
+            seconds(forward_fill(&points, step, 2)),
+            [(0, 2.0), (2, 2.0), (4, 2.0), (8, 8.0)]
+        );
+        assert_eq!(forward_fill(&points, step, usize::MAX).len(), 5);
+    }
+}
@@ -0,0 +1,999 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::percentile;
+use crate::processor::Record;
+use crate::resample::{self, ResampleAgg};
+use crate::timestamp::{self, MICROS_PER_SECOND};
+
+/// Groups records by sensor, each group ordered by parsed timestamp. Readings with equal
+/// timestamps keep their file order.
+pub fn by_sensor<'a>(
+    records: impl Iterator<Item = &'a Record>,
+) -> BTreeMap<&'a str, Vec<&'a Record>> {
+    let mut groups: BTreeMap<&str, Vec<&Record>> = BTreeMap::new();
+    for r in records {
+        groups.entry(r.sensor_id.as_str()).or_default().push(r);
+    }
+    for series in groups.values_mut() {
+        series.sort_by_key(|r| r.time.expect("timestamps are parsed for per-sensor series"));
+    }
+    groups
+}
+
+/// The last `capacity` values of a series with O(1) mean and standard deviation.
+#[derive(Debug)]
+pub struct RollingWindow {
+    capacity: usize,
+    values: VecDeque<f64>,
+    sum: f64,
+    sum_sq: f64,
+}
+
+impl RollingWindow {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            values: VecDeque::with_capacity(capacity),
+            sum: 0.0,
+            sum_sq: 0.0,
+        }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.values.len() == self.capacity {
+            if let Some(old) = self.values.pop_front() {
+                self.sum -= old;
+                self.sum_sq -= old * old;
+            }
+        }
+        self.values.push_back(value);
+        self.sum += value;
+        self.sum_sq += value * value;
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.values.len() == self.capacity
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.sum / self.values.len() as f64
+    }
+
+    /// Population standard deviation of the values in the window.
+    pub fn std_dev(&self) -> f64 {
+        let n = self.values.len() as f64;
+        let mean = self.sum / n;
+        (self.sum_sq / n - mean * mean).max(0.0).sqrt()
+    }
+}
+
+/// The last `capacity` values with their mean and variance kept by Welford's update,
+/// which, unlike [`RollingWindow`], does not lose precision to `sum_sq / n - mean²`
+/// when the variance is small next to the mean.
+#[derive(Debug)]
+pub struct WelfordWindow {
+    capacity: usize,
+    values: VecDeque<f64>,
+    mean: f64,
+    /// Sum of squared deviations from `mean`.
+    m2: f64,
+}
+
+impl WelfordWindow {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            values: VecDeque::with_capacity(capacity),
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.values.len() == self.capacity {
+            if let Some(old) = self.values.pop_front() {
+                let n = self.values.len() as f64;
+                if n == 0.0 {
+                    (self.mean, self.m2) = (0.0, 0.0);
+                } else {
+                    let delta = old - self.mean;
+                    self.mean -= delta / n;
+                    self.m2 = (self.m2 - delta * (old - self.mean)).max(0.0);
+                }
+            }
+        }
+        self.values.push_back(value);
+        let delta = value - self.mean;
+        self.mean += delta / self.values.len() as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Sample variance of the values in the window; `None` with fewer than two.
+    pub fn variance(&self) -> Option<f64> {
+        let n = self.values.len();
+        (n > 1).then(|| self.m2 / (n - 1) as f64)
+    }
+}
+
+/// Running mean and variance of the last `window` values, as of the `row`-th value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OnlineStats {
+    pub row: usize,
+    /// Values in the window, fewer than `window` until it fills up.
+    pub count: usize,
+    pub mean: f64,
+    pub variance: Option<f64>,
+}
+
+/// Feeds `values` through a [`WelfordWindow`] and takes a snapshot after every
+/// `every`-th value.
+pub fn online_stats(
+    values: impl Iterator<Item = f64>,
+    window: usize,
+    every: usize,
+) -> Vec<OnlineStats> {
+    let mut welford = WelfordWindow::new(window);
+    let mut snapshots = Vec::new();
+    for (i, value) in values.enumerate() {
+        welford.push(value);
+        if (i + 1) % every == 0 {
+            snapshots.push(OnlineStats {
+                row: i + 1,
+                count: welford.values.len(),
+                mean: welford.mean(),
+                variance: welford.variance(),
+            });
+        }
+    }
+    snapshots
+}
+
+/// The last `capacity` values kept both in arrival order and sorted, so each push is a
+/// binary search plus one shift instead of re-sorting the window.
+#[derive(Debug)]
+pub struct RollingMedian {
+    capacity: usize,
+    values: VecDeque<f64>,
+    sorted: Vec<f64>,
+}
+
+impl RollingMedian {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            values: VecDeque::with_capacity(capacity),
+            sorted: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.values.len() == self.capacity {
+            if let Some(old) = self.values.pop_front() {
+                let i = self.sorted.partition_point(|v| v.total_cmp(&old).is_lt());
+                self.sorted.remove(i);
+            }
+        }
+        self.values.push_back(value);
+        let i = self.sorted.partition_point(|v| v.total_cmp(&value).is_lt());
+        self.sorted.insert(i, value);
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.values.len() == self.capacity
+    }
+
+    /// Middle value, or the mean of the two middle values for an even count.
+    pub fn median(&self) -> f64 {
+        let n = self.sorted.len();
+        if n % 2 == 1 {
+            self.sorted[n / 2]
+        } else {
+            (self.sorted[n / 2 - 1] + self.sorted[n / 2]) / 2.0
+        }
+    }
+}
+
+/// A per-sensor transform whose output replaces the sensor's readings in `--output`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform {
+    RollingSum(usize),
+    RollingMedian(usize),
+    MinmaxScale,
+    Cumulative,
+    Resample {
+        /// Bucket width in microseconds.
+        step: i64,
+        /// Forward-fill at most this many empty buckets after each reading.
+        ffill: Option<usize>,
+        agg: ResampleAgg,
+    },
+}
+
+impl Transform {
+    /// Applies the transform to one sensor's timestamp-ordered series, returning
+    /// (timestamp, value) rows.
+    pub fn apply<'a>(self, series: &[&'a Record]) -> Vec<(Cow<'a, str>, f64)> {
+        let rows = match self {
+            Transform::RollingSum(n) => rolling_sum(series, n),
+            Transform::RollingMedian(n) => rolling_median(series, n),
+            Transform::MinmaxScale => minmax_scale(series),
+            Transform::Cumulative => cumulative(series),
+            Transform::Resample { step, ffill, agg } => {
+                let mut points = resample::resample(series, step, agg);
+                if let Some(limit) = ffill {
+                    points = resample::forward_fill(&points, step, limit);
+                }
+                return points
+                    .into_iter()
+                    .map(|(start, value)| (Cow::Owned(timestamp::format_iso8601(start)), value))
+                    .collect();
+            }
+        };
+        rows.into_iter()
+            .map(|(r, value)| (Cow::Borrowed(r.timestamp.as_str()), value))
+            .collect()
+    }
+}
+
+/// Sum of each reading and the `n - 1` before it. Output starts at the first full window,
+/// so a series shorter than `n` produces nothing.
+pub fn rolling_sum<'a>(series: &[&'a Record], n: usize) -> Vec<(&'a Record, f64)> {
+    let mut window = RollingWindow::new(n);
+    let mut out = Vec::with_capacity(series.len().saturating_sub(n - 1));
+    for &r in series {
+        window.push(r.value);
+        if window.is_full() {
+            out.push((r, window.sum()));
+        }
+    }
+    out
+}
+
+/// Median of each reading and the `n - 1` before it, starting at the first full window.
+pub fn rolling_median<'a>(series: &[&'a Record], n: usize) -> Vec<(&'a Record, f64)> {
+    let mut window = RollingMedian::new(n);
+    let mut out = Vec::with_capacity(series.len().saturating_sub(n - 1));
+    for &r in series {
+        window.push(r.value);
+        if window.is_full() {
+            out.push((r, window.median()));
+        }
+    }
+    out
+}
+
+/// Each reading scaled to [0, 1] between the series' min and max, in two passes. A
+/// constant series has no range to scale by, so all its readings become 0.
+pub fn minmax_scale<'a>(series: &[&'a Record]) -> Vec<(&'a Record, f64)> {
+    let (min, max) = series
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), r| {
+            (min.min(r.value), max.max(r.value))
+        });
+    let range = max - min;
+    series
+        .iter()
+        .map(|&r| {
+            let scaled = if range > 0.0 {
+                (r.value - min) / range
+            } else {
+                0.0
+            };
+            (r, scaled)
+        })
+        .collect()
+}
+
+/// Each sensor's Tukey fences, `Q1 - k * IQR` and `Q3 + k * IQR`, over its values above
+/// `threshold`.
+pub fn iqr_fences<'a>(
+    records: impl Iterator<Item = &'a Record>,
+    threshold: f64,
+    k: f64,
+) -> HashMap<String, (f64, f64)> {
+    let mut values: HashMap<&str, Vec<f64>> = HashMap::new();
+    for r in records.filter(|r| r.value > threshold) {
+        values.entry(&r.sensor_id).or_default().push(r.value);
+    }
+    values
+        .into_iter()
+        .map(|(sensor_id, values)| {
+            let [q1, q3] = percentile::of_values(values, &[25.0, 75.0])[..] else {
+                unreachable!("two percentiles requested")
+            };
+            let iqr = q3 - q1;
+            (sensor_id.to_string(), (q1 - k * iqr, q3 + k * iqr))
+        })
+        .collect()
+}
+
+/// Running total of the series' values, one row per reading.
+pub fn cumulative<'a>(series: &[&'a Record]) -> Vec<(&'a Record, f64)> {
+    let mut total = 0.0;
+    series
+        .iter()
+        .map(|&r| {
+            total += r.value;
+            (r, total)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DroppedCount {
+    pub sensor_id: String,
+    pub dropped: usize,
+}
+
+/// Drops readings whose change from the sensor's previous kept reading exceeds
+/// `max_rate` per second. Two different values at the same instant count as an
+/// infinite rate. Kept records stay in file order; counts are sorted by sensor ID.
+pub fn drop_glitches(records: Vec<Record>, max_rate: f64) -> (Vec<Record>, Vec<DroppedCount>) {
+    drop_per_sensor(records, |series| {
+        let mut drop = Vec::new();
+        let mut prev: Option<&Record> = None;
+        for &(i, r) in series {
+            if let Some(p) = prev {
+                let dt = (r.time.unwrap() - p.time.unwrap()) as f64 / MICROS_PER_SECOND as f64;
+                let dv = (r.value - p.value).abs();
+                if dv > 0.0 && (dt == 0.0 || dv / dt > max_rate) {
+                    drop.push(i);
+                    continue;
+                }
+            }
+            prev = Some(r);
+        }
+        drop
+    })
+}
+
+/// Keeps each sensor's first `n` readings by timestamp.
+pub fn cap_per_sensor(records: Vec<Record>, n: usize) -> (Vec<Record>, Vec<DroppedCount>) {
+    drop_per_sensor(records, |series| {
+        series.iter().skip(n).map(|&(i, _)| i).collect()
+    })
+}
+
+/// Runs `select` over each sensor's timestamp-ordered (index, record) pairs and removes
+/// the indices it returns, keeping the rest in file order.
+fn drop_per_sensor(
+    records: Vec<Record>,
+    mut select: impl FnMut(&[(usize, &Record)]) -> Vec<usize>,
+) -> (Vec<Record>, Vec<DroppedCount>) {
+    let mut groups: BTreeMap<&str, Vec<(usize, &Record)>> = BTreeMap::new();
+    for (i, r) in records.iter().enumerate() {
+        groups.entry(r.sensor_id.as_str()).or_default().push((i, r));
+    }
+
+    let mut keep = vec![true; records.len()];
+    let mut counts = Vec::new();
+    for (sensor_id, mut series) in groups {
+        series.sort_by_key(|(_, r)| r.time.expect("timestamps are parsed for per-sensor series"));
+        let dropped = select(&series);
+        if !dropped.is_empty() {
+            counts.push(DroppedCount {
+                sensor_id: sensor_id.to_string(),
+                dropped: dropped.len(),
+            });
+        }
+        for i in dropped {
+            keep[i] = false;
+        }
+    }
+
+    let kept = records
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(r, keep)| keep.then_some(r))
+        .collect();
+    (kept, counts)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairDelta {
+    pub left: String,
+    pub right: String,
+    /// Timestamps with a reading from both sensors.
+    pub matched: usize,
+    /// Timestamps with a reading from only the left, or only the right, sensor.
+    pub unmatched_left: usize,
+    pub unmatched_right: usize,
+    /// Stats of `left - right` over the matched timestamps; `None` without any.
+    pub mean: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Sample standard deviation; `None` with fewer than two pairs.
+    pub std_dev: Option<f64>,
+}
+
+/// Joins two sensors' readings on equal timestamps and summarizes `left - right`.
+/// Expects at most one reading per sensor and timestamp.
+pub fn pair_delta<'a>(
+    records: impl Iterator<Item = &'a Record>,
+    left: &str,
+    right: &str,
+) -> PairDelta {
+    let time = |r: &Record| r.time.expect("timestamps are parsed for --pair-delta");
+    let mut right_values = HashMap::new();
+    let mut left_values = Vec::new();
+    for r in records {
+        if r.sensor_id == left {
+            left_values.push((time(r), r.value));
+        } else if r.sensor_id == right {
+            right_values.insert(time(r), r.value);
+        }
+    }
+
+    let deltas: Vec<f64> = left_values
+        .iter()
+        .filter_map(|(t, v)| right_values.get(t).map(|r| v - r))
+        .collect();
+    let matched = deltas.len();
+    let n = matched as f64;
+    let mean = (matched > 0).then(|| deltas.iter().sum::<f64>() / n);
+    let std_dev = mean
+        .filter(|_| matched > 1)
+        .map(|mean| (deltas.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt());
+    PairDelta {
+        left: left.to_string(),
+        right: right.to_string(),
+        matched,
+        unmatched_left: left_values.len() - matched,
+        unmatched_right: right_values.len() - matched,
+        mean,
+        min: deltas.iter().copied().reduce(f64::min),
+        max: deltas.iter().copied().reduce(f64::max),
+        std_dev,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockReset {
+    pub sensor_id: String,
+    /// 1-based data row of the reading that jumped back.
+    pub row: usize,
+    /// Timestamp of the sensor's previous reading, and of this one.
+    pub from: i64,
+    pub to: i64,
+}
+
+/// Finds readings, in file order, whose timestamp is earlier than the sensor's
+/// previous reading. Each backward jump counts once; later readings are compared with
+/// the reading after the jump, so a logger that resets and then runs on is one reset.
+pub fn clock_resets(records: &[Record]) -> Vec<ClockReset> {
+    let mut previous: HashMap<&str, i64> = HashMap::new();
+    let mut resets = Vec::new();
+    for (i, r) in records.iter().enumerate() {
+        let time = r
+            .time
+            .expect("timestamps are parsed for --detect-clock-resets");
+        if let Some(from) = previous.insert(r.sensor_id.as_str(), time) {
+            if time < from {
+                resets.push(ClockReset {
+                    sensor_id: r.sensor_id.clone(),
+                    row: i + 1,
+                    from,
+                    to: time,
+                });
+            }
+        }
+    }
+    resets
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueDrop {
+    pub sensor_id: String,
+    /// 1-based data row of the reading whose value fell.
+    pub row: usize,
+    pub time: i64,
+    /// Value of the sensor's previous reading by timestamp, and of this one.
+    pub from: f64,
+    pub to: f64,
+}
+
+/// Finds readings of counter-type sensors whose value is below that of the sensor's
+/// previous reading by timestamp (readings with equal timestamps keep their file order).
+/// A counter that resets and counts on from zero shows up as one drop.
+pub fn value_drops(records: &[Record]) -> Vec<ValueDrop> {
+    let mut groups: BTreeMap<&str, Vec<(usize, &Record)>> = BTreeMap::new();
+    for (i, r) in records.iter().enumerate() {
+        groups.entry(r.sensor_id.as_str()).or_default().push((i, r));
+    }
+
+    let mut drops = Vec::new();
+    for series in groups.values_mut() {
+        series.sort_by_key(|(_, r)| {
+            r.time
+                .expect("timestamps are parsed for --expect-monotonic-values")
+        });
+        for pair in series.windows(2) {
+            let ((_, previous), (i, r)) = (pair[0], pair[1]);
+            if r.value < previous.value {
+                drops.push(ValueDrop {
+                    sensor_id: r.sensor_id.clone(),
+                    row: i + 1,
+                    time: r.time.unwrap_or(0),
+                    from: previous.value,
+                    to: r.value,
+                });
+            }
+        }
+    }
+    drops
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flatline {
+    pub sensor_id: String,
+    pub start: i64,
+    pub end: i64,
+    pub readings: usize,
+    pub value: f64,
+}
+
+/// Finds maximal runs of readings within `epsilon` of the run's first value that span at
+/// least `min_duration` microseconds. Comparing against the first value (rather than the
+/// previous reading) keeps a slow drift from counting as frozen.
+pub fn flatlines(series: &[&Record], min_duration: i64, epsilon: f64) -> Vec<Flatline> {
+    let mut found = Vec::new();
+    let mut start = 0;
+
+    for i in 1..=series.len() {
+        let run_continues =
+            i < series.len() && (series[i].value - series[start].value).abs() <= epsilon;
+        if run_continues {
+            continue;
+        }
+        let (first, last) = (series[start], series[i - 1]);
+        let (t0, t1) = (first.time.unwrap_or(0), last.time.unwrap_or(0));
+        if i - start > 1 && t1 - t0 >= min_duration {
+            found.push(Flatline {
+                sensor_id: first.sensor_id.clone(),
+                start: t0,
+                end: t1,
+                readings: i - start,
+                value: first.value,
+            });
+        }
+        start = i;
+    }
+
+    found
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coverage {
+    pub sensor_id: String,
+    /// Slots of the expected interval between the sensor's first and last reading.
+    pub expected: usize,
+    /// Expected slots with at least one reading.
+    pub filled: usize,
+}
+
+impl Coverage {
+    pub fn percent(&self) -> f64 {
+        self.filled as f64 / self.expected as f64 * 100.0
+    }
+}
+
+/// Compares a sensor's timestamp-ordered readings against one every `interval`
+/// microseconds over its observed span. Each reading fills the slot nearest to it, so
+/// jitter below half an interval does not cost coverage and extra readings add none.
+pub fn coverage(series: &[&Record], interval: i64) -> Coverage {
+    let time = |r: &Record| r.time.expect("timestamps are parsed for coverage");
+    let first = time(series[0]);
+    let last = time(series[series.len() - 1]);
+    let slot = |t: i64| (t - first + interval / 2) / interval;
+
+    let mut filled = 0;
+    let mut previous = None;
+    for &r in series {
+        let s = slot(time(r));
+        if previous != Some(s) {
+            filled += 1;
+            previous = Some(s);
+        }
+    }
+    Coverage {
+        sensor_id: series[0].sensor_id.clone(),
+        expected: slot(last) as usize + 1,
+        filled,
+    }
+}
+
+/// Measurement-noise estimate of a timestamp-ordered series: the sample standard
+/// deviation of its first differences divided by sqrt(2). On a slowly varying signal each
+/// difference holds the noise of two readings, hence the sqrt(2). `None` below three
+/// readings, which leave fewer than two differences.
+pub fn noise_floor(series: &[&Record]) -> Option<f64> {
+    if series.len() < 3 {
+        return None;
+    }
+    let diffs: Vec<f64> = series.windows(2).map(|w| w[1].value - w[0].value).collect();
+    let n = diffs.len() as f64;
+    let mean = diffs.iter().sum::<f64>() / n;
+    let variance = diffs.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    Some(variance.sqrt() / std::f64::consts::SQRT_2)
+}
+
+/// Relative weights of the `--quality-score` components.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityWeights {
+    pub coverage: f64,
+    pub noise: f64,
+    pub flatline: f64,
+}
+
+impl Default for QualityWeights {
+    fn default() -> Self {
+        Self {
+            coverage: 1.0,
+            noise: 1.0,
+            flatline: 1.0,
+        }
+    }
+}
+
+/// A sensor's `--quality-score`: each component is in [0, 1], 1 being best.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityScore {
+    pub sensor_id: String,
+    /// Coverage percentage / 100.
+    pub coverage: f64,
+    /// 1 - noise floor / standard deviation of the values, clamped to [0, 1].
+    pub noise: f64,
+    /// 1 - the share of readings inside flatline runs.
+    pub flatline: f64,
+    /// The weighted mean of the components, scaled to 0-100.
+    pub score: f64,
+}
+
+/// Combines a sensor's coverage, noise floor and the readings in its flatline runs into
+/// one 0-100 score.
+/// A noise floor equal to the values' own spread means the readings look like pure
+/// noise; a constant series has no noise, and one too short to estimate it scores 0.
+pub fn quality_score(
+    series: &[&Record],
+    coverage: &Coverage,
+    flat_readings: usize,
+    weights: QualityWeights,
+) -> QualityScore {
+    let n = series.len() as f64;
+    let mean = series.iter().map(|r| r.value).sum::<f64>() / n;
+    let std = (series.iter().map(|r| (r.value - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+    let noise = match noise_floor(series) {
+        None => 0.0,
+        Some(_) if std == 0.0 => 1.0,
+        Some(floor) => (1.0 - floor / std).clamp(0.0, 1.0),
+    };
+    let flatline = 1.0 - flat_readings as f64 / n;
+    let coverage = coverage.percent() / 100.0;
+
+    let total = weights.coverage + weights.noise + weights.flatline;
+    let score = 100.0
+        * (weights.coverage * coverage + weights.noise * noise + weights.flatline * flatline)
+        / total;
+    QualityScore {
+        sensor_id: series[0].sensor_id.clone(),
+        coverage,
+        noise,
+        flatline,
+        score,
+    }
+}
+
+/// Reading counts per time slot for `--timeline`, over the span of the whole input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    pub start: i64,
+    pub end: i64,
+    /// Each sensor's count per slot, sorted by sensor ID.
+    pub sensors: Vec<(String, Vec<usize>)>,
+}
+
+/// Splits the span from the earliest to the latest reading into `slots` equal slots
+/// and counts each sensor's readings per slot. `None` without any readings.
+pub fn timeline(records: &[Record], slots: usize) -> Option<Timeline> {
+    let time = |r: &Record| r.time.expect("timestamps are parsed for --timeline");
+    let start = records.iter().map(time).min()?;
+    let end = records.iter().map(time).max()?;
+    // Widen by one so the latest reading lands in the last slot, not one past it.
+    let span = (end - start) as i128 + 1;
+
+    let mut counts: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for r in records {
+        let slot = ((time(r) - start) as i128 * slots as i128 / span) as usize;
+        counts
+            .entry(r.sensor_id.as_str())
+            .or_insert_with(|| vec![0; slots])[slot] += 1;
+    }
+    Some(Timeline {
+        start,
+        end,
+        sensors: counts
+            .into_iter()
+            .map(|(id, counts)| (id.to_string(), counts))
+            .collect(),
+    })
+}
+
+#[derive(Debug)]
+pub struct Anomaly<'a> {
+    pub record: &'a Record,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub zscore: f64,
+}
+
+/// Flags readings more than `k` standard deviations away from the mean of the `window`
+/// readings before them. Nothing is flagged until a full window has been seen.
+pub fn rolling_zscore<'a>(series: &[&'a Record], window: usize, k: f64) -> Vec<Anomaly<'a>> {
+    let mut rolling = RollingWindow::new(window);
+    let mut anomalies = Vec::new();
+
+    for &r in series {
+        if rolling.is_full() {
+            let (mean, std_dev) = (rolling.mean(), rolling.std_dev());
+            let deviation = (r.value - mean).abs();
+            let zscore = if std_dev > 0.0 {
+                deviation / std_dev
+            } else if deviation > 0.0 {
+                f64::INFINITY
+            } else {
+                0.0
+            };
+            if zscore > k {
+                anomalies.push(Anomaly {
+                    record: r,
+                    mean,
+                    std_dev,
+                    zscore,
+                });
+            }
+        }
+        rolling.push(r.value);
+    }
+
+    anomalies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(values: &[f64]) -> Vec<Record> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| Record {
+                timestamp: format!("t{i}"),
+                sensor_id: "S1".to_string(),
+                value,
+                time: Some(i as i64),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_welford_window_matches_batch_variance() {
+        let values: Vec<f64> = (0..200)
+            .map(|i| 1e6 + ((i * 37) % 11) as f64 * 0.1)
+            .collect();
+        let snapshots = online_stats(values.iter().copied(), 25, 10);
+        assert_eq!(snapshots.len(), 20);
+
+        for s in &snapshots {
+            let window = &values[s.row.saturating_sub(25)..s.row];
+            assert_eq!(s.count, window.len());
+            let n = window.len() as f64;
+            let mean = window.iter().sum::<f64>() / n;
+            let variance = window.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            assert!(
+                (s.mean - mean).abs() < 1e-6,
+                "row {}: {} vs {mean}",
+                s.row,
+                s.mean
+            );
+            let online = s.variance.unwrap();
+            assert!(
+                (online - variance).abs() < 1e-6,
+                "row {}: {online} vs {variance}",
+                s.row
+            );
+        }
+
+        let mut single = WelfordWindow::new(1);
+        single.push(3.0);
+        single.push(5.0);
+        assert_eq!((single.mean(), single.variance()), (5.0, None));
+    }
+
+    #[test]
+    fn test_rolling_window_mean_and_std() {
+        let mut w = RollingWindow::new(3);
+        for v in [1.0, 2.0, 3.0, 4.0] {
+            w.push(v);
+        }
+        assert!(w.is_full());
+        assert!((w.mean() - 3.0).abs() < 1e-12);
+        assert!((w.std_dev() - (2.0f64 / 3.0).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_rolling_zscore_flags_injected_spike() {
+        let records = series(&[10.0, 10.5, 9.5, 10.2, 9.8, 10.1, 25.0, 10.0, 9.9]);
+        let refs: Vec<&Record> = records.iter().collect();
+        let anomalies = rolling_zscore(&refs, 4, 3.0);
+
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].record.value, 25.0);
+        assert!(anomalies[0].zscore > 3.0);
+    }
+
+    #[test]
+    fn test_rolling_median_removes_spike() {
+        let records = series(&[10.0, 11.0, 10.5, 95.0, 10.2, 10.8, 10.4]);
+        let refs: Vec<&Record> = records.iter().collect();
+        let medians: Vec<f64> = Transform::RollingMedian(3)
+            .apply(&refs)
+            .iter()
+            .map(|(_, v)| *v)
+            .collect();
+
+        assert_eq!(medians, [10.5, 11.0, 10.5, 10.8, 10.4]);
+        assert!(medians.iter().all(|&m| m < 12.0));
+
+        let mut even = RollingMedian::new(4);
+        [4.0, 1.0, 3.0, 2.0, 10.0]
+            .iter()
+            .for_each(|&v| even.push(v));
+        assert_eq!(even.median(), 2.5);
+    }
+
+    #[test]
+    fn test_drop_glitches_removes_impossible_jump() {
+        // One reading per second rising 0.5/s, with a 40-unit spike at t=4.
+        let mut records = series(&[10.0, 10.5, 11.0, 11.5, 51.5, 12.0, 12.5]);
+        for r in &mut records {
+            r.time = Some(r.time.unwrap() * MICROS_PER_SECOND);
+        }
+        let (kept, counts) = drop_glitches(records, 2.0);
+
+        let values: Vec<f64> = kept.iter().map(|r| r.value).collect();
+        assert_eq!(values, [10.0, 10.5, 11.0, 11.5, 12.0, 12.5]);
+        assert_eq!(
+            counts,
+            [DroppedCount {
+                sensor_id: "S1".to_string(),
+                dropped: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn test_cap_per_sensor_keeps_earliest() {
+        let mut records = series(&[5.0, 4.0, 3.0, 2.0, 1.0]);
+        records.reverse();
+        let (kept, counts) = cap_per_sensor(records, 2);
+
+        // File order is preserved; the two earliest readings (t0, t1) are kept.
+        let values: Vec<f64> = kept.iter().map(|r| r.value).collect();
+        assert_eq!(values, [4.0, 5.0]);
+        assert_eq!(counts[0].dropped, 3);
+    }
+
+    #[test]
+    fn test_coverage_with_half_the_samples_missing() {
+        // Expected every second over 0..=9, but only the even seconds arrived (one twice).
+        let mut records = series(&[1.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+        for (r, t) in records.iter_mut().zip([0, 2, 4, 4, 6, 9]) {
+            r.time = Some(t * MICROS_PER_SECOND);
+        }
+        let refs: Vec<&Record> = records.iter().collect();
+        let c = coverage(&refs, MICROS_PER_SECOND);
+
+        assert_eq!((c.expected, c.filled), (10, 5));
+        assert_eq!(c.percent(), 50.0);
+    }
+
+    #[test]
+    fn test_noise_floor_recovers_added_noise() {
+        // A slow sine plus Gaussian noise of std-dev 0.5 (Box-Muller over an LCG).
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut uniform = || {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            ((state >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+        };
+        let values: Vec<f64> = (0..5_000)
+            .map(|i| {
+                let noise =
+                    (-2.0 * uniform().ln()).sqrt() * (2.0 * std::f64::consts::PI * uniform()).cos();
+                20.0 + 5.0 * (i as f64 / 500.0).sin() + 0.5 * noise
+            })
+            .collect();
+        // Shuffled in the file: the estimate follows the timestamps.
+        let records = series(&values);
+        let shuffled: Vec<&Record> = (0..values.len())
+            .map(|i| &records[i * 7_919 % values.len()])
+            .collect();
+        assert!(noise_floor(&shuffled).unwrap() > 2.0);
+        let groups = by_sensor(shuffled.into_iter());
+        let estimate = noise_floor(&groups["S1"]).unwrap();
+        assert!((estimate - 0.5).abs() < 0.05, "{estimate}");
+
+        let short = series(&[1.0, 2.0]);
+        assert_eq!(noise_floor(&short.iter().collect::<Vec<_>>()), None);
+    }
+
+    #[test]
+    fn test_minmax_scale_to_unit_range() {
+        let records = series(&[10.0, 40.0, 20.0, 50.0]);
+        let refs: Vec<&Record> = records.iter().collect();
+        let scaled: Vec<f64> = Transform::MinmaxScale
+            .apply(&refs)
+            .iter()
+            .map(|(_, v)| *v)
+            .collect();
+        assert_eq!(scaled, [0.0, 0.75, 0.25, 1.0]);
+
+        let constant = series(&[7.0, 7.0]);
+        let refs: Vec<&Record> = constant.iter().collect();
+        let scaled: Vec<f64> = minmax_scale(&refs).iter().map(|(_, v)| *v).collect();
+        assert_eq!(scaled, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_cumulative_running_total() {
+        let records = series(&[2.0, 0.5, 3.0, -1.0]);
+        let refs: Vec<&Record> = records.iter().collect();
+        let rows = Transform::Cumulative.apply(&refs);
+        let totals: Vec<f64> = rows.iter().map(|(_, v)| *v).collect();
+        assert_eq!(totals, [2.0, 2.5, 5.5, 4.5]);
+        assert_eq!(rows[2].0, "t2");
+    }
+
+    #[test]
+    fn test_rolling_sum() {
+        let records = series(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        let refs: Vec<&Record> = records.iter().collect();
+
+        let sums: Vec<f64> = Transform::RollingSum(3)
+            .apply(&refs)
+            .iter()
+            .map(|(_, v)| *v)
+            .collect();
+        assert_eq!(sums, [6.0, 9.0, 12.0]);
+
+        let (first, _) = rolling_sum(&refs, 3)[0];
+        assert_eq!(first.timestamp, "t2");
+        assert!(rolling_sum(&refs, 6).is_empty());
+        assert_eq!(rolling_sum(&refs, 1).len(), 5);
+    }
+
+    #[test]
+    fn test_flatline_run_detected() {
+        let records = series(&[1.0, 2.0, 5.0, 5.0, 5.0, 5.0, 5.0, 6.0, 7.0, 7.0]);
+        let refs: Vec<&Record> = records.iter().collect();
+
+        let found = flatlines(&refs, 3, 1e-9);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].start, found[0].end), (2, 6));
+        assert_eq!(found[0].readings, 5);
+        assert_eq!(found[0].value, 5.0);
+
+        assert!(flatlines(&refs, 5, 1e-9).is_empty());
+        assert_eq!(flatlines(&refs, 1, 1e-9).len(), 2);
+    }
+}
@@ -0,0 +1,156 @@
+//! `--stats-on-signal`: SIGUSR1 asks for the running stats of the read pass on stderr,
+//! without interrupting it. `--partial-on-interrupt`: the first SIGINT stops the read
+//! pass so the rows read so far are reported; a second one exits at once. The handlers
+//! only set flags; the read loop polls them.
+
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Exit code of a run cut short by `--partial-on-interrupt`, as for a shell's SIGINT.
+pub const PARTIAL_EXIT_CODE: i32 = 130;
+
+/// Installs the SIGUSR1 handler. A no-op where there is no SIGUSR1 (Windows).
+pub fn install() -> Result<()> {
+    #[cfg(all(unix, feature = "signals"))]
+    {
+        extern "C" fn handle(_: libc::c_int) {
+            REQUESTED.store(true, Ordering::Relaxed);
+        }
+        // SAFETY: `handle` only stores to an atomic, which is async-signal-safe, and
+        // SA_RESTART keeps blocking reads from failing with EINTR.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut()) != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Installs the SIGINT handler for `--partial-on-interrupt`. A no-op without Unix signals.
+pub fn install_interrupt() -> Result<()> {
+    #[cfg(all(unix, feature = "signals"))]
+    {
+        extern "C" fn handle(_: libc::c_int) {
+            if INTERRUPTED.swap(true, Ordering::Relaxed) {
+                // SAFETY: `_exit` is async-signal-safe, unlike `std::process::exit`.
+                unsafe { libc::_exit(PARTIAL_EXIT_CODE) }
+            }
+        }
+        // SAFETY: as for `install`.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut()) != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether SIGINT has been received.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+pub fn set_interrupted(value: bool) {
+    INTERRUPTED.store(value, Ordering::Relaxed);
+}
+
+/// Whether a snapshot was requested since the last call.
+pub fn take_request() -> bool {
+    REQUESTED.load(Ordering::Relaxed) && REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+/// Running totals of the read pass.
+#[derive(Debug)]
+pub struct Progress {
+    start: Instant,
+    threshold: f64,
+    rows: usize,
+    filtered: usize,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Progress {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            start: Instant::now(),
+            threshold,
+            rows: 0,
+            filtered: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn add(&mut self, value: f64) {
+        self.rows += 1;
+        if value > self.threshold {
+            self.filtered += 1;
+            self.sum += value;
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+    }
+
+    /// One-line summary of the rows read so far.
+    pub fn snapshot(&self) -> String {
+        let mut out = format!(
+            "Interim stats after {:.1?}: {} rows read, {} after filter",
+            self.start.elapsed(),
+            self.rows,
+            self.filtered
+        );
+        if self.filtered > 0 {
+            out.push_str(&format!(
+                ", average {:.6} (min {:.6}, max {:.6})",
+                self.sum / self.filtered as f64,
+                self.min,
+                self.max
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_reports_running_stats() {
+        let mut progress = Progress::new(0.0);
+        for value in [10.0, -1.0, 30.0] {
+            progress.add(value);
+        }
+        let snapshot = progress.snapshot();
+        assert!(
+            snapshot.contains("3 rows read, 2 after filter"),
+            "{snapshot}"
+        );
+        assert!(
+            snapshot.ends_with("average 20.000000 (min 10.000000, max 30.000000)"),
+            "{snapshot}"
+        );
+
+        REQUESTED.store(true, Ordering::Relaxed);
+        assert!(take_request());
+        assert!(!take_request());
+    }
+}
@@ -0,0 +1,167 @@
+//! Snappy block compression (the raw format Parquet pages use, without framing).
+//!
+//! A greedy single-pass encoder: 4-byte matches found through a hash of recent
+//! positions become copies, everything else is emitted as literals.
+
+const HASH_BITS: u32 = 14;
+const MAX_OFFSET: usize = 65_535;
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 16);
+    varint(&mut out, input.len() as u64);
+
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i + 4 <= input.len() {
+        let key = u32::from_le_bytes(input[i..i + 4].try_into().unwrap());
+        let slot = (key.wrapping_mul(0x1e35_a7bd) >> (32 - HASH_BITS)) as usize;
+        let candidate = std::mem::replace(&mut table[slot], i);
+        if candidate == usize::MAX
+            || i - candidate > MAX_OFFSET
+            || input[candidate..candidate + 4] != input[i..i + 4]
+        {
+            i += 1;
+            continue;
+        }
+
+        literal(&mut out, &input[literal_start..i]);
+        let mut len = 4;
+        while i + len < input.len() && input[candidate + len] == input[i + len] {
+            len += 1;
+        }
+        copy(&mut out, i - candidate, len);
+        i += len;
+        literal_start = i;
+    }
+    literal(&mut out, &input[literal_start..]);
+    out
+}
+
+fn varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn literal(out: &mut Vec<u8>, bytes: &[u8]) {
+    if bytes.is_empty() {
+        return;
+    }
+    let n = bytes.len() - 1;
+    if n < 60 {
+        out.push((n as u8) << 2);
+    } else {
+        // Tags 60..=63 say how many little-endian bytes of length follow.
+        let width = (usize::BITS - n.leading_zeros()).div_ceil(8) as usize;
+        out.push((59 + width as u8) << 2);
+        out.extend_from_slice(&n.to_le_bytes()[..width]);
+    }
+    out.extend_from_slice(bytes);
+}
+
+fn copy(out: &mut Vec<u8>, offset: usize, mut len: usize) {
+    // Leave at least 4 bytes for the last copy, the shortest a 1-byte-offset copy holds.
+    while len >= 68 {
+        copy2(out, offset, 64);
+        len -= 64;
+    }
+    if len > 64 {
+        copy2(out, offset, 60);
+        len -= 60;
+    }
+    if len <= 11 && offset < 2048 {
+        out.push(0b01 | ((len as u8 - 4) << 2) | (((offset >> 8) as u8) << 5));
+        out.push(offset as u8);
+    } else {
+        copy2(out, offset, len);
+    }
+}
+
+fn copy2(out: &mut Vec<u8>, offset: usize, len: usize) {
+    out.push(0b10 | ((len as u8 - 1) << 2));
+    out.extend_from_slice(&(offset as u16).to_le_bytes());
+}
+
+/// Inverse of [`compress`], for reading pages back in tests.
+#[cfg(test)]
+pub fn decompress(input: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let mut len = 0u64;
+    let mut shift = 0;
+    loop {
+        let b = input[pos];
+        pos += 1;
+        len |= u64::from(b & 0x7f) << shift;
+        shift += 7;
+        if b < 0x80 {
+            break;
+        }
+    }
+
+    let mut out: Vec<u8> = Vec::with_capacity(len as usize);
+    while pos < input.len() {
+        let tag = input[pos];
+        pos += 1;
+        let (offset, len) = match tag & 0b11 {
+            0b00 => {
+                let mut n = usize::from(tag >> 2);
+                if n >= 60 {
+                    let width = n - 59;
+                    let mut bytes = [0; 8];
+                    bytes[..width].copy_from_slice(&input[pos..pos + width]);
+                    n = usize::from_le_bytes(bytes);
+                    pos += width;
+                }
+                out.extend_from_slice(&input[pos..pos + n + 1]);
+                pos += n + 1;
+                continue;
+            }
+            0b01 => {
+                let offset = usize::from(tag >> 5) << 8 | usize::from(input[pos]);
+                pos += 1;
+                (offset, usize::from((tag >> 2) & 0b111) + 4)
+            }
+            0b10 => {
+                let offset = usize::from(u16::from_le_bytes([input[pos], input[pos + 1]]));
+                pos += 2;
+                (offset, usize::from(tag >> 2) + 1)
+            }
+            _ => panic!("4-byte offsets are never written"),
+        };
+        let start = out.len() - offset;
+        for k in 0..len {
+            out.push(out[start + k]);
+        }
+    }
+    assert_eq!(out.len() as u64, len);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_compresses_repetition() {
+        let repetitive: Vec<u8> = "2024-01-01T00:00:00,S1,".repeat(500).into_bytes();
+        let packed = compress(&repetitive);
+        assert!(packed.len() < repetitive.len() / 10, "{}", packed.len());
+        assert_eq!(decompress(&packed), repetitive);
+
+        // Long literals, short inputs and overlapping copies.
+        let noise: Vec<u8> = (0..5_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        for input in [
+            &noise[..],
+            b"",
+            b"abc",
+            b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        ] {
+            assert_eq!(decompress(&compress(input)), input);
+        }
+    }
+}
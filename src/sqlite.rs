@@ -0,0 +1,572 @@
+//! `--roster-db`: reads one column of a table straight from a SQLite database file.
+//!
+//! Only what a roster lookup needs is implemented: the file header, table b-tree pages
+//! (interior and leaf, with overflow chains), the record format and enough of
+//! `CREATE TABLE` to find a column's position. The database is opened read-only and
+//! never locked, so it should not be written to while it is read.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+const MAGIC: &[u8] = b"SQLite format 3\0";
+const HEADER_SIZE: usize = 100;
+
+const PAGE_INTERIOR_TABLE: u8 = 0x05;
+const PAGE_LEAF_TABLE: u8 = 0x0d;
+
+/// Interior pages deeper than this mean a corrupt (cyclic) b-tree.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Int(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    fn into_text(self) -> Option<String> {
+        match self {
+            Value::Null => None,
+            Value::Int(n) => Some(n.to_string()),
+            Value::Real(x) => Some(x.to_string()),
+            Value::Text(s) => Some(s),
+            Value::Blob(b) => Some(String::from_utf8_lossy(&b).into_owned()),
+        }
+    }
+}
+
+/// Every non-NULL value of `column` in `table`, as text, in rowid order.
+pub fn read_column(path: &Path, table: &str, column: &str) -> Result<Vec<String>> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Cannot open database '{}'", path.display()))?;
+    let db = Database::new(&bytes)
+        .with_context(|| format!("'{}' is not a readable SQLite database", path.display()))?;
+    check_wal(path, &bytes)?;
+
+    let schema = db
+        .rows(1)
+        .with_context(|| format!("Cannot read the schema of '{}'", path.display()))?;
+    let mut tables = Vec::new();
+    let mut found = None;
+    for (_, row) in schema {
+        let mut row = row.into_iter();
+        let (kind, name) = (row.next(), row.next());
+        let (_, root, sql) = (row.next(), row.next(), row.next());
+        if kind != Some(Value::Text("table".to_string())) {
+            continue;
+        }
+        let Some(Value::Text(name)) = name else {
+            continue;
+        };
+        if name.eq_ignore_ascii_case(table) {
+            found = Some((root, sql));
+        }
+        tables.push(name);
+    }
+    let Some((root, sql)) = found else {
+        anyhow::bail!(
+            "Table '{table}' not found in '{}' (tables: {})",
+            path.display(),
+            list(&tables)
+        );
+    };
+
+    let sql = match sql {
+        Some(Value::Text(sql)) => sql,
+        _ => String::new(),
+    };
+    let columns = table_columns(&sql);
+    if sql.to_ascii_uppercase().contains("WITHOUT ROWID") {
+        anyhow::bail!(
+            "Table '{table}' in '{}' is a WITHOUT ROWID table, which is not supported",
+            path.display()
+        );
+    }
+    let Some(index) = columns
+        .iter()
+        .position(|c| c.name.eq_ignore_ascii_case(column))
+    else {
+        let names: Vec<String> = columns.into_iter().map(|c| c.name).collect();
+        anyhow::bail!(
+            "Table '{table}' in '{}' has no column '{column}' (columns: {})",
+            path.display(),
+            list(&names)
+        );
+    };
+    let root = match root {
+        Some(Value::Int(page)) if page > 0 => page as u32,
+        _ => anyhow::bail!(
+            "Table '{table}' in '{}' has no b-tree (a virtual table?)",
+            path.display()
+        ),
+    };
+
+    let rows = db
+        .rows(root)
+        .with_context(|| format!("Cannot read table '{table}' in '{}'", path.display()))?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(rowid, row)| {
+            if columns[index].rowid_alias {
+                return Some(rowid.to_string());
+            }
+            row.into_iter().nth(index).and_then(Value::into_text)
+        })
+        .collect())
+}
+
+fn list(names: &[String]) -> String {
+    if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+/// In WAL mode recent commits may only be in the `-wal` file, which is not read.
+fn check_wal(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+    let pending = std::fs::metadata(&wal).is_ok_and(|m| m.len() > 0);
+    if bytes[18] == 2 && pending {
+        anyhow::bail!(
+            "'{}' has uncommitted WAL data; run `PRAGMA wal_checkpoint(TRUNCATE)` on it first",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+struct Database<'a> {
+    bytes: &'a [u8],
+    page_size: usize,
+    /// Page size less the bytes reserved at the end of each page.
+    usable: usize,
+}
+
+impl<'a> Database<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Self> {
+        if bytes.len() < HEADER_SIZE || &bytes[..MAGIC.len()] != MAGIC {
+            anyhow::bail!("no SQLite header");
+        }
+        let page_size = match u16::from_be_bytes([bytes[16], bytes[17]]) {
+            1 => 65_536,
+            n if n >= 512 && n.is_power_of_two() => usize::from(n),
+            n => anyhow::bail!("invalid page size {n}"),
+        };
+        let encoding = u32::from_be_bytes(bytes[56..60].try_into().unwrap());
+        if encoding > 1 {
+            anyhow::bail!("only UTF-8 databases are supported (text encoding {encoding})");
+        }
+        Ok(Self {
+            bytes,
+            page_size,
+            usable: page_size - usize::from(bytes[20]),
+        })
+    }
+
+    fn page(&self, number: u32) -> Result<&'a [u8]> {
+        let start = (number as usize)
+            .checked_sub(1)
+            .context("page 0 does not exist")?
+            * self.page_size;
+        self.bytes
+            .get(start..start + self.page_size)
+            .with_context(|| format!("page {number} is past the end of the file"))
+    }
+
+    /// Every (rowid, record) of the table b-tree rooted at `root`, in rowid order.
+    fn rows(&self, root: u32) -> Result<Vec<(i64, Vec<Value>)>> {
+        let mut rows = Vec::new();
+        self.walk(root, 0, &mut rows)?;
+        Ok(rows)
+    }
+
+    fn walk(&self, number: u32, depth: usize, rows: &mut Vec<(i64, Vec<Value>)>) -> Result<()> {
+        if depth > MAX_DEPTH {
+            anyhow::bail!("b-tree deeper than {MAX_DEPTH} pages");
+        }
+        let page = self.page(number)?;
+        let header = if number == 1 { HEADER_SIZE } else { 0 };
+        let kind = page[header];
+        let cells = usize::from(u16::from_be_bytes([page[header + 3], page[header + 4]]));
+        let pointers = header + if kind == PAGE_INTERIOR_TABLE { 12 } else { 8 };
+        let cell = |i: usize| -> Result<usize> {
+            let at = pointers + 2 * i;
+            let bytes = page.get(at..at + 2).context("cell pointer past the page")?;
+            Ok(usize::from(u16::from_be_bytes([bytes[0], bytes[1]])))
+        };
+
+        match kind {
+            PAGE_INTERIOR_TABLE => {
+                for i in 0..cells {
+                    let at = cell(i)?;
+                    let child = page.get(at..at + 4).context("cell past the page")?;
+                    self.walk(
+                        u32::from_be_bytes(child.try_into().unwrap()),
+                        depth + 1,
+                        rows,
+                    )?;
+                }
+                let right = &page[header + 8..header + 12];
+                self.walk(
+                    u32::from_be_bytes(right.try_into().unwrap()),
+                    depth + 1,
+                    rows,
+                )
+            }
+            PAGE_LEAF_TABLE => {
+                for i in 0..cells {
+                    let mut pos = cell(i)?;
+                    let size = varint(page, &mut pos)? as usize;
+                    let rowid = varint(page, &mut pos)? as i64;
+                    let payload = self.payload(page, pos, size)?;
+                    rows.push((rowid, record(&payload)?));
+                }
+                Ok(())
+            }
+            other => anyhow::bail!("page {number} is not a table b-tree page (type {other:#04x})"),
+        }
+    }
+
+    /// A cell's `size`-byte payload starting at `pos`, following its overflow chain.
+    fn payload(&self, page: &[u8], pos: usize, size: usize) -> Result<Vec<u8>> {
+        let u = self.usable;
+        let max_local = u - 35;
+        let local = if size <= max_local {
+            size
+        } else {
+            let min_local = (u - 12) * 32 / 255 - 23;
+            let k = min_local + (size - min_local) % (u - 4);
+            if k <= max_local {
+                k
+            } else {
+                min_local
+            }
+        };
+        let mut out = page
+            .get(pos..pos + local)
+            .context("cell payload past the page")?
+            .to_vec();
+        if local == size {
+            return Ok(out);
+        }
+
+        let next = page
+            .get(pos + local..pos + local + 4)
+            .context("cell past the page")?;
+        let mut next = u32::from_be_bytes(next.try_into().unwrap());
+        while out.len() < size {
+            let overflow = self.page(next)?;
+            let take = (size - out.len()).min(u - 4);
+            out.extend_from_slice(&overflow[4..4 + take]);
+            next = u32::from_be_bytes(overflow[..4].try_into().unwrap());
+        }
+        Ok(out)
+    }
+}
+
+/// A big-endian SQLite varint: 7 bits per byte, except that a 9th byte contributes 8.
+fn varint(bytes: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut n = 0u64;
+    for i in 0..9 {
+        let b = *bytes.get(*pos).context("varint past the end of the data")?;
+        *pos += 1;
+        if i == 8 {
+            return Ok(n << 8 | u64::from(b));
+        }
+        n = n << 7 | u64::from(b & 0x7f);
+        if b < 0x80 {
+            break;
+        }
+    }
+    Ok(n)
+}
+
+fn record(payload: &[u8]) -> Result<Vec<Value>> {
+    let mut pos = 0;
+    let header_size = varint(payload, &mut pos)? as usize;
+    let mut types = Vec::new();
+    while pos < header_size {
+        types.push(varint(payload, &mut pos)?);
+    }
+
+    let mut body = header_size;
+    let mut take = |n: usize| -> Result<&[u8]> {
+        let bytes = payload
+            .get(body..body + n)
+            .context("record shorter than its header")?;
+        body += n;
+        Ok(bytes)
+    };
+    let int = |bytes: &[u8]| {
+        let fill = if bytes[0] & 0x80 != 0 { 0xff } else { 0 };
+        let mut be = [fill; 8];
+        be[8 - bytes.len()..].copy_from_slice(bytes);
+        Value::Int(i64::from_be_bytes(be))
+    };
+    types
+        .into_iter()
+        .map(|serial| {
+            Ok(match serial {
+                0 => Value::Null,
+                1..=4 => int(take(serial as usize)?),
+                5 => int(take(6)?),
+                6 => int(take(8)?),
+                7 => Value::Real(f64::from_be_bytes(take(8)?.try_into().unwrap())),
+                8 => Value::Int(0),
+                9 => Value::Int(1),
+                10 | 11 => anyhow::bail!("reserved serial type {serial}"),
+                n if n % 2 == 0 => Value::Blob(take((n as usize - 12) / 2)?.to_vec()),
+                n => {
+                    Value::Text(String::from_utf8_lossy(take((n as usize - 13) / 2)?).into_owned())
+                }
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq)]
+struct Column {
+    name: String,
+    /// `INTEGER PRIMARY KEY`: stored as the rowid, with NULL in the record.
+    rowid_alias: bool,
+}
+
+/// The columns of a `CREATE TABLE name (...)` statement, skipping table constraints.
+fn table_columns(sql: &str) -> Vec<Column> {
+    let (Some(open), Some(close)) = (sql.find('('), sql.rfind(')')) else {
+        return Vec::new();
+    };
+    split_top_level(&sql[open + 1..close])
+        .into_iter()
+        .filter_map(|def| {
+            let (name, rest) = first_token(def.trim());
+            let keyword = name.to_ascii_uppercase();
+            if ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"].contains(&keyword.as_str()) {
+                return None;
+            }
+            let rest = rest.to_ascii_uppercase();
+            let words: Vec<&str> = rest.split_whitespace().collect();
+            Some(Column {
+                name: unquote(name),
+                rowid_alias: words.starts_with(&["INTEGER", "PRIMARY", "KEY"])
+                    && !words.contains(&"DESC"),
+            })
+        })
+        .collect()
+}
+
+/// Splits on commas outside parentheses and quotes.
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut quote, mut start) = (0, None, 0);
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '[') => quote = Some(']'),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// The leading identifier of `s`, quoted or not, and the rest.
+fn first_token(s: &str) -> (&str, &str) {
+    let close = match s.chars().next() {
+        Some('"') => Some('"'),
+        Some('`') => Some('`'),
+        Some('[') => Some(']'),
+        _ => None,
+    };
+    let end = match close {
+        Some(close) => s[1..].find(close).map_or(s.len(), |i| i + 2),
+        None => s.find(|c: char| c.is_whitespace()).unwrap_or(s.len()),
+    };
+    (&s[..end], &s[end..])
+}
+
+fn unquote(name: &str) -> String {
+    let quoted = name.len() >= 2
+        && matches!(
+            (name.chars().next(), name.chars().last()),
+            (Some('"'), Some('"')) | (Some('`'), Some('`')) | (Some('['), Some(']'))
+        );
+    if quoted {
+        name[1..name.len() - 1].to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Writes a one-table database small enough for a single leaf page, for tests.
+#[cfg(test)]
+pub fn write_test_db(path: &Path, sql: &str, table: &str, rows: &[Vec<Value>]) {
+    const PAGE: usize = 512;
+
+    fn put_varint(out: &mut Vec<u8>, n: usize) {
+        assert!(n < 1 << 14);
+        if n >= 0x80 {
+            out.push(0x80 | (n >> 7) as u8);
+        }
+        out.push(n as u8 & 0x7f);
+    }
+
+    fn encode(values: &[Value]) -> Vec<u8> {
+        let (mut types, mut body) = (Vec::new(), Vec::new());
+        for value in values {
+            let serial = match value {
+                Value::Null => 0,
+                Value::Int(n) => {
+                    body.extend_from_slice(&n.to_be_bytes());
+                    6
+                }
+                Value::Real(x) => {
+                    body.extend_from_slice(&x.to_be_bytes());
+                    7
+                }
+                Value::Text(s) => {
+                    body.extend_from_slice(s.as_bytes());
+                    s.len() * 2 + 13
+                }
+                Value::Blob(b) => {
+                    body.extend_from_slice(b);
+                    b.len() * 2 + 12
+                }
+            };
+            put_varint(&mut types, serial);
+        }
+        assert!(types.len() < 0x7f);
+        let mut out = vec![types.len() as u8 + 1];
+        out.extend(types);
+        out.extend(body);
+        out
+    }
+
+    fn leaf(page: &mut [u8], header: usize, rows: impl Iterator<Item = Vec<u8>>) {
+        page[header] = PAGE_LEAF_TABLE;
+        let mut end = PAGE;
+        let mut count = 0;
+        for (i, payload) in rows.enumerate() {
+            let mut cell = Vec::new();
+            put_varint(&mut cell, payload.len());
+            put_varint(&mut cell, i + 1);
+            cell.extend(payload);
+            end -= cell.len();
+            page[end..end + cell.len()].copy_from_slice(&cell);
+            let at = header + 8 + 2 * i;
+            page[at..at + 2].copy_from_slice(&(end as u16).to_be_bytes());
+            count += 1;
+        }
+        assert!(header + 8 + 2 * count <= end, "rows do not fit a page");
+        page[header + 3..header + 5].copy_from_slice(&(count as u16).to_be_bytes());
+        page[header + 5..header + 7].copy_from_slice(&(end as u16).to_be_bytes());
+    }
+
+    let mut bytes = vec![0u8; 2 * PAGE];
+    bytes[..MAGIC.len()].copy_from_slice(MAGIC);
+    bytes[16..18].copy_from_slice(&(PAGE as u16).to_be_bytes());
+    bytes[18..24].copy_from_slice(&[1, 1, 0, 64, 32, 32]);
+    bytes[28..32].copy_from_slice(&2u32.to_be_bytes());
+    bytes[44..48].copy_from_slice(&4u32.to_be_bytes());
+    bytes[56..60].copy_from_slice(&1u32.to_be_bytes());
+    bytes[96..100].copy_from_slice(&3_040_001u32.to_be_bytes());
+
+    let schema = [
+        Value::Text("table".to_string()),
+        Value::Text(table.to_string()),
+        Value::Text(table.to_string()),
+        Value::Int(2),
+        Value::Text(sql.to_string()),
+    ];
+    leaf(
+        &mut bytes[..PAGE],
+        HEADER_SIZE,
+        std::iter::once(encode(&schema)),
+    );
+    leaf(&mut bytes[PAGE..], 0, rows.iter().map(|row| encode(row)));
+    std::fs::write(path, bytes).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_column_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("meta.db");
+        let text = |s: &str| Value::Text(s.to_string());
+        write_test_db(
+            &path,
+            "CREATE TABLE \"Roster\" (id INTEGER PRIMARY KEY, [sensor id] TEXT, serial INT, \
+             notes TEXT DEFAULT 'a,b', CHECK (serial > -100))",
+            "Roster",
+            &[
+                vec![Value::Null, text("S1"), Value::Int(-7), text("x")],
+                vec![Value::Null, Value::Null, Value::Int(300), Value::Null],
+                vec![Value::Null, text("S3"), Value::Real(1.5), Value::Null],
+            ],
+        );
+
+        assert_eq!(
+            read_column(&path, "roster", "SENSOR ID").unwrap(),
+            ["S1", "S3"]
+        );
+        assert_eq!(
+            read_column(&path, "Roster", "serial").unwrap(),
+            ["-7", "300", "1.5"]
+        );
+        assert_eq!(read_column(&path, "Roster", "id").unwrap(), ["1", "2", "3"]);
+
+        let err = read_column(&path, "sensors", "id").unwrap_err().to_string();
+        assert!(
+            err.contains("Table 'sensors' not found") && err.ends_with("(tables: Roster)"),
+            "{err}"
+        );
+        let err = read_column(&path, "Roster", "active")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.ends_with("has no column 'active' (columns: id, sensor id, serial, notes)"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_varint_and_record_serial_types() {
+        let mut pos = 0;
+        assert_eq!(varint(&[0x81, 0x00], &mut pos).unwrap(), 128);
+        assert_eq!(pos, 2);
+        let mut pos = 0;
+        assert_eq!(varint(&[0xff; 9], &mut pos).unwrap(), u64::MAX);
+
+        // 1-, 3- and 6-byte ints, the constants 0 and 1, and a 2-byte blob.
+        let payload = [
+            7, 1, 3, 5, 8, 9, 16, 0xfe, 0x01, 0x00, 0x00, 0, 0, 0, 0, 1, 0, 0xab, 0xcd,
+        ];
+        assert_eq!(
+            record(&payload).unwrap(),
+            [
+                Value::Int(-2),
+                Value::Int(65_536),
+                Value::Int(256),
+                Value::Int(0),
+                Value::Int(1),
+                Value::Blob(vec![0xab, 0xcd]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,490 @@
+use anyhow::{bail, Result};
+use std::fmt;
+use std::str::FromStr;
+
+pub const MICROS_PER_SECOND: i64 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TimeUnit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+}
+
+impl TimeUnit {
+    pub fn micros(self) -> i64 {
+        match self {
+            TimeUnit::Second => MICROS_PER_SECOND,
+            TimeUnit::Minute => 60 * MICROS_PER_SECOND,
+            TimeUnit::Hour => 3_600 * MICROS_PER_SECOND,
+            TimeUnit::Day => 86_400 * MICROS_PER_SECOND,
+        }
+    }
+
+    /// Rounds `ts` down to the start of its unit (floor, also for pre-1970 values).
+    pub fn truncate(self, ts: i64) -> i64 {
+        ts - ts.rem_euclid(self.micros())
+    }
+}
+
+/// How the Timestamp column is parsed: ISO 8601 by default, Unix epoch seconds or
+/// milliseconds, or a strftime-style pattern.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TimestampFormat {
+    #[default]
+    Iso8601,
+    Epoch,
+    EpochMillis,
+    Pattern(String),
+}
+
+const PATTERN_SPECIFIERS: &str = "%Y %y %m %b %d %e %H %M %S %f %z %T %F %%";
+
+impl FromStr for TimestampFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "iso8601" => Ok(Self::Iso8601),
+            "epoch" => Ok(Self::Epoch),
+            "epoch_ms" => Ok(Self::EpochMillis),
+            pattern => {
+                let mut chars = pattern.chars();
+                while let Some(c) = chars.next() {
+                    if c == '%' {
+                        match chars.next() {
+                            Some('Y' | 'y' | 'm' | 'b' | 'd' | 'e' | 'H' | 'M' | 'S' | 'f' | 'z'
+                            | 'T' | 'F' | '%') => {}
+                            Some(other) => bail!(
+                                "unsupported specifier '%{other}' in timestamp format (supported: {PATTERN_SPECIFIERS})"
+                            ),
+                            None => bail!("timestamp format ends with a lone '%'"),
+                        }
+                    }
+                }
+                Ok(Self::Pattern(pattern.to_string()))
+            }
+        }
+    }
+}
+
+impl fmt::Display for TimestampFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Iso8601 => f.write_str("iso8601"),
+            Self::Epoch => f.write_str("epoch"),
+            Self::EpochMillis => f.write_str("epoch_ms"),
+            Self::Pattern(p) => f.write_str(p),
+        }
+    }
+}
+
+impl TimestampFormat {
+    pub fn parse(&self, s: &str) -> Result<i64> {
+        match self {
+            Self::Iso8601 => parse_iso8601(s),
+            Self::Epoch => parse_epoch(s, MICROS_PER_SECOND),
+            Self::EpochMillis => parse_epoch(s, 1_000),
+            Self::Pattern(pattern) => parse_pattern(s.trim(), pattern).map(|(ts, _)| ts),
+        }
+    }
+
+    /// Like [`parse`](Self::parse), also telling whether `s` names an instant by itself:
+    /// epoch times and timestamps with a `Z` or `±HH:MM` offset do, local times do not.
+    pub fn parse_zoned(&self, s: &str) -> Result<(i64, bool)> {
+        match self {
+            Self::Iso8601 => iso8601_zoned(s),
+            Self::Epoch => Ok((parse_epoch(s, MICROS_PER_SECOND)?, true)),
+            Self::EpochMillis => Ok((parse_epoch(s, 1_000)?, true)),
+            Self::Pattern(pattern) => parse_pattern(s.trim(), pattern),
+        }
+    }
+}
+
+fn parse_epoch(s: &str, micros_per_unit: i64) -> Result<i64> {
+    let s = s.trim();
+    if let Ok(units) = s.parse::<i64>() {
+        if let Some(micros) = units.checked_mul(micros_per_unit) {
+            return Ok(micros);
+        }
+    }
+    match s.parse::<f64>() {
+        Ok(units) if units.is_finite() => Ok((units * micros_per_unit as f64).round() as i64),
+        _ => bail!("invalid epoch timestamp '{s}'"),
+    }
+}
+
+/// Matches `s` against a strftime-style `pattern`; literal characters must match exactly.
+fn parse_pattern(s: &str, pattern: &str) -> Result<(i64, bool)> {
+    let invalid = || anyhow::anyhow!("timestamp '{s}' does not match format '{pattern}'");
+    let (mut year, mut month, mut day) = (1970, 1, 1);
+    let (mut hour, mut minute, mut second, mut micros, mut offset_secs) = (0, 0, 0, 0, 0);
+    let mut zoned = false;
+
+    let mut input = s.as_bytes();
+    // `%T` and `%F` push their expansion on top of the rest of the pattern.
+    let mut pats: Vec<&[u8]> = vec![pattern.as_bytes()];
+
+    while let Some(pat) = pats.last_mut() {
+        let Some((&p, rest)) = pat.split_first() else {
+            pats.pop();
+            continue;
+        };
+        *pat = rest;
+        if p != b'%' {
+            match input.split_first() {
+                Some((&c, rest)) if c == p => input = rest,
+                _ => return Err(invalid()),
+            }
+            continue;
+        }
+        let (&spec, rest) = pat.split_first().ok_or_else(invalid)?;
+        *pat = rest;
+        match spec {
+            b'T' => pats.push(b"%H:%M:%S"),
+            b'F' => pats.push(b"%Y-%m-%d"),
+            b'Y' => year = take_number(&mut input, 4, 4).ok_or_else(invalid)?,
+            b'y' => {
+                let yy = take_number(&mut input, 2, 2).ok_or_else(invalid)?;
+                year = if yy < 69 { 2000 + yy } else { 1900 + yy };
+            }
+            b'm' => month = take_number(&mut input, 1, 2).ok_or_else(invalid)?,
+            b'd' => day = take_number(&mut input, 1, 2).ok_or_else(invalid)?,
+            b'e' => {
+                if input.first() == Some(&b' ') {
+                    input = &input[1..];
+                }
+                day = take_number(&mut input, 1, 2).ok_or_else(invalid)?;
+            }
+            b'H' => hour = take_number(&mut input, 1, 2).ok_or_else(invalid)?,
+            b'M' => minute = take_number(&mut input, 1, 2).ok_or_else(invalid)?,
+            b'S' => second = take_number(&mut input, 1, 2).ok_or_else(invalid)?,
+            b'f' => {
+                let len = input.iter().take_while(|c| c.is_ascii_digit()).count();
+                if len == 0 {
+                    return Err(invalid());
+                }
+                micros = 0;
+                for (i, c) in input[..len].iter().enumerate().take(6) {
+                    micros += i64::from(c - b'0') * 10_i64.pow(5 - i as u32);
+                }
+                input = &input[len..];
+            }
+            b'b' => {
+                const MONTHS: [&[u8]; 12] = [
+                    b"jan", b"feb", b"mar", b"apr", b"may", b"jun", b"jul", b"aug", b"sep", b"oct",
+                    b"nov", b"dec",
+                ];
+                let abbrev = input.get(..3).ok_or_else(invalid)?.to_ascii_lowercase();
+                month = MONTHS
+                    .iter()
+                    .position(|m| *m == abbrev.as_slice())
+                    .ok_or_else(invalid)? as i64
+                    + 1;
+                input = &input[3..];
+            }
+            b'z' => match input.split_first() {
+                Some((b'Z' | b'z', rest)) => {
+                    input = rest;
+                    zoned = true;
+                }
+                Some((&sign @ (b'+' | b'-'), rest)) => {
+                    input = rest;
+                    let h = take_number(&mut input, 2, 2).ok_or_else(invalid)?;
+                    if input.first() == Some(&b':') {
+                        input = &input[1..];
+                    }
+                    let m = take_number(&mut input, 2, 2).ok_or_else(invalid)?;
+                    offset_secs = (h * 3_600 + m * 60) * if sign == b'-' { -1 } else { 1 };
+                    zoned = true;
+                }
+                _ => return Err(invalid()),
+            },
+            b'%' => match input.split_first() {
+                Some((b'%', rest)) => input = rest,
+                _ => return Err(invalid()),
+            },
+            _ => return Err(invalid()),
+        }
+    }
+
+    if !input.is_empty() {
+        return Err(invalid());
+    }
+
+    from_parts(year, month, day, hour, minute, second, micros)
+        .map(|ts| (ts - offset_secs * MICROS_PER_SECOND, zoned))
+        .ok_or_else(invalid)
+}
+
+/// Consumes between `min` and `max` leading ASCII digits.
+fn take_number(input: &mut &[u8], min: usize, max: usize) -> Option<i64> {
+    let len = input
+        .iter()
+        .take(max)
+        .take_while(|c| c.is_ascii_digit())
+        .count();
+    if len < min {
+        return None;
+    }
+    let value = digits(&input[..len])?;
+    *input = &input[len..];
+    Some(value)
+}
+
+/// Parses a duration such as `500ms`, `10s`, `5m`, `2h` or `1d` into microseconds.
+pub fn parse_duration(s: &str) -> Result<i64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let micros_per_unit = match unit.trim() {
+        "us" => 1,
+        "ms" => 1_000,
+        "s" | "" => MICROS_PER_SECOND,
+        "m" | "min" => TimeUnit::Minute.micros(),
+        "h" => TimeUnit::Hour.micros(),
+        "d" => TimeUnit::Day.micros(),
+        other => bail!("unknown duration unit '{other}' in '{s}' (use us, ms, s, m, h or d)"),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid duration '{s}'"))?;
+    let micros = (number * micros_per_unit as f64).round() as i64;
+    if micros <= 0 {
+        bail!("duration '{s}' must be positive");
+    }
+    Ok(micros)
+}
+
+/// Parses an ISO 8601 timestamp into microseconds since the Unix epoch.
+///
+/// Accepts `YYYY-MM-DD`, `YYYY-MM-DDTHH:MM[:SS[.fraction]]` (a space may replace the
+/// `T`) and an optional `Z` or `±HH:MM` offset. Timestamps without an offset are UTC.
+pub fn parse_iso8601(s: &str) -> Result<i64> {
+    iso8601_zoned(s).map(|(ts, _)| ts)
+}
+
+/// [`parse_iso8601`], also telling whether the timestamp carried an offset.
+fn iso8601_zoned(s: &str) -> Result<(i64, bool)> {
+    let s = s.trim();
+    let b = s.as_bytes();
+    let invalid = || anyhow::anyhow!("invalid ISO 8601 timestamp '{s}'");
+
+    if b.len() < 10 || b[4] != b'-' || b[7] != b'-' {
+        return Err(invalid());
+    }
+    let year = digits(&b[0..4]).ok_or_else(invalid)?;
+    let month = digits(&b[5..7]).ok_or_else(invalid)?;
+    let day = digits(&b[8..10]).ok_or_else(invalid)?;
+    let (mut hour, mut minute, mut second, mut micros) = (0, 0, 0, 0);
+
+    let mut rest = &b[10..];
+    if !rest.is_empty() && (rest[0] == b'T' || rest[0] == b't' || rest[0] == b' ') {
+        rest = &rest[1..];
+        if rest.len() < 5 || rest[2] != b':' {
+            return Err(invalid());
+        }
+        hour = digits(&rest[0..2]).ok_or_else(invalid)?;
+        minute = digits(&rest[3..5]).ok_or_else(invalid)?;
+        rest = &rest[5..];
+        if rest.first() == Some(&b':') {
+            if rest.len() < 3 {
+                return Err(invalid());
+            }
+            second = digits(&rest[1..3]).ok_or_else(invalid)?;
+            rest = &rest[3..];
+        }
+        if rest.first() == Some(&b'.') || rest.first() == Some(&b',') {
+            let frac_len = rest[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+            if frac_len == 0 {
+                return Err(invalid());
+            }
+            // Anything below a microsecond is dropped.
+            for (i, c) in rest[1..=frac_len].iter().enumerate().take(6) {
+                micros += i64::from(c - b'0') * 10_i64.pow(5 - i as u32);
+            }
+            rest = &rest[1 + frac_len..];
+        }
+    }
+
+    let zoned = !rest.is_empty();
+    let offset_secs = match rest {
+        [] => 0,
+        [b'Z'] | [b'z'] => 0,
+        [sign @ (b'+' | b'-'), h1, h2, b':', m1, m2] | [sign @ (b'+' | b'-'), h1, h2, m1, m2] => {
+            let h = digits(&[*h1, *h2]).ok_or_else(invalid)?;
+            let m = digits(&[*m1, *m2]).ok_or_else(invalid)?;
+            let secs = h * 3_600 + m * 60;
+            if *sign == b'-' {
+                -secs
+            } else {
+                secs
+            }
+        }
+        _ => return Err(invalid()),
+    };
+
+    from_parts(year, month, day, hour, minute, second, micros)
+        .map(|ts| (ts - offset_secs * MICROS_PER_SECOND, zoned))
+        .ok_or_else(invalid)
+}
+
+/// Formats microseconds since the epoch as `YYYY-MM-DDTHH:MM:SS`, adding a
+/// fractional part only when the timestamp is not on a whole second.
+pub fn format_iso8601(ts: i64) -> String {
+    let days = ts.div_euclid(86_400 * MICROS_PER_SECOND);
+    let rem = ts.rem_euclid(86_400 * MICROS_PER_SECOND);
+    let (year, month, day) = civil_from_days(days);
+    let secs = rem / MICROS_PER_SECOND;
+    let micros = rem % MICROS_PER_SECOND;
+    let mut out = format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}",
+        secs / 3_600,
+        secs / 60 % 60,
+        secs % 60
+    );
+    if micros != 0 {
+        out.push_str(&format!(".{micros:06}"));
+    }
+    out
+}
+
+fn from_parts(
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+    micros: i64,
+) -> Option<i64> {
+    if !(1..=12).contains(&month)
+        || day < 1
+        || day > days_in_month(year, month)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    Some(((days * 86_400 + hour * 3_600 + minute * 60 + second) * MICROS_PER_SECOND) + micros)
+}
+
+fn digits(b: &[u8]) -> Option<i64> {
+    if b.is_empty() || !b.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(b.iter().fold(0, |acc, c| acc * 10 + i64::from(c - b'0')))
+}
+
+pub fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Howard Hinnant's civil calendar algorithms.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format_round_trip() {
+        let ts = parse_iso8601("2024-01-01T00:00:01").unwrap();
+        assert_eq!(ts, 1_704_067_201 * MICROS_PER_SECOND);
+        assert_eq!(format_iso8601(ts), "2024-01-01T00:00:01");
+
+        let ts = parse_iso8601("2024-02-29 12:30:45.123456+01:00").unwrap();
+        assert_eq!(format_iso8601(ts), "2024-02-29T11:30:45.123456");
+        assert!(parse_iso8601("2023-02-29T00:00:00").is_err());
+        assert!(parse_iso8601("not a date").is_err());
+    }
+
+    #[test]
+    fn test_truncate() {
+        let ts = parse_iso8601("2024-01-01T10:17:42.999").unwrap();
+        assert_eq!(
+            format_iso8601(TimeUnit::Second.truncate(ts)),
+            "2024-01-01T10:17:42"
+        );
+        assert_eq!(
+            format_iso8601(TimeUnit::Minute.truncate(ts)),
+            "2024-01-01T10:17:00"
+        );
+        assert_eq!(
+            format_iso8601(TimeUnit::Day.truncate(ts)),
+            "2024-01-01T00:00:00"
+        );
+        assert_eq!(TimeUnit::Second.truncate(-1), -MICROS_PER_SECOND);
+    }
+
+    #[test]
+    fn test_custom_pattern() {
+        let format: TimestampFormat = "%d/%m/%Y %H:%M".parse().unwrap();
+        let ts = format.parse("31/12/2023 23:59").unwrap();
+        assert_eq!(format_iso8601(ts), "2023-12-31T23:59:00");
+
+        let format: TimestampFormat = "%b %e %y %T.%f %z".parse().unwrap();
+        let ts = format.parse("Mar  5 24 08:15:30.25 -02:00").unwrap();
+        assert_eq!(format_iso8601(ts), "2024-03-05T10:15:30.250000");
+
+        assert!(format.parse("Mar  5 24 08:15:30").is_err());
+        assert!("%Q".parse::<TimestampFormat>().is_err());
+
+        let format: TimestampFormat = "%F %%T".parse().unwrap();
+        let ts = format.parse("2024-01-01 %T").unwrap();
+        assert_eq!(format_iso8601(ts), "2024-01-01T00:00:00");
+        assert!(format.parse("2024-01-01 00:00:00").is_err());
+    }
+
+    #[test]
+    fn test_epoch_formats() {
+        let ts = TimestampFormat::Epoch.parse("1704067200").unwrap();
+        assert_eq!(format_iso8601(ts), "2024-01-01T00:00:00");
+        let ts = TimestampFormat::Epoch.parse("1704067200.5").unwrap();
+        assert_eq!(format_iso8601(ts), "2024-01-01T00:00:00.500000");
+        let ts = TimestampFormat::EpochMillis.parse("1704067200123").unwrap();
+        assert_eq!(format_iso8601(ts), "2024-01-01T00:00:00.123000");
+        assert!(TimestampFormat::Epoch.parse("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("10ms").unwrap(), 10_000);
+        assert_eq!(parse_duration("1.5s").unwrap(), 1_500_000);
+        assert_eq!(parse_duration("5m").unwrap(), 300 * MICROS_PER_SECOND);
+        assert_eq!(parse_duration("2h").unwrap(), 7_200 * MICROS_PER_SECOND);
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("3 weeks").is_err());
+    }
+}
@@ -0,0 +1,521 @@
+//! `--from-tz` / `--to-tz`: time zones from the system's compiled zoneinfo files (TZif,
+//! RFC 8536), or POSIX TZ strings such as `EST5EDT,M3.2.0,M11.1.0`.
+//!
+//! A zone is its table of UTC offset changes plus, for instants past the last one, the
+//! POSIX rule from the file's footer; current zoneinfo builds often store only recent
+//! transitions and leave every later year to that rule.
+
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+
+use crate::timestamp::{self, MICROS_PER_SECOND};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Where the zoneinfo files live unless `TZDIR` says otherwise.
+const DEFAULT_TZDIR: &str = "/usr/share/zoneinfo";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zone {
+    pub name: String,
+    /// (UTC seconds, offset in seconds east of UTC from then on), ascending.
+    transitions: Vec<(i64, i32)>,
+    /// Offset before the first transition.
+    initial: i32,
+    /// Offsets after the last transition.
+    rule: Option<Rule>,
+}
+
+impl Zone {
+    pub fn utc() -> Self {
+        Self {
+            name: "UTC".to_string(),
+            transitions: Vec::new(),
+            initial: 0,
+            rule: None,
+        }
+    }
+
+    /// Loads an IANA zone such as `Europe/Berlin` from `$TZDIR` (default
+    /// `/usr/share/zoneinfo`), or else parses `name` as a POSIX TZ string.
+    pub fn load(name: &str) -> Result<Self> {
+        if name.eq_ignore_ascii_case("UTC") || name == "Z" {
+            return Ok(Self::utc());
+        }
+        let dir =
+            std::env::var_os("TZDIR").map_or_else(|| PathBuf::from(DEFAULT_TZDIR), PathBuf::from);
+        let safe = !name.is_empty()
+            && !name.starts_with('/')
+            && name.split('/').all(|part| !part.is_empty() && part != "..");
+        if safe {
+            let path = dir.join(name);
+            if path.is_file() {
+                let bytes = std::fs::read(&path)
+                    .with_context(|| format!("Cannot read time zone file '{}'", path.display()))?;
+                return Self::from_tzif(name, &bytes)
+                    .with_context(|| format!("'{}' is not a valid TZif file", path.display()));
+            }
+        }
+        match Rule::parse(name) {
+            Some(rule) => Ok(Self {
+                name: name.to_string(),
+                transitions: Vec::new(),
+                initial: rule.std,
+                rule: Some(rule),
+            }),
+            None => bail!(
+                "Unknown time zone '{name}': not found in {} (set TZDIR to use another directory) and not a POSIX TZ string such as EST5EDT,M3.2.0,M11.1.0",
+                dir.display()
+            ),
+        }
+    }
+
+    fn from_tzif(name: &str, bytes: &[u8]) -> Result<Self> {
+        if bytes.get(..4) != Some(b"TZif") {
+            bail!("no TZif header");
+        }
+        let version = *bytes.get(4).context("truncated TZif header")?;
+        let v1 = Header::read(bytes, 0)?;
+        // Version 2+ files repeat the data with 64-bit times, followed by a POSIX footer.
+        let (header, time_size) = if version >= b'2' {
+            (Header::read(bytes, v1.end(4))?, 8)
+        } else {
+            (v1, 4)
+        };
+
+        let times = header.at + 44;
+        let indices = times + header.times * time_size;
+        let types = indices + header.times;
+        let end = header.end(time_size);
+        if bytes.len() < end {
+            bail!("file is truncated");
+        }
+        let offsets: Vec<i32> = (0..header.types)
+            .map(|i| {
+                i32::from_be_bytes(bytes[types + 6 * i..types + 6 * i + 4].try_into().unwrap())
+            })
+            .collect();
+        let transitions = (0..header.times)
+            .map(|i| {
+                let at = times + i * time_size;
+                let time = if time_size == 8 {
+                    i64::from_be_bytes(bytes[at..at + 8].try_into().unwrap())
+                } else {
+                    i64::from(i32::from_be_bytes(bytes[at..at + 4].try_into().unwrap()))
+                };
+                let offset = *offsets
+                    .get(usize::from(bytes[indices + i]))
+                    .context("transition to an undefined local time type")?;
+                Ok((time, offset))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let rule = if version >= b'2' {
+            let footer = bytes.get(end..).unwrap_or_default();
+            let footer = std::str::from_utf8(footer)
+                .ok()
+                .and_then(|f| f.strip_prefix('\n'))
+                .and_then(|f| f.split('\n').next())
+                .context("missing footer")?;
+            match footer {
+                "" => None,
+                footer => Some(
+                    Rule::parse(footer)
+                        .with_context(|| format!("unsupported footer rule '{footer}'"))?,
+                ),
+            }
+        } else {
+            None
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            transitions,
+            initial: *offsets.first().context("no local time types")?,
+            rule,
+        })
+    }
+
+    /// Offset from UTC, in seconds, at the instant `utc` (seconds since the epoch).
+    fn offset_at(&self, utc: i64) -> i32 {
+        match self.transitions.last() {
+            Some(&(last, offset)) if utc >= last => self
+                .rule
+                .as_ref()
+                .map_or(offset, |rule| rule.offset_at(utc)),
+            None => self
+                .rule
+                .as_ref()
+                .map_or(self.initial, |rule| rule.offset_at(utc)),
+            Some(_) => match self.transitions.partition_point(|&(t, _)| t <= utc) {
+                0 => self.initial,
+                i => self.transitions[i - 1].1,
+            },
+        }
+    }
+
+    /// The instant a wall-clock time in this zone denotes, both in microseconds.
+    ///
+    /// A time the zone passes twice (when clocks go back) is taken at its earliest
+    /// instant. A time the zone skips (when clocks go forward) is moved forward by the
+    /// length of the gap, so 02:30 on a night that jumps from 02:00 to 03:00 is 03:30.
+    pub fn to_utc(&self, local: i64) -> i64 {
+        let secs = local.div_euclid(MICROS_PER_SECOND);
+        let fraction = local.rem_euclid(MICROS_PER_SECOND);
+        // Offset changes are months apart, so at most these two apply within a day.
+        let before = i64::from(self.offset_at(secs - SECONDS_PER_DAY));
+        let after = i64::from(self.offset_at(secs + SECONDS_PER_DAY));
+        let fits = |offset: i64| i64::from(self.offset_at(secs - offset)) == offset;
+        let offset = match (fits(before), fits(after)) {
+            (true, true) => before.max(after),
+            (false, true) => after,
+            _ => before,
+        };
+        (secs - offset) * MICROS_PER_SECOND + fraction
+    }
+
+    /// The wall-clock time in this zone at the instant `utc`, both in microseconds.
+    pub fn to_local(&self, utc: i64) -> i64 {
+        let offset = self.offset_at(utc.div_euclid(MICROS_PER_SECOND));
+        utc + i64::from(offset) * MICROS_PER_SECOND
+    }
+}
+
+/// Counts from a TZif header, starting at `at`.
+struct Header {
+    at: usize,
+    utc_indicators: usize,
+    std_indicators: usize,
+    leaps: usize,
+    times: usize,
+    types: usize,
+    chars: usize,
+}
+
+impl Header {
+    fn read(bytes: &[u8], at: usize) -> Result<Self> {
+        let count = |i: usize| -> Result<usize> {
+            let field = bytes
+                .get(at + 20 + 4 * i..at + 24 + 4 * i)
+                .context("file is truncated")?;
+            Ok(u32::from_be_bytes(field.try_into().unwrap()) as usize)
+        };
+        Ok(Self {
+            at,
+            utc_indicators: count(0)?,
+            std_indicators: count(1)?,
+            leaps: count(2)?,
+            times: count(3)?,
+            types: count(4)?,
+            chars: count(5)?,
+        })
+    }
+
+    /// Offset just past this header's data block, with times `time_size` bytes wide.
+    fn end(&self, time_size: usize) -> usize {
+        self.at
+            + 44
+            + self.times * (time_size + 1)
+            + self.types * 6
+            + self.chars
+            + self.leaps * (time_size + 4)
+            + self.std_indicators
+            + self.utc_indicators
+    }
+}
+
+/// A POSIX TZ rule: standard time plus, optionally, yearly daylight saving time.
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    /// Offsets in seconds east of UTC (POSIX writes them west, as `EST5`).
+    std: i32,
+    dst: Option<Dst>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Dst {
+    offset: i32,
+    /// Day and local time (seconds, possibly negative or past 24h) DST starts and ends.
+    start: (RuleDay, i64),
+    end: (RuleDay, i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RuleDay {
+    /// `Jn`: day 1-365, never counting February 29.
+    Julian(i64),
+    /// `n`: zero-based day of the year, counting February 29.
+    Ordinal(i64),
+    /// `Mm.w.d`: weekday `d` (0 = Sunday) of week `w` (5 = last) of month `m`.
+    Weekday { month: i64, week: i64, weekday: i64 },
+}
+
+impl RuleDay {
+    /// Days since the epoch of this rule day in `year`.
+    fn in_year(self, year: i64) -> i64 {
+        let jan1 = timestamp::days_from_civil(year, 1, 1);
+        match self {
+            RuleDay::Julian(n) => {
+                let leap = timestamp::days_in_month(year, 2) == 29;
+                jan1 + n - 1 + i64::from(leap && n >= 60)
+            }
+            RuleDay::Ordinal(n) => jan1 + n,
+            RuleDay::Weekday {
+                month,
+                week,
+                weekday,
+            } => {
+                let first = timestamp::days_from_civil(year, month, 1);
+                // 1970-01-01 was a Thursday.
+                let first_weekday = (first + 4).rem_euclid(7);
+                let mut day = first + (weekday - first_weekday).rem_euclid(7) + (week - 1) * 7;
+                let month_end = first + timestamp::days_in_month(year, month);
+                while day >= month_end {
+                    day -= 7;
+                }
+                day
+            }
+        }
+    }
+}
+
+impl Rule {
+    fn offset_at(&self, utc: i64) -> i32 {
+        let Some(dst) = &self.dst else {
+            return self.std;
+        };
+        let local_days = (utc + i64::from(self.std)).div_euclid(SECONDS_PER_DAY);
+        let (year, _, _) = timestamp::civil_from_days(local_days);
+        // The start is given in standard time, the end in daylight time.
+        let start = dst.start.0.in_year(year) * SECONDS_PER_DAY + dst.start.1 - i64::from(self.std);
+        let end = dst.end.0.in_year(year) * SECONDS_PER_DAY + dst.end.1 - i64::from(dst.offset);
+        let in_dst = if start < end {
+            start <= utc && utc < end
+        } else {
+            // Southern hemisphere: DST spans the new year.
+            !(end <= utc && utc < start)
+        };
+        if in_dst {
+            dst.offset
+        } else {
+            self.std
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        let mut p = Cursor(s.as_bytes());
+        p.name()?;
+        let std = -p.duration()?;
+        if p.0.is_empty() {
+            return Some(Self {
+                std: i32::try_from(std).ok()?,
+                dst: None,
+            });
+        }
+        p.name()?;
+        let offset = match p.0.first() {
+            Some(b'+' | b'-' | b'0'..=b'9') => -p.duration()?,
+            _ => std + 3_600,
+        };
+        p.eat(b',')?;
+        let start = p.transition()?;
+        p.eat(b',')?;
+        let end = p.transition()?;
+        if !p.0.is_empty() {
+            return None;
+        }
+        Some(Self {
+            std: i32::try_from(std).ok()?,
+            dst: Some(Dst {
+                offset: i32::try_from(offset).ok()?,
+                start,
+                end,
+            }),
+        })
+    }
+}
+
+struct Cursor<'a>(&'a [u8]);
+
+impl Cursor<'_> {
+    fn eat(&mut self, c: u8) -> Option<()> {
+        let (&first, rest) = self.0.split_first()?;
+        (first == c).then(|| self.0 = rest)
+    }
+
+    /// A zone abbreviation: three or more letters, or anything in angle brackets.
+    fn name(&mut self) -> Option<()> {
+        if self.eat(b'<').is_some() {
+            let close = self.0.iter().position(|&c| c == b'>')?;
+            self.0 = &self.0[close + 1..];
+            return Some(());
+        }
+        let len = self
+            .0
+            .iter()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .count();
+        (len >= 3).then(|| self.0 = &self.0[len..])
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        let len = self.0.iter().take_while(|c| c.is_ascii_digit()).count();
+        let (digits, rest) = self.0.split_at(len);
+        self.0 = rest;
+        std::str::from_utf8(digits).ok()?.parse().ok()
+    }
+
+    /// `[+-]hh[:mm[:ss]]` in seconds.
+    fn duration(&mut self) -> Option<i64> {
+        let sign = match self.0.first() {
+            Some(b'-') => {
+                self.0 = &self.0[1..];
+                -1
+            }
+            Some(b'+') => {
+                self.0 = &self.0[1..];
+                1
+            }
+            _ => 1,
+        };
+        let mut secs = self.number()? * 3_600;
+        for scale in [60, 1] {
+            if self.eat(b':').is_none() {
+                break;
+            }
+            secs += self.number()? * scale;
+        }
+        Some(sign * secs)
+    }
+
+    /// `date[/time]`, with the time defaulting to 02:00.
+    fn transition(&mut self) -> Option<(RuleDay, i64)> {
+        let day = if self.eat(b'J').is_some() {
+            RuleDay::Julian(self.number().filter(|n| (1..=365).contains(n))?)
+        } else if self.eat(b'M').is_some() {
+            let month = self.number().filter(|m| (1..=12).contains(m))?;
+            self.eat(b'.')?;
+            let week = self.number().filter(|w| (1..=5).contains(w))?;
+            self.eat(b'.')?;
+            let weekday = self.number().filter(|d| (0..=6).contains(d))?;
+            RuleDay::Weekday {
+                month,
+                week,
+                weekday,
+            }
+        } else {
+            RuleDay::Ordinal(self.number().filter(|n| (0..=365).contains(n))?)
+        };
+        let time = match self.eat(b'/') {
+            Some(()) => self.duration()?,
+            None => 7_200,
+        };
+        Some((day, time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp::{format_iso8601, parse_iso8601};
+
+    fn to_utc(zone: &Zone, local: &str) -> String {
+        format_iso8601(zone.to_utc(parse_iso8601(local).unwrap()))
+    }
+
+    fn to_local(zone: &Zone, utc: &str) -> String {
+        format_iso8601(zone.to_local(parse_iso8601(utc).unwrap()))
+    }
+
+    /// A version 2 TZif file for Central European Time: two transitions in 2000, then
+    /// the POSIX rule.
+    fn cet_tzif() -> Vec<u8> {
+        let header = |times: u32, types: u32, chars: u32| {
+            let mut h = b"TZif2".to_vec();
+            h.extend([0; 15]);
+            for count in [0, 0, 0, times, types, chars] {
+                h.extend(u32::to_be_bytes(count));
+            }
+            h
+        };
+        let mut bytes = header(0, 1, 4);
+        bytes.extend([0, 0, 0x0e, 0x10, 0, 0]);
+        bytes.extend(b"CET\0");
+        bytes.extend(header(2, 2, 9));
+        for utc in [954_032_400i64, 972_781_200] {
+            bytes.extend(utc.to_be_bytes());
+        }
+        bytes.extend([1, 0]);
+        bytes.extend([0, 0, 0x0e, 0x10, 0, 0]);
+        bytes.extend([0, 0, 0x1c, 0x20, 1, 4]);
+        bytes.extend(b"CET\0CEST\0");
+        bytes.extend(b"\nCET-1CEST,M3.5.0,M10.5.0/3\n");
+        bytes
+    }
+
+    #[test]
+    fn test_convert_across_utc_offsets() {
+        let new_york = Zone::load("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        assert_eq!(
+            to_utc(&new_york, "2024-01-15T12:00:00"),
+            "2024-01-15T17:00:00"
+        );
+        assert_eq!(
+            to_utc(&new_york, "2024-07-01T12:00:00.25"),
+            "2024-07-01T16:00:00.250000"
+        );
+        assert_eq!(
+            to_local(&new_york, "2024-07-01T16:00:00"),
+            "2024-07-01T12:00:00"
+        );
+        // 01:30 happens twice on 2024-11-03: the earlier, still EDT, instant is taken.
+        assert_eq!(
+            to_utc(&new_york, "2024-11-03T01:30:00"),
+            "2024-11-03T05:30:00"
+        );
+        // 02:30 never happens on 2024-03-10: it is read as 03:30 EDT.
+        assert_eq!(
+            to_utc(&new_york, "2024-03-10T02:30:00"),
+            "2024-03-10T07:30:00"
+        );
+
+        let sydney = Zone::load("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(
+            to_local(&sydney, "2024-01-15T12:00:00"),
+            "2024-01-15T23:00:00"
+        );
+        assert_eq!(
+            to_local(&sydney, "2024-07-01T12:00:00"),
+            "2024-07-01T22:00:00"
+        );
+
+        let berlin = Zone::from_tzif("CET", &cet_tzif()).unwrap();
+        assert_eq!(
+            to_utc(&berlin, "1999-07-01T12:00:00"),
+            "1999-07-01T11:00:00"
+        );
+        assert_eq!(
+            to_utc(&berlin, "2000-07-01T12:00:00"),
+            "2000-07-01T10:00:00"
+        );
+        assert_eq!(
+            to_utc(&berlin, "2030-07-01T12:00:00"),
+            "2030-07-01T10:00:00"
+        );
+        assert_eq!(
+            to_utc(&berlin, "2030-12-01T12:00:00"),
+            "2030-12-01T11:00:00"
+        );
+        let tzif = cet_tzif();
+        for len in [4, 20, tzif.len() / 2] {
+            assert!(Zone::from_tzif("CET", &tzif[..len]).is_err(), "{len} bytes");
+        }
+
+        assert_eq!(Zone::load("UTC").unwrap(), Zone::utc());
+        let err = Zone::load("Mars/Olympus_Mons").unwrap_err().to_string();
+        assert!(
+            err.starts_with("Unknown time zone 'Mars/Olympus_Mons'"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use anyhow::Result;
+
+/// Warnings raised while processing, reported together once the run is done.
+#[derive(Debug, Default, Clone)]
+pub struct Warnings {
+    messages: Vec<String>,
+}
+
+impl Warnings {
+    pub fn push(&mut self, message: impl Into<String>) {
+        self.messages.push(message.into());
+    }
+
+    pub fn extend(&mut self, other: Warnings) {
+        self.messages.extend(other.messages);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.messages.iter().map(String::as_str)
+    }
+
+    /// Prints every warning to stderr, or with `abort` fails with all of them instead.
+    pub fn emit(&self, abort: bool) -> Result<()> {
+        if abort && !self.is_empty() {
+            let list: String = self.iter().map(|m| format!("\n  - {m}")).collect();
+            anyhow::bail!(
+                "{} warning(s) with --abort-on-warning:{list}",
+                self.messages.len()
+            );
+        }
+        for message in self.iter() {
+            eprintln!("Warning: {message}");
+        }
+        Ok(())
+    }
+}